@@ -0,0 +1,461 @@
+use rustfft::{num_complex::Complex, FftPlanner};
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+/** The window functions that a buffer can be shaped with before it's analyzed
+
+A window tapers the ends of the buffer so that a sinusoid that doesn't fit a whole number of cycles into it doesn't smear
+across the whole spectrum. Windows with lower sidelobes keep quiet peaks from being buried by loud ones, but they have
+wider main lobes, so peaks have to be further apart to be told apart.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Window {
+    /// No window. This has the narrowest main lobe (2 bins wide), but its sidelobes are only 13dB down.
+    Rectangular,
+    /// The Hann window, whose main lobe is 4 bins wide and whose sidelobes are 31dB down and fall off quickly
+    Hann,
+    /// The 4-term Blackman-Harris window, whose main lobe is 8 bins wide and whose sidelobes are 92dB down
+    BlackmanHarris,
+    /// The Kaiser window with the given shape parameter (β), which trades main lobe width for sidelobe level. A β of 0
+    /// is the rectangular window, and a β of about 8.6 has sidelobes that are about 90dB down.
+    Kaiser(f32),
+}
+
+/** The ways that the frequency and amplitude of a spectral peak can be found
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PeakInterpolation {
+    /// Use the center of the peak's bin, which is only as accurate as the width of a bin
+    Bin,
+    /// Fit a parabola through the magnitudes of the peak's bin and its neighbors
+    Parabolic,
+    /// Fit a parabola through the log magnitudes of the peak's bin and its neighbors. The log of a window's main lobe is
+    /// closer to a parabola than the main lobe itself, so this is usually the most accurate (it's exact for a Gaussian
+    /// window).
+    Quadratic,
+}
+
+/** How a buffer is analyzed by a [`Spectrum`]
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnalysisOptions {
+    /// The window that the buffer is shaped with
+    pub window: Window,
+    /// How many times longer the FFT is than the buffer. The buffer is padded with zeros, which interpolates the
+    /// spectrum without making the main lobes any narrower.
+    pub padding: usize,
+    /// How the peaks are found between the bins
+    pub interpolation: PeakInterpolation,
+}
+
+/** A sinusoid found in a [`Spectrum`]
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Peak {
+    /// The frequency of the sinusoid, in cycles per sample
+    pub frequency: f32,
+    /// The amplitude of the sinusoid
+    pub amplitude: f32,
+    /// The phase of the sinusoid at the start of the buffer, in radians from -π to π. This is the phase of a sine, like
+    /// the phases that [`utils::additive`](super::utils::additive) takes, so the sinusoid is
+    /// `amplitude * sin(2π * frequency * n + phase)`.
+    pub phase: f32,
+}
+
+/** The spectrum of a windowed buffer of audio
+
+The magnitudes are scaled so that a sinusoid's peak has the sinusoid's amplitude, whatever the window, the padding or the
+length of the buffer is. All of the frequencies are in cycles per sample, so multiply them by the sample rate to get Hz,
+or by the length of a single cycle to get harmonic numbers.
+*/
+#[derive(Clone, Debug)]
+pub struct Spectrum {
+    // The bins from DC up to the Nyquist frequency
+    bins: Vec<Complex<f32>>,
+    // The length of the buffer and of the FFT
+    len: usize,
+    fftlen: usize,
+    // The sum of the window, which is the gain that it gives a sinusoid at the center of a bin
+    gain: f32,
+    window: Window,
+    interpolation: PeakInterpolation,
+}
+
+impl Default for AnalysisOptions {
+    fn default() -> Self {
+        AnalysisOptions {
+            window: Window::Hann,
+            padding: 1,
+            interpolation: PeakInterpolation::Quadratic,
+        }
+    }
+}
+
+impl Window {
+    /** Returns the window's coefficients for a buffer of the given length
+
+    The windows are symmetric, so the first and last coefficients are the same.
+    */
+    pub fn coefficients(&self, len: usize) -> Vec<f32> {
+        if len <= 1 {
+            return vec![1.0; len];
+        }
+        let last = (len - 1) as f64;
+        Vec::from_iter((0..len).map(|n| {
+            let x = n as f64 / last;
+            let angle = std::f64::consts::TAU * x;
+            let w = match self {
+                Window::Rectangular => 1.0,
+                Window::Hann => 0.5 - 0.5 * angle.cos(),
+                Window::BlackmanHarris => {
+                    0.35875 - 0.48829 * angle.cos() + 0.14128 * (2.0 * angle).cos()
+                        - 0.01168 * (3.0 * angle).cos()
+                }
+                Window::Kaiser(beta) => {
+                    let beta = *beta as f64;
+                    let r = 2.0 * x - 1.0;
+                    bessel_i0(beta * (1.0 - r * r).max(0.0).sqrt()) / bessel_i0(beta)
+                }
+            };
+            w as f32
+        }))
+    }
+
+    /* Returns half of the width of the window's main lobe, in bins of an FFT without padding
+     */
+    fn half_width(&self) -> f32 {
+        match self {
+            Window::Rectangular => 1.0,
+            Window::Hann => 2.0,
+            Window::BlackmanHarris => 4.0,
+            Window::Kaiser(beta) => (1.0 + (beta / PI).powi(2)).sqrt(),
+        }
+    }
+}
+
+impl Spectrum {
+    /** Analyzes a buffer of audio
+
+    # Arguments
+
+    * `buffer`:  The audio
+    * `options`: How to analyze it
+    */
+    pub fn new(buffer: &[f32], options: &AnalysisOptions) -> Self {
+        let len = buffer.len();
+        let fftlen = len * options.padding.max(1);
+        let window = options.window.coefficients(len);
+
+        let mut fftbuf = vec![Complex { re: 0.0, im: 0.0 }; fftlen];
+        for ((coef, v), w) in fftbuf.iter_mut().zip(buffer.iter()).zip(window.iter()) {
+            coef.re = v * w;
+        }
+        if fftlen > 0 {
+            let mut planner = FftPlanner::new();
+            planner.plan_fft_forward(fftlen).process(&mut fftbuf);
+        }
+        fftbuf.truncate(fftlen / 2 + 1);
+
+        Spectrum {
+            bins: fftbuf,
+            len,
+            fftlen,
+            gain: window.iter().sum(),
+            window: options.window,
+            interpolation: options.interpolation,
+        }
+    }
+
+    /** Returns the frequency at the center of a bin, in cycles per sample
+     */
+    pub fn bin_frequency(&self, bin: usize) -> f32 {
+        bin as f32 / self.fftlen as f32
+    }
+
+    /** Returns the magnitude of each bin, from DC up to the Nyquist frequency
+     */
+    pub fn magnitudes(&self) -> Vec<f32> {
+        Vec::from_iter((0..self.bins.len()).map(|k| self.magnitude(k)))
+    }
+
+    /** Returns all of the peaks in the spectrum that are louder than a threshold, from the lowest frequency to the highest
+
+    A peak is a bin that's louder than the bins on either side of it. Its frequency and amplitude are refined with the
+    spectrum's [`PeakInterpolation`]. Note that the sidelobes of a loud peak are peaks too, so the threshold should be
+    above the window's sidelobe level.
+
+    # Arguments
+
+    * `threshold`: The quietest amplitude that counts as a peak
+    */
+    pub fn peaks(&self, threshold: f32) -> Vec<Peak> {
+        let mags = self.magnitudes();
+        let top = mags.len().saturating_sub(1);
+        Vec::from_iter(
+            (1..top)
+                .filter(|k| {
+                    mags[*k] > threshold && mags[*k] > mags[k - 1] && mags[*k] >= mags[k + 1]
+                })
+                .map(|k| self.refine(k))
+                .filter(|peak| peak.amplitude > threshold),
+        )
+    }
+
+    /** Returns the harmonic series of a fundamental frequency
+
+    Each harmonic is the loudest peak within half a main lobe (or half of the fundamental, if that's narrower) of the
+    harmonic's frequency, refined with the spectrum's [`PeakInterpolation`]. This is how a single cycle can be rebuilt
+    from a recording: the amplitudes and phases can be passed straight to [`utils::additive`](super::utils::additive).
+
+    # Arguments
+
+    * `fundamental`: The frequency of the fundamental, in cycles per sample (see
+                     [`utils::detect_pitch`](super::utils::detect_pitch))
+    * `count`:       The number of harmonics to return, starting with the fundamental
+
+    # Returns
+    The harmonics, starting with the fundamental. The series stops early at the last harmonic that's below the Nyquist
+    frequency, and a fundamental that isn't positive has no harmonics.
+    */
+    pub fn harmonics(&self, fundamental: f32, count: usize) -> Vec<Peak> {
+        let mags = self.magnitudes();
+        let top = mags.len().saturating_sub(1);
+        let spacing = fundamental * self.fftlen as f32;
+        let reach =
+            (self.window.half_width() * self.fftlen as f32 / self.len as f32).min(spacing / 2.0);
+        if spacing.is_nan() || spacing <= 0.0 {
+            return Vec::new();
+        }
+
+        let mut harmonics = Vec::with_capacity(count);
+        for h in 1..=count {
+            let center = h as f32 * spacing;
+            if center + 1.0 >= top as f32 {
+                break;
+            }
+            // Always look at the bin that's nearest to the harmonic, even if the fundamental is less than two bins
+            let nearest = (center.round() as usize).max(1);
+            let low = ((center - reach).ceil().max(1.0) as usize).min(nearest);
+            let high = ((center + reach).floor() as usize).clamp(nearest, top - 1);
+            let k = (low..=high)
+                .max_by(|a, b| mags[*a].total_cmp(&mags[*b]))
+                .unwrap();
+            harmonics.push(self.refine(k));
+        }
+        harmonics
+    }
+
+    /* Returns the magnitude of a bin, scaled to the amplitude of a sinusoid
+     */
+    fn magnitude(&self, bin: usize) -> f32 {
+        if self.gain <= 0.0 {
+            return 0.0;
+        }
+        // Only DC and the Nyquist frequency don't have a mirror image in the negative frequencies
+        let scale = if bin == 0 || 2 * bin == self.fftlen {
+            1.0
+        } else {
+            2.0
+        };
+        scale * self.bins[bin].norm() / self.gain
+    }
+
+    /* Refines the peak at a bin that has a neighbor on either side
+     */
+    fn refine(&self, bin: usize) -> Peak {
+        let (a, b, c) = (
+            self.magnitude(bin - 1),
+            self.magnitude(bin),
+            self.magnitude(bin + 1),
+        );
+        let (offset, amplitude) = match self.interpolation {
+            PeakInterpolation::Bin => (0.0, b),
+            PeakInterpolation::Parabolic => {
+                let p = vertex(a, b, c);
+                (p, b - 0.25 * (a - c) * p)
+            }
+            PeakInterpolation::Quadratic if a > 0.0 && b > 0.0 && c > 0.0 => {
+                let (a, b, c) = (a.ln(), b.ln(), c.ln());
+                let p = vertex(a, b, c);
+                (p, (b - 0.25 * (a - c) * p).exp())
+            }
+            PeakInterpolation::Quadratic => (0.0, b),
+        };
+
+        // The window is centered on the middle of the buffer, which delays the phase of the bin by half of the buffer,
+        // at the distance between the bin and the peak
+        let delay = -TAU * offset / self.fftlen as f32 * (self.len - 1) as f32 / 2.0;
+        let cosine = self.bins[bin].arg() + delay;
+        Peak {
+            frequency: (bin as f32 + offset) / self.fftlen as f32,
+            amplitude,
+            phase: (cosine + FRAC_PI_2 + PI).rem_euclid(TAU) - PI,
+        }
+    }
+}
+
+/* Returns how far the top of a parabola through three evenly spaced points is from the middle one, which is at most half
+ * a bin
+ */
+fn vertex(y0: f32, y1: f32, y2: f32) -> f32 {
+    let curve = y0 - 2.0 * y1 + y2;
+    if curve < 0.0 {
+        (0.5 * (y0 - y2) / curve).clamp(-0.5, 0.5)
+    } else {
+        0.0
+    }
+}
+
+/* The zeroth order modified Bessel function of the first kind, which shapes the Kaiser window
+ */
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1.0;
+    while term > 1e-12 * sum {
+        term *= (x / (2.0 * k)).powi(2);
+        sum += term;
+        k += 1.0;
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::utils::additive;
+    use super::{AnalysisOptions, PeakInterpolation, Spectrum, Window};
+    use float_cmp::approx_eq;
+    use std::f32::consts::{PI, TAU};
+
+    /* The distance between two phases, which wraps around at ±π
+     */
+    fn phase_error(a: f32, b: f32) -> f32 {
+        ((a - b + PI).rem_euclid(TAU) - PI).abs()
+    }
+
+    #[test]
+    fn test_windows() {
+        let hann = Window::Hann.coefficients(5);
+        for (w, expected) in hann.iter().zip([0.0, 0.5, 1.0, 0.5, 0.0]) {
+            assert!(approx_eq!(f32, *w, expected, epsilon = 1e-6), "{:?}", hann);
+        }
+        assert_eq!(Window::Kaiser(0.0).coefficients(8), vec![1.0; 8]);
+        assert_eq!(Window::Rectangular.coefficients(8), vec![1.0; 8]);
+
+        for window in [Window::BlackmanHarris, Window::Kaiser(8.6)] {
+            let coefs = window.coefficients(65);
+            // Symmetric, tapered to nearly nothing at the ends, with a peak of 1 in the middle
+            for n in 0..65 {
+                assert!(approx_eq!(f32, coefs[n], coefs[64 - n], epsilon = 1e-6));
+            }
+            assert!(coefs[0] < 0.01, "{:?} starts at {}", window, coefs[0]);
+            assert!(approx_eq!(f32, coefs[32], 1.0, epsilon = 1e-6));
+        }
+    }
+
+    #[test]
+    fn test_peaks() {
+        //! Two sinusoids between bins are found with accurate frequencies, amplitudes and phases
+        let len = 1024;
+        let tones = [(0.1234, 1.0, 0.3), (0.2718, 0.25, -2.0)];
+        let signal = Vec::from_iter((0..len).map(|n| {
+            tones
+                .iter()
+                .map(|(f, a, p)| a * (TAU * f * n as f32 + p).sin())
+                .sum::<f32>()
+        }));
+
+        let mut options = AnalysisOptions {
+            window: Window::BlackmanHarris,
+            padding: 4,
+            interpolation: PeakInterpolation::Quadratic,
+        };
+        let peaks = Spectrum::new(&signal, &options).peaks(0.01);
+        assert_eq!(peaks.len(), 2, "{:?}", peaks);
+        for (peak, (f, a, p)) in peaks.iter().zip(tones) {
+            assert!((peak.frequency - f).abs() < 1e-5, "{:?}", peak);
+            assert!((peak.amplitude / a - 1.0).abs() < 0.01, "{:?}", peak);
+            assert!(phase_error(peak.phase, p) < 0.01, "{:?}", peak);
+        }
+
+        // Without interpolation, the peaks are only as good as the bins
+        options.interpolation = PeakInterpolation::Bin;
+        let peaks = Spectrum::new(&signal, &options).peaks(0.01);
+        assert!((peaks[0].frequency - 0.1234).abs() > 1e-5);
+        assert!((peaks[0].frequency - 0.1234).abs() < 0.5 / 4096.0);
+
+        // A parabola through the magnitudes is less accurate than through their logs, but still better than the bins
+        options.interpolation = PeakInterpolation::Parabolic;
+        let peaks = Spectrum::new(&signal, &options).peaks(0.01);
+        assert!((peaks[0].frequency - 0.1234).abs() < 2e-5, "{:?}", peaks[0]);
+        assert!((peaks[0].amplitude - 1.0).abs() < 0.01, "{:?}", peaks[0]);
+    }
+
+    #[test]
+    fn test_harmonics() {
+        //! The harmonics of a single cycle are exactly the ones that it was built from
+        let amplitudes = [1.0, 0.5, 0.25, 0.125];
+        let phases = [0.0, 1.0, -2.0, 0.5];
+        let cycle = additive(256, &amplitudes, &phases);
+        // Every harmonic of a single cycle falls right on a bin, so it doesn't need a window or interpolation
+        let options = AnalysisOptions {
+            window: Window::Rectangular,
+            padding: 1,
+            interpolation: PeakInterpolation::Bin,
+        };
+        let harmonics = Spectrum::new(&cycle, &options).harmonics(1.0 / 256.0, 4);
+        assert_eq!(harmonics.len(), 4);
+        for (k, harmonic) in harmonics.iter().enumerate() {
+            assert_eq!(harmonic.frequency, (k + 1) as f32 / 256.0);
+            assert!(approx_eq!(
+                f32,
+                harmonic.amplitude,
+                amplitudes[k],
+                epsilon = 1e-5
+            ));
+            assert!(
+                phase_error(harmonic.phase, phases[k]) < 1e-4,
+                "{:?}",
+                harmonic
+            );
+        }
+
+        // The harmonics of a period that isn't a whole number of samples are found too
+        let period = 469.3;
+        let signal = Vec::from_iter((0..8192).map(|n| {
+            let t = n as f32 / period;
+            (0..4)
+                .map(|k| amplitudes[k] * (TAU * (k + 1) as f32 * t + phases[k]).sin())
+                .sum::<f32>()
+        }));
+        let options = AnalysisOptions {
+            window: Window::BlackmanHarris,
+            padding: 4,
+            interpolation: PeakInterpolation::Quadratic,
+        };
+        let spectrum = Spectrum::new(&signal, &options);
+        let harmonics = spectrum.harmonics(1.0 / period, 8);
+        assert_eq!(harmonics.len(), 8);
+        for (k, harmonic) in harmonics[..4].iter().enumerate() {
+            assert!(
+                (harmonic.frequency * period - (k + 1) as f32).abs() < 1e-3,
+                "{:?}",
+                harmonic
+            );
+            assert!(
+                (harmonic.amplitude / amplitudes[k] - 1.0).abs() < 0.01,
+                "{:?}",
+                harmonic
+            );
+            assert!(
+                phase_error(harmonic.phase, phases[k]) < 0.02,
+                "{:?}",
+                harmonic
+            );
+        }
+        // The harmonics that aren't there are below the window's sidelobes
+        assert!(harmonics[4..].iter().all(|h| h.amplitude < 1e-4));
+
+        // The series stops at the Nyquist frequency
+        assert_eq!(spectrum.harmonics(0.1, 10).len(), 4);
+    }
+}
@@ -1,40 +1,33 @@
 use super::midi;
 use super::midi::Message;
 use std::sync::Arc;
+use wavetable::swap::TableReceiver;
 use wavetable::system::System;
 use wavetable::voice::Voice;
-use wavetable::wt::Wavetable;
 
 pub struct Instrument {
     //table: Wavetable,
     voices: Vec<Voice>,
     buffer: Vec<f32>,
+    tables: TableReceiver,
 }
 
 impl Instrument {
-    pub fn new(
-        system: &Arc<System>,
-        table: &Arc<Wavetable>,
-        nvoices: usize,
-        att: f32,
-        dec: f32,
-        sus: f32,
-        rel: f32,
-    ) -> Self {
-        let mut inst = Instrument {
+    pub fn new(system: &Arc<System>, voices: Vec<Voice>, tables: TableReceiver) -> Self {
+        Instrument {
             //table,
-            voices: Vec::new(),
+            voices,
             buffer: vec![0f32; system.bufsize()],
-        };
-
-        for _ in 0..nvoices {
-            inst.voices
-                .push(Voice::new(system, table, att, dec, sus, rel))
+            tables,
         }
-        inst
     }
 
     pub fn perform(&mut self, outbuf: &mut [f32]) {
+        if let Some((table, fade)) = self.tables.receive() {
+            for voice in self.voices.iter_mut() {
+                voice.set_table(table, fade);
+            }
+        }
         for out in outbuf.iter_mut() {
             *out = 0.0;
         }
@@ -1,5 +1,7 @@
+use std::io::BufRead;
 use std::sync::mpsc::channel;
 use std::sync::Arc;
+use std::thread;
 
 use clap::Parser;
 use portmidi::PortMidi;
@@ -9,8 +11,11 @@ mod midi;
 mod stream;
 use instrument::Instrument;
 use midi::{Message, MidiError};
+use wavetable::swap;
 use wavetable::system::System;
-use wavetable::wt::Wavetable;
+use wavetable::unison::{Unison, MAX_UNISON};
+use wavetable::voice::Voice;
+use wavetable::wt::{ImportOptions, Normalize, Wavetable};
 
 fn main() -> Result<(), i32> {
     let args = Args::parse();
@@ -25,22 +30,65 @@ fn main() -> Result<(), i32> {
         args.bufsize,
     ));
 
-    let table = Wavetable::from_sndfile(&args.wavetable, args.trim).map_err(|e| {
+    let options = ImportOptions {
+        remove_dc: args.clean,
+        rotate: args.clean,
+        normalize: args.normalize.unwrap_or(Normalize::Off),
+        ..ImportOptions::default()
+    };
+    let table = load_table(&args, &args.wavetable, &options).map_err(|e| {
         println!("{}", e);
         1
     })?;
 
     let table = Arc::new(table);
+    let (sender, receiver) = swap::table_swap(&system, &table);
 
-    let mut instrument = Instrument::new(
-        &system,
-        &table,
-        args.voices,
+    if args.unison == 0 || args.unison > MAX_UNISON {
+        println!("The unison count must be between 1 and {}", MAX_UNISON);
+        return Err(1);
+    }
+    let (att, dec, sus, rel) = (
         args.attack / 1000.0,
         args.decay / 1000.0,
         args.sustain,
         args.release / 1000.0,
     );
+    let voices = Vec::from_iter((0..args.voices).map(|_| {
+        if args.unison > 1 {
+            let mut unison = Unison::new(&system, &table, args.unison);
+            unison.set_detune(args.detune);
+            Voice::new_unison(&system, unison, att, dec, sus, rel)
+        } else {
+            Voice::new(&system, &table, att, dec, sus, rel)
+        }
+    }));
+    let mut instrument = Instrument::new(&system, voices, receiver);
+    // The voices and the receiver hold the table from here on
+    drop(table);
+
+    // Load new tables from stdin while the synth plays
+    let fade = (args.fade / 1000.0 * args.samplerate as f32) as usize;
+    let loader = sender.clone();
+    thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            match load_table(&args, line, &options) {
+                Ok(table) => {
+                    println!("Loaded {}", line);
+                    loader.send(Arc::new(table), fade);
+                }
+                Err(e) => println!("{}", e),
+            }
+        }
+    });
 
     // Create Midi Device
     let pm = PortMidi::new().unwrap();
@@ -77,6 +125,7 @@ fn main() -> Result<(), i32> {
     })?;
 
     loop {
+        sender.collect();
         if let Some(event) = mididev.read().unwrap() {
             let msg = midi::map_message(&event.message);
             match msg {
@@ -97,7 +146,7 @@ fn main() -> Result<(), i32> {
 #[clap(version = "wavesynth 0.1.0", long_about = None)]
 #[clap(about = "A MIDI-controlled wavetable synthesizer")]
 struct Args {
-    /// Path to an audio file to use for a wavetable
+    /// Path to an audio file to use for a wavetable (or an expression, with --expr)
     wavetable: String,
 
     /// Envelope attack, in ms
@@ -116,6 +165,22 @@ struct Args {
     #[clap(short, long)]
     trim: bool,
 
+    /// Treat the WAVETABLE argument as an expression of the phase, x, like "sin(x) + 0.3*sin(3*x)"
+    #[clap(short = 'x', long)]
+    expr: bool,
+
+    /// Keep the waveform's exact length instead of resampling it to a power of two
+    #[clap(short, long)]
+    exact: bool,
+
+    /// Remove the waveform's DC offset and rotate it to start at a rising zero crossing
+    #[clap(short, long)]
+    clean: bool,
+
+    /// Normalize the waveform to a peak or RMS level, like "peak=0.9" or "rms=0.3"
+    #[clap(short, long, parse(try_from_str = parse_normalize))]
+    normalize: Option<Normalize>,
+
     /// Optional MIDI device to use. If not given, then device will be queried
     #[clap(short, long)]
     midi_device: Option<String>,
@@ -128,7 +193,50 @@ struct Args {
     #[clap(short, long, default_value = "256")]
     bufsize: usize,
 
+    /// The number of detuned oscillators that each voice plays, up to 16
+    #[clap(short, long, default_value = "1")]
+    unison: usize,
+
+    /// The detune of the outermost unison oscillators, in cents
+    #[clap(short, long, default_value = "20")]
+    detune: f32,
+
     /// The maximum number of voices to use
     #[clap(short, long, default_value = "8")]
     voices: usize,
+
+    /// The crossfade time when a new wavetable is loaded from stdin, in ms
+    #[clap(short, long, default_value = "50")]
+    fade: f32,
+}
+
+fn load_table(
+    args: &Args,
+    source: &str,
+    options: &ImportOptions,
+) -> Result<Wavetable, std::io::Error> {
+    if args.expr {
+        Wavetable::from_expr(source, 2048)
+    } else if args.exact {
+        Wavetable::from_sndfile_exact_with(source, args.trim, options)
+    } else {
+        Wavetable::from_sndfile_with(source, args.trim, options)
+    }
+}
+
+fn parse_normalize(arg: &str) -> Result<Normalize, String> {
+    let (mode, level) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("Expected peak=<level> or rms=<level>. Got {}", arg))?;
+    let level = level
+        .parse::<f32>()
+        .map_err(|e| format!("Invalid level {}. {}", level, e))?;
+    match mode {
+        "peak" => Ok(Normalize::Peak(level)),
+        "rms" => Ok(Normalize::Rms(level)),
+        _ => Err(format!(
+            "Unknown normalization {}. Expected peak or rms",
+            mode
+        )),
+    }
 }
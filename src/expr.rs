@@ -0,0 +1,791 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::f64::consts::{E, PI, TAU};
+use std::io::ErrorKind;
+
+/** A parsed wavetable expression
+
+Expressions describe a table with ordinary math notation, like `sin(x) + 0.3*sin(3*x)*env(frame)`. An expression is
+parsed once and then evaluated at every sample (see [`Expr::waveform`]) or at every harmonic (see [`Expr::harmonics`]) of
+each frame of the table.
+
+# Syntax
+
+* Numbers, like `3`, `0.25` or `1e-3`
+* The arithmetic operators `+`, `-`, `*`, `/`, `%` (the remainder, with the sign of the divisor) and `^` (the power,
+  which groups to the right and binds tighter than a leading minus, so `-x^2` is `-(x^2)`)
+* The comparisons `<`, `<=`, `>`, `>=`, `==` and `!=`, which give 1 if they're true and 0 if they're not
+* Parentheses
+
+# Variables
+
+* `x`:     The phase, which runs from 0 up to (but not including) 2π over the cycle. This is only available to waveform
+           expressions.
+* `h`:     The harmonic number, starting at 1 for the fundamental. This is only available to harmonic expressions.
+* `frame`: The position of the frame in the table, from 0 for the first frame to 1 for the last. This is 0 for a table
+           with a single frame.
+* `pi`, `tau` and `e`: The constants
+
+# Functions
+
+* `sin`, `cos`, `tan`, `asin`, `acos`, `atan`, `sinh`, `cosh`, `tanh`
+* `exp`, `ln`, `log2`, `log10`, `sqrt`, `abs`, `sign`, `floor`, `ceil`, `round`, `fract`
+* `atan2(y, x)`, `pow(a, b)`, `min(a, b)`, `max(a, b)`, `clamp(v, lo, hi)`, `if(cond, a, b)` (`a` if `cond` isn't 0,
+  otherwise `b`)
+* `saw(p)`, `square(p)` and `tri(p)`: Naive (not band-limited) waveforms with a period of 2π, like `sin(p)`
+* `env(p)`: A smooth envelope that rises from 0 at `p = 0` to 1 at `p = 0.5` and falls back to 0 at `p = 1`, like a
+  Hann window. It's 0 outside of that range. It's meant to shape how a harmonic comes and goes across the frames, as in
+  `env(frame)`.
+* `noise()`: A random value in the range of [-1, 1]. The random sequence is seeded the same way every time a table is
+  generated, so an expression always generates the same table.
+
+Expressions can be nested up to 256 levels deep.
+*/
+pub struct Expr {
+    root: Node,
+}
+
+/* A node in the expression's syntax tree
+ */
+enum Node {
+    Num(f64),
+    Var(Var),
+    Neg(Box<Node>),
+    Binary(BinOp, Box<Node>, Box<Node>),
+    Call(Func, Vec<Node>),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Var {
+    X,
+    H,
+    Frame,
+}
+
+#[derive(Clone, Copy)]
+enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+#[derive(Clone, Copy)]
+enum Func {
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Exp,
+    Ln,
+    Log2,
+    Log10,
+    Sqrt,
+    Abs,
+    Sign,
+    Floor,
+    Ceil,
+    Round,
+    Fract,
+    Atan2,
+    Pow,
+    Min,
+    Max,
+    Clamp,
+    If,
+    Saw,
+    Square,
+    Tri,
+    Env,
+    Noise,
+}
+
+/* The functions, by name, along with the number of arguments that each takes
+ */
+const FUNCS: [(&str, Func, usize); 31] = [
+    ("sin", Func::Sin, 1),
+    ("cos", Func::Cos, 1),
+    ("tan", Func::Tan, 1),
+    ("asin", Func::Asin, 1),
+    ("acos", Func::Acos, 1),
+    ("atan", Func::Atan, 1),
+    ("sinh", Func::Sinh, 1),
+    ("cosh", Func::Cosh, 1),
+    ("tanh", Func::Tanh, 1),
+    ("exp", Func::Exp, 1),
+    ("ln", Func::Ln, 1),
+    ("log2", Func::Log2, 1),
+    ("log10", Func::Log10, 1),
+    ("sqrt", Func::Sqrt, 1),
+    ("abs", Func::Abs, 1),
+    ("sign", Func::Sign, 1),
+    ("floor", Func::Floor, 1),
+    ("ceil", Func::Ceil, 1),
+    ("round", Func::Round, 1),
+    ("fract", Func::Fract, 1),
+    ("atan2", Func::Atan2, 2),
+    ("pow", Func::Pow, 2),
+    ("min", Func::Min, 2),
+    ("max", Func::Max, 2),
+    ("clamp", Func::Clamp, 3),
+    ("if", Func::If, 3),
+    ("saw", Func::Saw, 1),
+    ("square", Func::Square, 1),
+    ("tri", Func::Tri, 1),
+    ("env", Func::Env, 1),
+    ("noise", Func::Noise, 0),
+];
+
+/* The values of the variables at the point being evaluated
+ */
+struct Context {
+    x: f64,
+    h: f64,
+    frame: f64,
+    rng: StdRng,
+}
+
+impl Expr {
+    /** Parses an expression
+
+    # Arguments
+
+    * `src`: The expression. See the [`Expr`] documentation for the syntax.
+
+    # Errors
+
+    Returns an error that describes the problem and where it is if the expression can't be parsed.
+    */
+    pub fn parse(src: &str) -> Result<Self, std::io::Error> {
+        let mut parser = Parser {
+            tokens: tokenize(src)?,
+            pos: 0,
+            depth: 0,
+        };
+        let root = parser.expression()?;
+        match parser.peek() {
+            None => Ok(Expr { root }),
+            Some((_, at)) => Err(error(format!("Unexpected '{}' at {}", &src[at..], at))),
+        }
+    }
+
+    /** Generates the samples of a table, frame by frame
+
+    The expression is evaluated with `x` stepping across each frame and `frame` stepping from 0 to 1 across the frames.
+
+    # Arguments
+
+    * `len`:    The number of samples in each frame
+    * `frames`: The number of frames
+
+    # Errors
+
+    Returns an error if the expression uses `h`, or if it evaluates to infinity or NaN anywhere.
+    */
+    pub fn waveform(&self, len: usize, frames: usize) -> Result<Vec<f32>, std::io::Error> {
+        self.check_vars(Var::H, "waveform")?;
+        let mut ctx = Context::new();
+        let mut table = Vec::with_capacity(len * frames);
+        for f in 0..frames {
+            ctx.frame = frame_pos(f, frames);
+            for i in 0..len {
+                ctx.x = TAU * i as f64 / len as f64;
+                table.push(self.eval_finite(&mut ctx)? as f32);
+            }
+        }
+        Ok(table)
+    }
+
+    /** Generates the harmonic amplitudes of a table, frame by frame
+
+    The expression is evaluated with `h` stepping from 1 to `count` for each frame, and `frame` stepping from 0 to 1
+    across the frames. The results can be synthesized with [`crate::utils::additive`].
+
+    # Arguments
+
+    * `count`:  The number of harmonics in each frame
+    * `frames`: The number of frames
+
+    # Errors
+
+    Returns an error if the expression uses `x`, or if it evaluates to infinity or NaN anywhere.
+    */
+    pub fn harmonics(&self, count: usize, frames: usize) -> Result<Vec<Vec<f32>>, std::io::Error> {
+        self.check_vars(Var::X, "harmonic")?;
+        let mut ctx = Context::new();
+        let mut amplitudes = Vec::with_capacity(frames);
+        for f in 0..frames {
+            ctx.frame = frame_pos(f, frames);
+            let mut frame = Vec::with_capacity(count);
+            for h in 1..=count {
+                ctx.h = h as f64;
+                frame.push(self.eval_finite(&mut ctx)? as f32);
+            }
+            amplitudes.push(frame);
+        }
+        Ok(amplitudes)
+    }
+
+    /* Returns an error if the expression uses a variable that isn't available
+     */
+    fn check_vars(&self, var: Var, kind: &str) -> Result<(), std::io::Error> {
+        if self.root.uses(var) {
+            let name = if var == Var::X { "x" } else { "h" };
+            return Err(error(format!(
+                "The variable '{}' can't be used in a {} expression",
+                name, kind
+            )));
+        }
+        Ok(())
+    }
+
+    fn eval_finite(&self, ctx: &mut Context) -> Result<f64, std::io::Error> {
+        let value = self.root.eval(ctx);
+        if value.is_finite() {
+            Ok(value)
+        } else {
+            Err(error(format!(
+                "The expression evaluated to {} at x = {}, h = {}, frame = {}",
+                value, ctx.x, ctx.h, ctx.frame
+            )))
+        }
+    }
+}
+
+impl Context {
+    fn new() -> Self {
+        Context {
+            x: 0.0,
+            h: 0.0,
+            frame: 0.0,
+            rng: StdRng::seed_from_u64(0),
+        }
+    }
+}
+
+impl Node {
+    fn eval(&self, ctx: &mut Context) -> f64 {
+        match self {
+            Node::Num(value) => *value,
+            Node::Var(Var::X) => ctx.x,
+            Node::Var(Var::H) => ctx.h,
+            Node::Var(Var::Frame) => ctx.frame,
+            Node::Neg(node) => -node.eval(ctx),
+            Node::Binary(op, lhs, rhs) => {
+                let (a, b) = (lhs.eval(ctx), rhs.eval(ctx));
+                let truth = |cond: bool| if cond { 1.0 } else { 0.0 };
+                match op {
+                    BinOp::Add => a + b,
+                    BinOp::Sub => a - b,
+                    BinOp::Mul => a * b,
+                    BinOp::Div => a / b,
+                    BinOp::Rem => a - b * (a / b).floor(),
+                    BinOp::Pow => a.powf(b),
+                    BinOp::Lt => truth(a < b),
+                    BinOp::Le => truth(a <= b),
+                    BinOp::Gt => truth(a > b),
+                    BinOp::Ge => truth(a >= b),
+                    BinOp::Eq => truth(a == b),
+                    BinOp::Ne => truth(a != b),
+                }
+            }
+            Node::Call(func, args) => {
+                let mut values = [0.0; 3];
+                for (value, arg) in values.iter_mut().zip(args) {
+                    *value = arg.eval(ctx);
+                }
+                let arg = |n: usize| values[n];
+                // The position within a 2π period, in the range of [0, 1)
+                let cycle = |p: f64| (p / TAU).rem_euclid(1.0);
+                match func {
+                    Func::Sin => arg(0).sin(),
+                    Func::Cos => arg(0).cos(),
+                    Func::Tan => arg(0).tan(),
+                    Func::Asin => arg(0).asin(),
+                    Func::Acos => arg(0).acos(),
+                    Func::Atan => arg(0).atan(),
+                    Func::Sinh => arg(0).sinh(),
+                    Func::Cosh => arg(0).cosh(),
+                    Func::Tanh => arg(0).tanh(),
+                    Func::Exp => arg(0).exp(),
+                    Func::Ln => arg(0).ln(),
+                    Func::Log2 => arg(0).log2(),
+                    Func::Log10 => arg(0).log10(),
+                    Func::Sqrt => arg(0).sqrt(),
+                    Func::Abs => arg(0).abs(),
+                    Func::Sign => {
+                        let v = arg(0);
+                        if v == 0.0 {
+                            0.0
+                        } else {
+                            v.signum()
+                        }
+                    }
+                    Func::Floor => arg(0).floor(),
+                    Func::Ceil => arg(0).ceil(),
+                    Func::Round => arg(0).round(),
+                    Func::Fract => arg(0).fract(),
+                    Func::Atan2 => arg(0).atan2(arg(1)),
+                    Func::Pow => arg(0).powf(arg(1)),
+                    Func::Min => arg(0).min(arg(1)),
+                    Func::Max => arg(0).max(arg(1)),
+                    Func::Clamp => {
+                        let (v, lo, hi) = (arg(0), arg(1), arg(2));
+                        v.max(lo).min(hi)
+                    }
+                    Func::If => {
+                        if arg(0) != 0.0 {
+                            arg(1)
+                        } else {
+                            arg(2)
+                        }
+                    }
+                    Func::Saw => 2.0 * cycle(arg(0)) - 1.0,
+                    Func::Square => {
+                        if cycle(arg(0)) < 0.5 {
+                            1.0
+                        } else {
+                            -1.0
+                        }
+                    }
+                    Func::Tri => {
+                        let c = cycle(arg(0));
+                        1.0 - 4.0 * (c - 0.25).abs().min((c - 1.25).abs())
+                    }
+                    Func::Env => {
+                        let p = arg(0);
+                        if (0.0..=1.0).contains(&p) {
+                            0.5 - 0.5 * (TAU * p).cos()
+                        } else {
+                            0.0
+                        }
+                    }
+                    Func::Noise => ctx.rng.gen_range(-1.0..=1.0),
+                }
+            }
+        }
+    }
+
+    fn uses(&self, var: Var) -> bool {
+        match self {
+            Node::Num(_) => false,
+            Node::Var(v) => *v == var,
+            Node::Neg(node) => node.uses(var),
+            Node::Binary(_, lhs, rhs) => lhs.uses(var) || rhs.uses(var),
+            Node::Call(_, args) => args.iter().any(|arg| arg.uses(var)),
+        }
+    }
+}
+
+fn frame_pos(frame: usize, frames: usize) -> f64 {
+    if frames > 1 {
+        frame as f64 / (frames - 1) as f64
+    } else {
+        0.0
+    }
+}
+
+fn error(msg: String) -> std::io::Error {
+    std::io::Error::new(ErrorKind::Other, msg)
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Num(f64),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Comma,
+}
+
+/* Splits the expression into tokens, each with its position in the source
+ */
+fn tokenize(src: &str) -> Result<Vec<(Token, usize)>, std::io::Error> {
+    const OPS: [&str; 13] = [
+        "<=", ">=", "==", "!=", "+", "-", "*", "/", "%", "^", "<", ">", "=",
+    ];
+    let bytes = src.as_bytes();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let c = bytes[pos] as char;
+        let start = pos;
+        if c.is_ascii_whitespace() {
+            pos += 1;
+            continue;
+        } else if c.is_ascii_digit() || c == '.' {
+            while pos < bytes.len() && (bytes[pos].is_ascii_digit() || bytes[pos] == b'.') {
+                pos += 1;
+            }
+            // An exponent, like 1e-3
+            if pos < bytes.len() && (bytes[pos] == b'e' || bytes[pos] == b'E') {
+                let mut end = pos + 1;
+                if end < bytes.len() && (bytes[end] == b'+' || bytes[end] == b'-') {
+                    end += 1;
+                }
+                if end < bytes.len() && bytes[end].is_ascii_digit() {
+                    pos = end;
+                    while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+                        pos += 1;
+                    }
+                }
+            }
+            let text = &src[start..pos];
+            let value = text
+                .parse()
+                .map_err(|_| error(format!("Invalid number '{}' at {}", text, start)))?;
+            tokens.push((Token::Num(value), start));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            while pos < bytes.len() && (bytes[pos].is_ascii_alphanumeric() || bytes[pos] == b'_') {
+                pos += 1;
+            }
+            tokens.push((Token::Ident(src[start..pos].to_string()), start));
+        } else if c == '(' {
+            tokens.push((Token::LParen, start));
+            pos += 1;
+        } else if c == ')' {
+            tokens.push((Token::RParen, start));
+            pos += 1;
+        } else if c == ',' {
+            tokens.push((Token::Comma, start));
+            pos += 1;
+        } else if let Some(op) = OPS.iter().find(|op| src[pos..].starts_with(*op)) {
+            if *op == "=" {
+                return Err(error(format!(
+                    "Unexpected '=' at {} (did you mean '=='?)",
+                    start
+                )));
+            }
+            tokens.push((Token::Op(op), start));
+            pos += op.len();
+        } else {
+            let c = src[pos..].chars().next().unwrap_or(c);
+            return Err(error(format!("Unexpected character '{}' at {}", c, start)));
+        }
+    }
+    Ok(tokens)
+}
+
+/* A recursive descent parser, with one function per level of precedence
+ */
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    // How many levels deep the parser is, which bounds its recursion
+    depth: usize,
+}
+
+/* The deepest that expressions can be nested, so that a hostile patch file can't overflow the stack
+ */
+const MAX_DEPTH: usize = 256;
+
+impl Parser {
+    fn peek(&self) -> Option<(&Token, usize)> {
+        self.tokens.get(self.pos).map(|(token, at)| (token, *at))
+    }
+
+    fn next(&mut self) -> Result<(Token, usize), std::io::Error> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| error("Unexpected end of expression".to_string()))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    /* Consumes the next token if it's one of the given operators
+     */
+    fn op(&mut self, ops: &[&str]) -> Option<&'static str> {
+        match self.peek() {
+            Some((Token::Op(op), _)) if ops.contains(op) => {
+                let op = *op;
+                self.pos += 1;
+                Some(op)
+            }
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, expected: Token, what: &str) -> Result<(), std::io::Error> {
+        let (token, at) = self.next()?;
+        if token == expected {
+            Ok(())
+        } else {
+            Err(error(format!("Expected {} at {}", what, at)))
+        }
+    }
+
+    // expression := sum (comparison sum)?
+    fn expression(&mut self) -> Result<Node, std::io::Error> {
+        let lhs = self.sum()?;
+        let op = match self.op(&["<", "<=", ">", ">=", "==", "!="]) {
+            Some("<") => BinOp::Lt,
+            Some("<=") => BinOp::Le,
+            Some(">") => BinOp::Gt,
+            Some(">=") => BinOp::Ge,
+            Some("==") => BinOp::Eq,
+            Some(_) => BinOp::Ne,
+            None => return Ok(lhs),
+        };
+        Ok(Node::Binary(op, Box::new(lhs), Box::new(self.sum()?)))
+    }
+
+    // sum := product (('+' | '-') product)*
+    fn sum(&mut self) -> Result<Node, std::io::Error> {
+        let mut node = self.product()?;
+        while let Some(op) = self.op(&["+", "-"]) {
+            let op = if op == "+" { BinOp::Add } else { BinOp::Sub };
+            node = Node::Binary(op, Box::new(node), Box::new(self.product()?));
+        }
+        Ok(node)
+    }
+
+    // product := unary (('*' | '/' | '%') unary)*
+    fn product(&mut self) -> Result<Node, std::io::Error> {
+        let mut node = self.unary()?;
+        while let Some(op) = self.op(&["*", "/", "%"]) {
+            let op = match op {
+                "*" => BinOp::Mul,
+                "/" => BinOp::Div,
+                _ => BinOp::Rem,
+            };
+            node = Node::Binary(op, Box::new(node), Box::new(self.unary()?));
+        }
+        Ok(node)
+    }
+
+    // unary := ('-' | '+') unary | power
+    //
+    // Every path back into the grammar passes through here, so this is where the depth is counted
+    fn unary(&mut self) -> Result<Node, std::io::Error> {
+        if self.depth == MAX_DEPTH {
+            let at = self.peek().map_or(0, |(_, at)| at);
+            return Err(error(format!(
+                "The expression is nested more than {} levels deep at {}",
+                MAX_DEPTH, at
+            )));
+        }
+        self.depth += 1;
+        let node = match self.op(&["-", "+"]) {
+            Some("-") => Ok(Node::Neg(Box::new(self.unary()?))),
+            Some(_) => self.unary(),
+            None => self.power(),
+        };
+        self.depth -= 1;
+        node
+    }
+
+    // power := primary ('^' unary)?
+    fn power(&mut self) -> Result<Node, std::io::Error> {
+        let base = self.primary()?;
+        match self.op(&["^"]) {
+            Some(_) => Ok(Node::Binary(
+                BinOp::Pow,
+                Box::new(base),
+                Box::new(self.unary()?),
+            )),
+            None => Ok(base),
+        }
+    }
+
+    // primary := number | variable | function '(' arguments ')' | '(' expression ')'
+    fn primary(&mut self) -> Result<Node, std::io::Error> {
+        let (token, at) = self.next()?;
+        match token {
+            Token::Num(value) => Ok(Node::Num(value)),
+            Token::LParen => {
+                let node = self.expression()?;
+                self.expect(Token::RParen, "')'")?;
+                Ok(node)
+            }
+            Token::Ident(name) => {
+                if let Some((_, func, arity)) = FUNCS.iter().find(|(f, _, _)| *f == name) {
+                    return self.call(&name, *func, *arity, at);
+                }
+                match name.as_str() {
+                    "x" => Ok(Node::Var(Var::X)),
+                    "h" => Ok(Node::Var(Var::H)),
+                    "frame" => Ok(Node::Var(Var::Frame)),
+                    "pi" => Ok(Node::Num(PI)),
+                    "tau" => Ok(Node::Num(TAU)),
+                    "e" => Ok(Node::Num(E)),
+                    _ => Err(error(format!("Unknown name '{}' at {}", name, at))),
+                }
+            }
+            _ => Err(error(format!("Expected a value at {}", at))),
+        }
+    }
+
+    fn call(
+        &mut self,
+        name: &str,
+        func: Func,
+        arity: usize,
+        at: usize,
+    ) -> Result<Node, std::io::Error> {
+        self.expect(Token::LParen, &format!("'(' after '{}'", name))?;
+        let mut args = Vec::with_capacity(arity);
+        if let Some((Token::RParen, _)) = self.peek() {
+            self.pos += 1;
+        } else {
+            loop {
+                args.push(self.expression()?);
+                let (token, tokat) = self.next()?;
+                match token {
+                    Token::Comma => continue,
+                    Token::RParen => break,
+                    _ => return Err(error(format!("Expected ',' or ')' at {}", tokat))),
+                }
+            }
+        }
+        if args.len() != arity {
+            return Err(error(format!(
+                "'{}' at {} takes {} argument(s), but was given {}",
+                name,
+                at,
+                arity,
+                args.len()
+            )));
+        }
+        Ok(Node::Call(func, args))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Expr;
+    use float_cmp::approx_eq;
+    use std::f64::consts::PI;
+
+    fn eval(src: &str) -> f32 {
+        Expr::parse(src).unwrap().waveform(1, 1).unwrap()[0]
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(eval("1 + 2 * 3"), 7.0);
+        assert_eq!(eval("(1 + 2) * 3"), 9.0);
+        assert_eq!(eval("2 ^ 3 ^ 2"), 512.0);
+        assert_eq!(eval("-2 ^ 2"), -4.0);
+        assert_eq!(eval("2 ^ -1"), 0.5);
+        assert_eq!(eval("10 - 4 - 3"), 3.0);
+        assert_eq!(eval("-7 % 3"), 2.0);
+        assert_eq!(eval("1 + 1 == 2"), 1.0);
+        assert_eq!(eval("3 < 2"), 0.0);
+        assert_eq!(eval("1.5e1 + .5"), 15.5);
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(eval("max(1, min(5, 3))"), 3.0);
+        assert_eq!(eval("clamp(7, 0, 2)"), 2.0);
+        assert_eq!(eval("if(0, 1, 2) + if(3, 10, 20)"), 12.0);
+        assert!(approx_eq!(f32, eval("sin(pi / 2) + cos(0)"), 2.0));
+        assert!(approx_eq!(
+            f32,
+            eval("saw(pi / 2) + square(3 * pi) + tri(tau + pi / 2)"),
+            -0.5
+        ));
+        assert_eq!(eval("env(0.5) + env(-1) + env(2)"), 1.0);
+        assert!(approx_eq!(f32, eval("env(0.25)"), 0.5));
+        let noise = Expr::parse("noise()").unwrap().waveform(1000, 1).unwrap();
+        assert!(noise.iter().all(|v| (-1.0..=1.0).contains(v)));
+        assert!(noise.iter().any(|v| *v != noise[0]));
+        // The noise is the same every time
+        assert_eq!(
+            noise,
+            Expr::parse("noise()").unwrap().waveform(1000, 1).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_variables() {
+        let expr = Expr::parse("sin(x) * frame").unwrap();
+        let table = expr.waveform(4, 3).unwrap();
+        assert_eq!(table.len(), 12);
+        let expected = [0.0, 0.0, 0.0, 0.0, 0.0, 0.5, 0.0, -0.5, 0.0, 1.0, 0.0, -1.0];
+        for (v, e) in table.iter().zip(expected) {
+            assert!(approx_eq!(f32, *v, e, epsilon = 1e-6), "{:?}", table);
+        }
+
+        let harmonics = Expr::parse("1 / h + frame")
+            .unwrap()
+            .harmonics(3, 2)
+            .unwrap();
+        assert_eq!(
+            harmonics,
+            vec![vec![1.0, 0.5, 1.0 / 3.0], vec![2.0, 1.5, 4.0 / 3.0]]
+        );
+        assert!(approx_eq!(f64, PI, eval("pi") as f64, epsilon = 1e-6));
+
+        // The third harmonic swells in the middle frame and is gone again by the last one
+        let table = Expr::parse("sin(x) + 0.3*sin(3*x)*env(frame)")
+            .unwrap()
+            .waveform(4, 3)
+            .unwrap();
+        let expected = [
+            0.0, 1.0, 0.0, -1.0, 0.0, 0.7, 0.0, -0.7, 0.0, 1.0, 0.0, -1.0,
+        ];
+        for (v, e) in table.iter().zip(expected) {
+            assert!(approx_eq!(f32, *v, e, epsilon = 1e-6), "{:?}", table);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        for (src, msg) in [
+            ("sin(x", "Unexpected end of expression"),
+            ("1 +", "Unexpected end of expression"),
+            ("foo(x)", "Unknown name 'foo' at 0"),
+            ("2 * y", "Unknown name 'y' at 4"),
+            (
+                "sin(1, 2)",
+                "'sin' at 0 takes 1 argument(s), but was given 2",
+            ),
+            ("1 2", "Unexpected '2' at 2"),
+            ("x = 1", "Unexpected '=' at 2 (did you mean '=='?)"),
+            ("1 # 2", "Unexpected character '#' at 2"),
+            ("sin + 1", "Expected '(' after 'sin' at 4"),
+            (
+                &format!("{}1{}", "(".repeat(300), ")".repeat(300)),
+                "The expression is nested more than 256 levels deep at 256",
+            ),
+            (
+                &"-".repeat(100_000),
+                "The expression is nested more than 256 levels deep at 256",
+            ),
+        ] {
+            match Expr::parse(src) {
+                Ok(_) => panic!("'{}' should not parse", src),
+                Err(e) => assert_eq!(e.to_string(), msg),
+            }
+        }
+
+        let e = Expr::parse("h").unwrap().waveform(8, 1).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "The variable 'h' can't be used in a waveform expression"
+        );
+        let e = Expr::parse("x").unwrap().harmonics(8, 1).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "The variable 'x' can't be used in a harmonic expression"
+        );
+        assert!(Expr::parse("1 / x").unwrap().waveform(8, 1).is_err());
+    }
+}
@@ -0,0 +1,422 @@
+use super::utils;
+use super::wt::Wavetable;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::{json, Value};
+use std::io::ErrorKind;
+
+/* The flags in a Surge .wt header
+ */
+const SURGE_IS_SAMPLE: u16 = 0x01;
+const SURGE_INT16: u16 = 0x04;
+const SURGE_INT16_IS_16: u16 = 0x08;
+
+/* The largest number of frames that a Surge .wt file can hold
+ */
+const SURGE_MAX_FRAMES: usize = 512;
+
+/** The number of samples in each frame of a Vital wavetable
+ */
+pub const VITAL_FRAME_LEN: usize = 2048;
+
+/** The largest number of frames that a Vital wavetable can hold
+ */
+pub const VITAL_MAX_FRAMES: usize = 256;
+
+/** Reads a Surge `.wt` wavetable file
+
+Surge's `.wt` files start with a 12 byte header: the magic `vawt`, the number of samples in each frame (as a
+little-endian u32), the number of frames (as a u16) and a set of flags (as a u16). The frames follow the header, either as
+32-bit floats or as 16-bit integers. Any metadata after the frames is ignored.
+
+# Arguments
+
+* `path`: The path to the `.wt` file
+
+# Errors
+
+Returns an error if the file can't be read or isn't a valid `.wt` file.
+*/
+pub fn read_surge(path: &str) -> Result<Wavetable, std::io::Error> {
+    let data = read_file(path)?;
+    let (table, frame_len) = decode_surge(&data).map_err(|e| with_path(path, e))?;
+    Ok(Wavetable::new_frames_exact(&table, frame_len))
+}
+
+/** Writes a Wavetable to a Surge `.wt` file
+
+The frames are written as 32-bit floats.
+
+# Arguments
+
+* `path`:  The path to the `.wt` file
+* `table`: The wavetable to write
+
+# Errors
+
+Returns an error if the file can't be written, or if the table can't be stored as a `.wt` file. Surge needs each frame to
+be a power of two long, and it can't hold more than 512 frames.
+*/
+pub fn write_surge(path: &str, table: &Wavetable) -> Result<(), std::io::Error> {
+    let data = encode_surge(table).map_err(|e| with_path(path, e))?;
+    write_file(path, &data)
+}
+
+/** Reads a Vital `.vitaltable` wavetable file
+
+Vital's wavetables are JSON files. The first group's first wave source holds a list of keyframes, each with a position
+in the range of [0, 256) and a frame of 2048 32-bit floats, encoded in base64. Each keyframe becomes a frame of the
+Wavetable, in order of position, so keyframes that aren't evenly spaced will morph at a different rate than in Vital.
+Other kinds of sources (like Vital's audio file and line sources) aren't supported.
+
+# Arguments
+
+* `path`: The path to the `.vitaltable` file
+
+# Errors
+
+Returns an error if the file can't be read, isn't valid JSON, or doesn't hold a wave source.
+*/
+pub fn read_vital(path: &str) -> Result<Wavetable, std::io::Error> {
+    let data = read_file(path)?;
+    let table = decode_vital(&data).map_err(|e| with_path(path, e))?;
+    Ok(Wavetable::new_frames(&table, VITAL_FRAME_LEN))
+}
+
+/** Writes a Wavetable to a Vital `.vitaltable` file
+
+The frames become keyframes that are spread evenly across Vital's 256 positions. Frames that aren't 2048 samples long
+are resampled.
+
+# Arguments
+
+* `path`:  The path to the `.vitaltable` file
+* `table`: The wavetable to write
+* `name`:  The name that Vital shows for the table
+
+# Errors
+
+Returns an error if the file can't be written or the table has more than [`VITAL_MAX_FRAMES`] frames.
+*/
+pub fn write_vital(path: &str, table: &Wavetable, name: &str) -> Result<(), std::io::Error> {
+    let data = encode_vital(table, name).map_err(|e| with_path(path, e))?;
+    write_file(path, &data)
+}
+
+/* Decodes the contents of a .wt file into its samples and frame length
+ */
+fn decode_surge(data: &[u8]) -> Result<(Vec<f32>, usize), std::io::Error> {
+    if data.len() < 12 || &data[0..4] != b"vawt" {
+        return Err(error("is not a Surge wavetable"));
+    }
+    let frame_len = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize;
+    let nframes = u16::from_le_bytes([data[8], data[9]]) as usize;
+    let flags = u16::from_le_bytes([data[10], data[11]]);
+    if frame_len == 0 || nframes == 0 {
+        return Err(error("has no samples"));
+    }
+    if flags & SURGE_IS_SAMPLE != 0 {
+        return Err(error("holds a sample, not a wavetable"));
+    }
+
+    let nsamples = frame_len * nframes;
+    let body = &data[12..];
+    let table = if flags & SURGE_INT16 != 0 {
+        // 16-bit tables are stored at 15 bits unless they say otherwise
+        let scale = if flags & SURGE_INT16_IS_16 != 0 {
+            1.0 / 32768.0
+        } else {
+            1.0 / 16384.0
+        };
+        if body.len() < nsamples * 2 {
+            return Err(error("is shorter than its header says"));
+        }
+        Vec::from_iter(
+            body.chunks_exact(2)
+                .take(nsamples)
+                .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 * scale),
+        )
+    } else {
+        if body.len() < nsamples * 4 {
+            return Err(error("is shorter than its header says"));
+        }
+        Vec::from_iter(
+            body.chunks_exact(4)
+                .take(nsamples)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        )
+    };
+    Ok((table, frame_len))
+}
+
+/* Encodes a Wavetable as the contents of a .wt file
+ */
+fn encode_surge(table: &Wavetable) -> Result<Vec<u8>, std::io::Error> {
+    if !table.len().is_power_of_two() {
+        return Err(error(&format!(
+            "can't be written because Surge frames must be a power of two long. Got {}",
+            table.len()
+        )));
+    }
+    if table.frames() > SURGE_MAX_FRAMES {
+        return Err(error(&format!(
+            "can't be written because Surge can't hold more than {} frames. Got {}",
+            SURGE_MAX_FRAMES,
+            table.frames()
+        )));
+    }
+    let samples = table.samples();
+    let mut data = Vec::with_capacity(12 + samples.len() * 4);
+    data.extend_from_slice(b"vawt");
+    data.extend_from_slice(&(table.len() as u32).to_le_bytes());
+    data.extend_from_slice(&(table.frames() as u16).to_le_bytes());
+    data.extend_from_slice(&0u16.to_le_bytes());
+    for v in samples {
+        data.extend_from_slice(&v.to_le_bytes());
+    }
+    Ok(data)
+}
+
+/* Decodes the contents of a .vitaltable file into its samples, which are always VITAL_FRAME_LEN samples per frame
+ */
+fn decode_vital(data: &[u8]) -> Result<Vec<f32>, std::io::Error> {
+    let root: Value =
+        serde_json::from_slice(data).map_err(|e| error(&format!("is not valid JSON. {}", e)))?;
+    let source = root["groups"]
+        .as_array()
+        .and_then(|groups| groups.first())
+        .and_then(|group| group["components"].as_array())
+        .and_then(|components| {
+            components
+                .iter()
+                .find(|component| component["type"] == "Wave Source")
+        })
+        .ok_or_else(|| error("has no wave source"))?;
+    let keyframes = source["keyframes"]
+        .as_array()
+        .filter(|keyframes| !keyframes.is_empty())
+        .ok_or_else(|| error("has no keyframes"))?;
+
+    let mut frames = Vec::with_capacity(keyframes.len());
+    for keyframe in keyframes {
+        let position = keyframe["position"].as_f64().unwrap_or(0.0);
+        let wave = keyframe["wave_data"]
+            .as_str()
+            .ok_or_else(|| error("has a keyframe without wave data"))?;
+        let bytes = BASE64
+            .decode(wave)
+            .map_err(|e| error(&format!("has a keyframe with bad wave data. {}", e)))?;
+        if bytes.len() != VITAL_FRAME_LEN * 4 {
+            return Err(error(&format!(
+                "has a keyframe with {} bytes of wave data, instead of {}",
+                bytes.len(),
+                VITAL_FRAME_LEN * 4
+            )));
+        }
+        let frame = Vec::from_iter(
+            bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        );
+        frames.push((position, frame));
+    }
+    frames.sort_by(|a, b| a.0.total_cmp(&b.0));
+    Ok(Vec::from_iter(
+        frames.into_iter().flat_map(|(_, frame)| frame),
+    ))
+}
+
+/* Encodes a Wavetable as the contents of a .vitaltable file
+ */
+fn encode_vital(table: &Wavetable, name: &str) -> Result<Vec<u8>, std::io::Error> {
+    let nframes = table.frames();
+    if nframes > VITAL_MAX_FRAMES {
+        return Err(error(&format!(
+            "can't be written because Vital can't hold more than {} frames. Got {}",
+            VITAL_MAX_FRAMES, nframes
+        )));
+    }
+    let samples = table.samples();
+    let keyframes = Vec::from_iter(samples.chunks_exact(table.len()).enumerate().map(
+        |(k, frame)| {
+            let frame = if frame.len() == VITAL_FRAME_LEN {
+                frame.to_vec()
+            } else {
+                utils::resample(frame, VITAL_FRAME_LEN, true)
+            };
+            let bytes = Vec::from_iter(frame.iter().flat_map(|v| v.to_le_bytes()));
+            let position = if nframes > 1 {
+                (k * (VITAL_MAX_FRAMES - 1) + (nframes - 1) / 2) / (nframes - 1)
+            } else {
+                0
+            };
+            json!({
+                "position": position,
+                "wave_data": BASE64.encode(bytes),
+            })
+        },
+    ));
+    let root = json!({
+        "author": "",
+        "full_normalize": false,
+        "groups": [{
+            "components": [{
+                "interpolation": 1,
+                "interpolation_style": 1,
+                "keyframes": keyframes,
+                "type": "Wave Source",
+            }],
+        }],
+        "name": name,
+        "remove_all_dc": false,
+        "version": "1.0.7",
+    });
+    Ok(root.to_string().into_bytes())
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>, std::io::Error> {
+    std::fs::read(path).map_err(|e| {
+        std::io::Error::new(ErrorKind::Other, format!("Unable to open {}. {}", path, e))
+    })
+}
+
+fn write_file(path: &str, data: &[u8]) -> Result<(), std::io::Error> {
+    std::fs::write(path, data).map_err(|e| {
+        std::io::Error::new(ErrorKind::Other, format!("Unable to write {}. {}", path, e))
+    })
+}
+
+/* Creates an error whose message is completed by the path of the file that it's about (see with_path)
+ */
+fn error(message: &str) -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+fn with_path(path: &str, e: std::io::Error) -> std::io::Error {
+    std::io::Error::new(e.kind(), format!("{} {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::wt::Wavetable;
+    use super::{
+        decode_surge, decode_vital, encode_surge, encode_vital, read_surge, read_vital,
+        write_surge, write_vital, VITAL_FRAME_LEN,
+    };
+    use float_cmp::approx_eq;
+    use std::f32::consts::PI;
+
+    fn generate_frames(len: usize, frames: usize) -> Vec<f32> {
+        Vec::from_iter((0..len * frames).map(|i| {
+            let (f, n) = (i / len, i % len);
+            0.5 * ((f + 1) as f32 * 2.0 * PI * n as f32 / len as f32).sin()
+        }))
+    }
+
+    fn assert_close(table: &[f32], expected: &[f32]) {
+        assert_eq!(table.len(), expected.len());
+        for (i, (v, e)) in table.iter().zip(expected).enumerate() {
+            assert!(
+                approx_eq!(f32, *v, *e, epsilon = 1e-6),
+                "table[{}] = {}, expected: {}",
+                i,
+                v,
+                e
+            );
+        }
+    }
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("wavetable-{}-{}", std::process::id(), name))
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_surge_round_trip() {
+        let samples = generate_frames(256, 3);
+        let wt = Wavetable::new_frames(&samples, 256);
+        let path = temp_path("round_trip.wt");
+        write_surge(&path, &wt).unwrap();
+        let read = read_surge(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((read.len(), read.frames()), (256, 3));
+        assert_close(&read.samples(), &samples);
+    }
+
+    #[test]
+    fn test_surge_int16() {
+        let mut data = Vec::from(&b"vawt"[..]);
+        data.extend_from_slice(&4u32.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&0x04u16.to_le_bytes());
+        for v in [0i16, 16384, 0, -16384] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        // 15-bit samples
+        assert_eq!(decode_surge(&data).unwrap(), (vec![0.0, 1.0, 0.0, -1.0], 4));
+
+        // 16-bit samples
+        data[10] |= 0x08;
+        assert_eq!(decode_surge(&data).unwrap(), (vec![0.0, 0.5, 0.0, -0.5], 4));
+
+        // Truncated
+        assert!(decode_surge(&data[..18]).is_err());
+        assert!(decode_surge(b"RIFF").is_err());
+    }
+
+    #[test]
+    fn test_surge_limits() {
+        let wt = Wavetable::new_exact(&generate_frames(300, 1));
+        assert!(encode_surge(&wt).is_err());
+        assert!(read_surge("test/missing.wt").is_err());
+    }
+
+    #[test]
+    fn test_vital_round_trip() {
+        let samples = generate_frames(VITAL_FRAME_LEN, 3);
+        let wt = Wavetable::new_frames(&samples, VITAL_FRAME_LEN);
+        let path = temp_path("round_trip.vitaltable");
+        write_vital(&path, &wt, "Sines").unwrap();
+        let read = read_vital(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((read.len(), read.frames()), (VITAL_FRAME_LEN, 3));
+        assert_close(&read.samples(), &samples);
+
+        let json: serde_json::Value =
+            serde_json::from_slice(&encode_vital(&wt, "Sines").unwrap()).unwrap();
+        assert_eq!(json["name"], "Sines");
+        let positions = Vec::from_iter(
+            json["groups"][0]["components"][0]["keyframes"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|k| k["position"].as_u64().unwrap()),
+        );
+        assert_eq!(positions, vec![0, 128, 255]);
+    }
+
+    #[test]
+    fn test_vital_resample() {
+        //! Frames that aren't 2048 samples long are resampled to fit
+        let wt = Wavetable::new(&generate_frames(1024, 1));
+        let data = encode_vital(&wt, "Sine").unwrap();
+        let table = decode_vital(&data).unwrap();
+        assert_eq!(table.len(), VITAL_FRAME_LEN);
+        for (i, v) in table.iter().enumerate() {
+            let expected = 0.5 * (2.0 * PI * i as f32 / VITAL_FRAME_LEN as f32).sin();
+            assert!(
+                approx_eq!(f32, *v, expected, epsilon = 1e-5),
+                "table[{}] = {}, expected: {}",
+                i,
+                v,
+                expected
+            );
+        }
+
+        assert!(decode_vital(b"{\"groups\": []}").is_err());
+        assert!(decode_vital(b"not json").is_err());
+    }
+}
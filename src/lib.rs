@@ -1,5 +1,10 @@
+pub mod analysis;
 pub mod envelope;
+pub mod expr;
+pub mod format;
+pub mod swap;
 pub mod system;
+pub mod unison;
 pub mod utils;
 pub mod voice;
 pub mod wt;
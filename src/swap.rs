@@ -0,0 +1,259 @@
+use super::system::System;
+use super::wt::Wavetable;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/** Creates a channel for swapping the wavetable that the audio thread plays
+
+The [`TableSender`] stays on a control thread (like a UI or a file loader) and the [`TableReceiver`] goes to the audio
+thread, along with the oscillators that it feeds. Neither end ever locks, and the receiver never allocates or frees
+anything, so it's safe to use in an audio callback.
+
+A table goes through three hands:
+1. The sender hands it over with [`TableSender::send`], which only swaps a pointer. If the receiver hasn't picked up the
+   last table yet, then that one is replaced (and freed by the sender).
+2. The receiver picks it up with [`TableReceiver::receive`], which also swaps a pointer, and hands it to its oscillators
+   (see [`Phasor::set_table`](super::wt::Phasor::set_table)). They crossfade from the old table to the new one, and then
+   let go of the old one.
+3. Once the crossfade is over, the receiver passes the old table back, and the sender lets go of it the next time that
+   it sends a table or calls [`TableSender::collect`].
+
+The receiver counts down the crossfade itself, one buffer of the system's `bufsize` samples for each call to
+[`TableReceiver::receive`], so it works no matter who else holds the tables. It waits for the crossfade to end before it
+picks up another table, so a table is never freed on the audio thread, as long as the oscillators that play it belong to
+the receiver's thread and render a buffer for each call to `receive`.
+
+# Arguments
+
+* `system`: The System parameters, whose buffer size is how far each call to `receive` moves the crossfade along
+* `table`:  The table that the oscillators start with
+*/
+pub fn table_swap(system: &Arc<System>, table: &Arc<Wavetable>) -> (TableSender, TableReceiver) {
+    let shared = Arc::new(Shared {
+        next: AtomicPtr::new(ptr::null_mut()),
+        fade: AtomicUsize::new(0),
+        retired: AtomicPtr::new(ptr::null_mut()),
+    });
+    (
+        TableSender {
+            shared: shared.clone(),
+        },
+        TableReceiver {
+            shared,
+            current: table.clone(),
+            old: None,
+            remaining: 0,
+            bufsize: system.bufsize(),
+        },
+    )
+}
+
+/** The control thread's end of a [`table_swap`]
+
+Senders can be cloned, and all of the clones send to the same receiver.
+*/
+#[derive(Clone)]
+pub struct TableSender {
+    shared: Arc<Shared>,
+}
+
+/** The audio thread's end of a [`table_swap`]
+ */
+pub struct TableReceiver {
+    shared: Arc<Shared>,
+    // The table that the oscillators are playing
+    current: Arc<Wavetable>,
+    // The table that the oscillators are fading out of, which is held until the crossfade is over
+    old: Option<Arc<Wavetable>>,
+    // The number of samples left in the crossfade, and the number that the oscillators render between calls to receive
+    remaining: usize,
+    bufsize: usize,
+}
+
+/* The state that's shared between a sender and a receiver. Each pointer is either null or was made by Arc::into_raw
+ * and owns one reference to its table.
+ */
+struct Shared {
+    // The table that's waiting for the receiver
+    next: AtomicPtr<Wavetable>,
+    // The crossfade time for the next table, in samples
+    fade: AtomicUsize,
+    // The table that the receiver is done with, which is waiting to be freed by a sender
+    retired: AtomicPtr<Wavetable>,
+}
+
+impl TableSender {
+    /** Sends a new table to the receiver
+
+    This also frees the table that the receiver is done with, if there is one.
+
+    # Arguments
+
+    * `table`: The new table
+    * `fade`:  The length of the crossfade from the old table to the new one (in samples)
+    */
+    pub fn send(&self, table: Arc<Wavetable>, fade: usize) {
+        self.collect();
+        self.shared.fade.store(fade, Ordering::Relaxed);
+        let pending = self
+            .shared
+            .next
+            .swap(Arc::into_raw(table) as *mut _, Ordering::AcqRel);
+        if !pending.is_null() {
+            // The receiver never picked this one up
+            drop(unsafe { Arc::from_raw(pending) });
+        }
+    }
+
+    /** Frees the table that the receiver is done with, if there is one
+     */
+    pub fn collect(&self) {
+        let retired = self.shared.retired.swap(ptr::null_mut(), Ordering::AcqRel);
+        if !retired.is_null() {
+            drop(unsafe { Arc::from_raw(retired) });
+        }
+    }
+}
+
+impl TableReceiver {
+    /** Picks up a new table, if one has been sent
+
+    This should be called once per buffer on the audio thread, before the oscillators render the buffer. When it returns
+    a table, that table (and the crossfade time) should be given to all of the oscillators that the receiver feeds.
+    */
+    pub fn receive(&mut self) -> Option<(&Arc<Wavetable>, usize)> {
+        // The oscillators have rendered a buffer since the last call
+        self.remaining = self.remaining.saturating_sub(self.bufsize);
+        if let Some(old) = self.old.take() {
+            if Arc::ptr_eq(&old, &self.current) {
+                // The same table was sent twice, so the current reference will keep it alive
+            } else if self.remaining > 0 || !self.shared.retired.load(Ordering::Acquire).is_null() {
+                // The oscillators are still fading out of it, or the sender hasn't let go of the last one yet
+                self.old = Some(old);
+                return None;
+            } else {
+                self.shared
+                    .retired
+                    .store(Arc::into_raw(old) as *mut _, Ordering::Release);
+            }
+        }
+
+        let next = self.shared.next.swap(ptr::null_mut(), Ordering::AcqRel);
+        if next.is_null() {
+            return None;
+        }
+        let table = unsafe { Arc::from_raw(next) };
+        self.old = Some(std::mem::replace(&mut self.current, table));
+        let fade = self.shared.fade.load(Ordering::Relaxed);
+        self.remaining = fade;
+        Some((&self.current, fade))
+    }
+
+    /** Returns the table that the oscillators are playing
+     */
+    pub fn table(&self) -> &Arc<Wavetable> {
+        &self.current
+    }
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        for table in [self.next.get_mut(), self.retired.get_mut()] {
+            if !table.is_null() {
+                drop(unsafe { Arc::from_raw(*table) });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::system::System;
+    use super::super::wt::{Phasor, Shape, Wavetable};
+    use super::table_swap;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_table_swap() {
+        let system = Arc::new(System::new(48000.0, 1, 64));
+        let sine = Arc::new(Wavetable::from_shape(Shape::Sine, 256));
+        let saw = Arc::new(Wavetable::from_shape(Shape::Saw, 256));
+        let (sender, mut receiver) = table_swap(&system, &sine);
+        let mut phasor = Phasor::new(&system, &sine);
+        let mut outbuf = [0.0; 64];
+        // Only the swap and the phasor hold the first table
+        let first = Arc::downgrade(&sine);
+        drop(sine);
+
+        assert!(receiver.receive().is_none());
+        sender.send(saw.clone(), 100);
+        let (table, fade) = receiver.receive().unwrap();
+        assert!(Arc::ptr_eq(table, &saw));
+        assert_eq!(fade, 100);
+        phasor.set_table(table, fade);
+
+        // The old table is held while the phasor fades out of it
+        let square = Arc::new(Wavetable::from_shape(Shape::Square, 256));
+        sender.send(square.clone(), 0);
+        phasor.perform(&mut outbuf, 440.0, 0.0);
+        assert!(receiver.receive().is_none());
+        assert_eq!(first.strong_count(), 2);
+
+        // Once the fade is over, the old table is passed back, and the next one is picked up
+        phasor.perform(&mut outbuf, 440.0, 0.0);
+        let (table, _) = receiver.receive().unwrap();
+        assert!(Arc::ptr_eq(table, &square));
+        phasor.set_table(table, 0);
+        assert_eq!(first.strong_count(), 1);
+        sender.collect();
+        assert_eq!(first.strong_count(), 0);
+
+        // Tables that are never picked up are freed by the sender
+        let triangle = Arc::new(Wavetable::from_shape(Shape::Triangle, 256));
+        sender.send(triangle.clone(), 0);
+        sender.send(saw.clone(), 0);
+        assert_eq!(Arc::strong_count(&triangle), 1);
+        drop(sender);
+        drop(receiver);
+        drop(phasor);
+        assert_eq!(Arc::strong_count(&saw), 1);
+        assert_eq!(Arc::strong_count(&square), 1);
+    }
+
+    #[test]
+    fn test_table_swap_held() {
+        //! Tables keep swapping while the caller holds on to clones of them
+        let system = Arc::new(System::new(48000.0, 1, 64));
+        let tables = Vec::from_iter(
+            [Shape::Sine, Shape::Saw, Shape::Square]
+                .into_iter()
+                .map(|shape| Arc::new(Wavetable::from_shape(shape, 256))),
+        );
+        let (sender, mut receiver) = table_swap(&system, &tables[0]);
+        let mut phasor = Phasor::new(&system, &tables[0]);
+        let mut outbuf = [0.0; 64];
+
+        for table in &tables[1..] {
+            sender.send(table.clone(), 100);
+            let mut received = None;
+            for _ in 0..4 {
+                if let Some((table, fade)) = receiver.receive() {
+                    phasor.set_table(table, fade);
+                    received = Some(table.clone());
+                    break;
+                }
+                phasor.perform(&mut outbuf, 440.0, 0.0);
+            }
+            assert!(Arc::ptr_eq(&received.unwrap(), table));
+            phasor.perform(&mut outbuf, 440.0, 0.0);
+            phasor.perform(&mut outbuf, 440.0, 0.0);
+        }
+        assert!(Arc::ptr_eq(phasor.table(), &tables[2]));
+
+        // The tables that were swapped out are only held by the caller once the sender has collected them
+        receiver.receive();
+        sender.collect();
+        assert_eq!(Arc::strong_count(&tables[0]), 1);
+    }
+}
@@ -0,0 +1,442 @@
+use super::system::System;
+use super::wt::{Phasor, Wavetable};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::f32::consts::{FRAC_PI_2, TAU};
+use std::sync::Arc;
+
+/** The maximum number of oscillators in a [`Unison`] stack
+ */
+pub const MAX_UNISON: usize = 16;
+
+/** A stack of detuned oscillators that all play the same wavetable
+
+All [`MAX_UNISON`] phasors are created up front, so the number of oscillators that are actually played can be changed
+without allocating. The oscillators are spread evenly across a detune range of +/- the detune amount (in cents), and the
+detune curve bends that spread: a curve of 1.0 keeps it linear, a curve above 1.0 bunches the oscillators toward the
+center pitch (like a classic supersaw) and a curve below 1.0 pushes them out toward the edges.
+
+The center oscillator is the one in the middle of the stack (or the middle two, if there's an even number of them). All
+of the others are side oscillators. The blend crossfades between the center and the sides, and the level of each group is
+normalized by the square root of its size, so that thickening the stack doesn't make it much louder.
+
+By default, every oscillator starts each note at a random phase, which keeps the stack from sounding like a single
+flanged oscillator on every note-on. This can be turned off with [`Unison::set_random_phase`].
+
+The stereo spread pans each oscillator according to its detune, with the outermost oscillators panned hard left and right
+at a spread of 1.0. It only affects [`Unison::perform_stereo`].
+*/
+pub struct Unison {
+    // The oscillators. Only the first `count` of them are played.
+    oscs: Vec<Phasor>,
+    // The number of oscillators to play
+    count: usize,
+    // The detune of the outermost oscillators, in cents
+    detune: f32,
+    // The exponent that shapes the detune spread
+    curve: f32,
+    // The balance between the center (0.0) and side (1.0) oscillators
+    blend: f32,
+    // The stereo width, in a range of [0, 1]
+    spread: f32,
+    // Whether notes start at random phases
+    random_phase: bool,
+    rng: StdRng,
+    // Each oscillator's frequency ratio and its mono, left and right gains
+    ratios: [f32; MAX_UNISON],
+    gains: [[f32; 3]; MAX_UNISON],
+    // Holds each oscillator's output before it's mixed in
+    buffer: Vec<f32>,
+}
+
+impl Unison {
+    /** Creates a new Unison stack
+
+    The stack starts with a detune of 20 cents, a linear detune curve, an even blend, and no stereo spread.
+
+    # Arguments
+
+    * `system`: The System parameters
+    * `table`:  The wavetable that the oscillators will use
+    * `count`:  The number of oscillators to play, in the range of [1, `MAX_UNISON`]
+
+    # Panics
+
+    This function will panic if `count` is out of range.
+    */
+    pub fn new(system: &Arc<System>, table: &Arc<Wavetable>, count: usize) -> Self {
+        let mut unison = Unison {
+            oscs: Vec::from_iter((0..MAX_UNISON).map(|_| Phasor::new(system, table))),
+            count: 1,
+            detune: 20.0,
+            curve: 1.0,
+            blend: 0.5,
+            spread: 0.0,
+            random_phase: true,
+            rng: StdRng::from_entropy(),
+            ratios: [1.0; MAX_UNISON],
+            gains: [[0.0; 3]; MAX_UNISON],
+            buffer: vec![0.0; system.bufsize()],
+        };
+        unison.set_count(count);
+        unison
+    }
+
+    /** Sets the number of oscillators to play
+
+    # Arguments
+
+    * `count`: The number of oscillators, in the range of [1, `MAX_UNISON`]
+
+    # Panics
+
+    This function will panic if `count` is out of range.
+    */
+    pub fn set_count(&mut self, count: usize) {
+        assert!(
+            (1..=MAX_UNISON).contains(&count),
+            "Unison count must be between 1 and {}. Got {}",
+            MAX_UNISON,
+            count
+        );
+        self.count = count;
+        // The oscillators that aren't played anymore won't finish their fades
+        for osc in self.oscs[count..].iter_mut() {
+            osc.end_fade();
+        }
+        self.update();
+    }
+
+    /** Replaces the oscillators' wavetable
+
+    The oscillators that are being played crossfade to the new table, and the rest switch to it right away. See
+    [`Phasor::set_table`].
+
+    # Arguments
+
+    * `table`: The new wavetable
+    * `fade`:  The length of the crossfade (in samples)
+    */
+    pub fn set_table(&mut self, table: &Arc<Wavetable>, fade: usize) {
+        for (k, osc) in self.oscs.iter_mut().enumerate() {
+            osc.set_table(table, if k < self.count { fade } else { 0 });
+        }
+    }
+
+    /** Ends the oscillators' crossfades from a previous table and lets go of that table
+     */
+    pub fn end_fade(&mut self) {
+        for osc in self.oscs.iter_mut() {
+            osc.end_fade();
+        }
+    }
+
+    /** Sets the detune amount
+
+    # Arguments
+
+    * `cents`: How far the outermost oscillators are detuned from the center pitch (in cents)
+    */
+    pub fn set_detune(&mut self, cents: f32) {
+        self.detune = cents;
+        self.update();
+    }
+
+    /** Sets the detune curve
+
+    # Arguments
+
+    * `curve`: The exponent that shapes the spread of the detune. This must be greater than 0.0.
+    */
+    pub fn set_curve(&mut self, curve: f32) {
+        self.curve = curve;
+        self.update();
+    }
+
+    /** Sets the blend between the center and side oscillators
+
+    # Arguments
+
+    * `blend`: The blend, from only the center oscillator (0.0) to only the side oscillators (1.0). Stacks of one or
+               two oscillators have no side oscillators, so the blend doesn't affect them.
+    */
+    pub fn set_blend(&mut self, blend: f32) {
+        self.blend = blend.clamp(0.0, 1.0);
+        self.update();
+    }
+
+    /** Sets the stereo spread
+
+    # Arguments
+
+    * `spread`: The stereo width, from mono (0.0) to the outermost oscillators panned hard left and right (1.0)
+    */
+    pub fn set_spread(&mut self, spread: f32) {
+        self.spread = spread.clamp(0.0, 1.0);
+        self.update();
+    }
+
+    /** Sets whether each oscillator starts a note at a random phase
+
+    If this is false, then all of the oscillators start at a phase of zero.
+    */
+    pub fn set_random_phase(&mut self, random: bool) {
+        self.random_phase = random;
+    }
+
+    /** Resets the oscillators' phases for a new note
+     */
+    pub fn reset(&mut self) {
+        for osc in self.oscs.iter_mut() {
+            if self.random_phase {
+                osc.set_phase(self.rng.gen_range(0.0..TAU));
+            } else {
+                osc.zero();
+            }
+        }
+    }
+
+    /** Performs the oscillators and mixes them down to a single channel
+
+    # Arguments
+
+    * `outbuf`: A buffer for storing the output waveform
+    * `freq`:   The center frequency (in Hz)
+    */
+    pub fn perform(&mut self, outbuf: &mut [f32], freq: f32) {
+        outbuf.fill(0.0);
+        let chunklen = self.buffer.len();
+        for chunk in outbuf.chunks_mut(chunklen) {
+            for k in 0..self.count {
+                let buffer = &mut self.buffer[..chunk.len()];
+                self.oscs[k].perform(buffer, freq * self.ratios[k], 0.0);
+                let gain = self.gains[k][0];
+                for (out, v) in chunk.iter_mut().zip(buffer.iter()) {
+                    *out += gain * v;
+                }
+            }
+        }
+    }
+
+    /** Performs the oscillators and mixes them down to two channels, panned by the stereo spread
+
+    # Arguments
+
+    * `left`:  A buffer for storing the left channel
+    * `right`: A buffer for storing the right channel. This must be the same length as `left`.
+    * `freq`:  The center frequency (in Hz)
+
+    # Panics
+
+    This function will panic if the `right` buffer length is shorter than the `left` length.
+    */
+    pub fn perform_stereo(&mut self, left: &mut [f32], right: &mut [f32], freq: f32) {
+        left.fill(0.0);
+        right[..left.len()].fill(0.0);
+        let chunklen = self.buffer.len();
+        for (lchunk, rchunk) in left.chunks_mut(chunklen).zip(right.chunks_mut(chunklen)) {
+            for k in 0..self.count {
+                let buffer = &mut self.buffer[..lchunk.len()];
+                self.oscs[k].perform(buffer, freq * self.ratios[k], 0.0);
+                let [_, lgain, rgain] = self.gains[k];
+                for ((l, r), v) in lchunk.iter_mut().zip(rchunk.iter_mut()).zip(buffer.iter()) {
+                    *l += lgain * v;
+                    *r += rgain * v;
+                }
+            }
+        }
+    }
+
+    /* Recalculates the oscillators' frequency ratios and gains
+     */
+    fn update(&mut self) {
+        let count = self.count;
+        let ncenter = if count % 2 == 0 { 2 } else { 1 };
+        let nside = count - ncenter;
+        // Without any side oscillators to blend with, the center ones are heard at full level
+        let (center_gain, side_gain) = if nside > 0 {
+            (
+                (1.0 - self.blend) / (ncenter as f32).sqrt(),
+                self.blend / (nside as f32).sqrt(),
+            )
+        } else {
+            (1.0 / (ncenter as f32).sqrt(), 0.0)
+        };
+        for k in 0..count {
+            // The oscillator's position in the stack, in a range of [-1, 1]
+            let x = if count > 1 {
+                2.0 * k as f32 / (count - 1) as f32 - 1.0
+            } else {
+                0.0
+            };
+            let offset = x.signum() * x.abs().powf(self.curve);
+            self.ratios[k] = (self.detune * offset / 1200.0).exp2();
+
+            let center = if count % 2 == 1 {
+                k * 2 + 1 == count
+            } else {
+                k + 1 == count / 2 || k == count / 2
+            };
+            let gain = if center { center_gain } else { side_gain };
+            // Equal-power pan, where 0 is hard left and 1 is hard right
+            let pan = (0.5 + 0.5 * self.spread * offset) * FRAC_PI_2;
+            self.gains[k] = [
+                gain,
+                gain * pan.cos() * 2f32.sqrt(),
+                gain * pan.sin() * 2f32.sqrt(),
+            ];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::system::System;
+    use super::super::wt::{Phasor, Wavetable};
+    use super::Unison;
+    use float_cmp::approx_eq;
+    use std::f32::consts::PI;
+    use std::sync::Arc;
+
+    fn generate_sine(len: usize) -> Vec<f32> {
+        Vec::from_iter((0..len).map(|i| (2.0 * PI * i as f32 / len as f32).sin()))
+    }
+
+    #[test]
+    fn test_single_unison() {
+        //! A single oscillator with no random phase should match a plain phasor
+        let system = Arc::new(System::new(48000.0, 1, 256));
+        let wt = Arc::new(Wavetable::new(&generate_sine(1024)));
+        let mut unison = Unison::new(&system, &wt, 1);
+        unison.set_random_phase(false);
+        unison.set_detune(50.0);
+        unison.set_blend(0.0);
+        unison.reset();
+        let mut phasor = Phasor::new(&system, &wt);
+
+        let mut outbuf = [0.0; 256];
+        let mut expected = [0.0; 256];
+        unison.perform(&mut outbuf, 440.0);
+        phasor.perform(&mut expected, 440.0, 0.0);
+        for (i, (v, e)) in outbuf.iter().zip(expected).enumerate() {
+            assert!(
+                approx_eq!(f32, *v, e, epsilon = 1e-6),
+                "out[{}] = {}, expected: {}",
+                i,
+                *v,
+                e
+            );
+        }
+    }
+
+    #[test]
+    fn test_unison_no_sides() {
+        //! A stack of two only has center oscillators, so it's heard at full level whatever the blend
+        let system = Arc::new(System::new(48000.0, 1, 256));
+        let wt = Arc::new(Wavetable::new(&generate_sine(1024)));
+        let mut unison = Unison::new(&system, &wt, 2);
+        unison.set_random_phase(false);
+        unison.set_detune(0.0);
+        unison.set_blend(1.0);
+        unison.reset();
+        for g in unison.gains[..2].iter() {
+            assert!(
+                approx_eq!(f32, g[0], 0.5f32.sqrt(), epsilon = 1e-6),
+                "gain = {}",
+                g[0]
+            );
+        }
+
+        // Two oscillators in phase sum to sqrt(2) times a single one
+        let mut phasor = Phasor::new(&system, &wt);
+        let mut outbuf = [0.0; 256];
+        let mut expected = [0.0; 256];
+        unison.perform(&mut outbuf, 440.0);
+        phasor.perform(&mut expected, 440.0, 0.0);
+        for (i, (v, e)) in outbuf.iter().zip(expected).enumerate() {
+            assert!(
+                approx_eq!(f32, *v, 2f32.sqrt() * e, epsilon = 1e-5),
+                "out[{}] = {}, expected: {}",
+                i,
+                *v,
+                2f32.sqrt() * e
+            );
+        }
+    }
+
+    #[test]
+    fn test_unison_detune() {
+        //! The oscillators should be spread across the detune range, shaped by the curve
+        let system = Arc::new(System::new(48000.0, 1, 256));
+        let wt = Arc::new(Wavetable::new(&generate_sine(1024)));
+        let mut unison = Unison::new(&system, &wt, 5);
+        unison.set_detune(100.0);
+        let semitone = 2f32.powf(1.0 / 12.0);
+        let expected = [
+            1.0 / semitone,
+            1.0 / semitone.sqrt(),
+            1.0,
+            semitone.sqrt(),
+            semitone,
+        ];
+        for (r, e) in unison.ratios[..5].iter().zip(expected) {
+            assert!(
+                approx_eq!(f32, *r, e, epsilon = 1e-6),
+                "ratio = {}, expected: {}",
+                r,
+                e
+            );
+        }
+
+        unison.set_curve(2.0);
+        let expected = [
+            1.0 / semitone,
+            1.0 / semitone.powf(0.25),
+            1.0,
+            semitone.powf(0.25),
+            semitone,
+        ];
+        for (r, e) in unison.ratios[..5].iter().zip(expected) {
+            assert!(
+                approx_eq!(f32, *r, e, epsilon = 1e-6),
+                "ratio = {}, expected: {}",
+                r,
+                e
+            );
+        }
+    }
+
+    #[test]
+    fn test_unison_blend_and_spread() {
+        let system = Arc::new(System::new(48000.0, 1, 256));
+        let wt = Arc::new(Wavetable::new(&generate_sine(1024)));
+
+        // Only the middle two oscillators of an even stack are heard with a blend of 0
+        let mut unison = Unison::new(&system, &wt, 4);
+        unison.set_blend(0.0);
+        for (g, e) in unison.gains[..4]
+            .iter()
+            .zip([0.0, 0.5f32.sqrt(), 0.5f32.sqrt(), 0.0])
+        {
+            assert!(
+                approx_eq!(f32, g[0], e, epsilon = 1e-6),
+                "gain = {}, expected: {}",
+                g[0],
+                e
+            );
+        }
+
+        // A full spread pans the outermost oscillators hard left and right
+        unison.set_blend(1.0);
+        unison.set_spread(1.0);
+        let [_, l, r] = unison.gains[0];
+        assert!(approx_eq!(f32, r, 0.0, epsilon = 1e-6), "right gain: {}", r);
+        assert!(l > 0.0, "left gain: {}", l);
+
+        let mut left = [0.0; 300];
+        let mut right = [0.0; 300];
+        unison.perform_stereo(&mut left, &mut right, 440.0);
+        assert!(left.iter().any(|v| *v != 0.0));
+        assert!(left.iter().zip(right).any(|(l, r)| *l != r));
+    }
+}
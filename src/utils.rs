@@ -1,70 +1,421 @@
 use num::FromPrimitive;
 use rustfft::{num_complex::Complex, FftPlanner};
-use sndfile_sys as sndfile;
-use sndfile_sys::{sf_count_t, SFM_READ, SF_INFO, SNDFILE};
 use std::cmp::Ordering::Equal;
-use std::ffi::{CStr, CString};
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom};
 
-/** Reads an audio file and returns the audio in it as a vector
+#[cfg(feature = "sndfile")]
+mod libsndfile;
+#[cfg(feature = "pure-rust")]
+mod native;
 
-If the audio file has multiple tracks then these tracks are mixed together into a single track
+/** Selects which channels [`read_audio`] returns
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channels {
+    /// Average all of the channels into a single channel
+    Mix,
+    /// Only return the channel with the given index, starting at 0
+    Select(usize),
+    /// Return every channel, interleaved frame by frame
+    All,
+}
+
+/** The direction that a sample loop plays in
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoopMode {
+    Forward,
+    Backward,
+    Alternating,
+}
+
+/** A sample loop, as stored in a file's `smpl` chunk (or an AIFF file's instrument chunk)
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Loop {
+    /// The first frame of the loop
+    pub start: usize,
+    /// The frame just after the end of the loop
+    pub end: usize,
+    pub mode: LoopMode,
+}
+
+/** Everything that [`read_audio`] learns about an audio file besides its samples
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AudioInfo {
+    /// The sample rate, in Hz
+    pub samplerate: i32,
+    /// The number of channels in the file, whichever ones were read
+    pub channels: usize,
+    /// The number of frames in the file
+    pub frames: usize,
+    /// The container format, or None if it's one that [`write_sndfile`] can't write
+    pub container: Option<Container>,
+    /// The sample format, or None if it's one that [`write_sndfile`] can't write
+    pub depth: Option<BitDepth>,
+    /// The MIDI note that the recording plays at its original speed, if the file names one
+    pub root_key: Option<u8>,
+    /// The sample loops that the file defines
+    pub loops: Vec<Loop>,
+}
+
+/** Reads every frame of an audio file, along with what the file says about itself
+
+Nothing is thrown away, so callers can decide what to keep. The root key and loop points come from the file's `smpl` and
+`inst` chunks (or the equivalent chunks in other formats), when it has them.
+
+Files are decoded by libsndfile with the `sndfile` feature (which is on by default), or in pure Rust with the `pure-rust`
+feature, which reads WAV, AIFF and FLAC files without linking to any C libraries. If both features are on, then the pure
+Rust decoder is tried first, and libsndfile reads anything that it can't.
 
 # Arguments
 
-* `path`: The path to the audio file
+* `path`:     The path to the audio file
+* `channels`: Which channels to return
+
+# Errors
+
+Returns an error if the file can't be read, or if `channels` selects a channel that the file doesn't have.
+*/
+pub fn read_audio(path: &str, channels: Channels) -> Result<(Vec<f32>, AudioInfo), std::io::Error> {
+    let (table, info) = decode(path)?;
+    select_channels(table, info, channels, path)
+}
+
+/** Reads every frame of an audio file from a reader, along with what the file says about itself
+
+This works just like [`read_audio`], but the file can come from anywhere, like an archive or a socket. The reader must
+hold the whole file, starting at its beginning.
+
+# Arguments
+
+* `reader`:   The reader that holds the audio file
+* `channels`: Which channels to return
+
+# Errors
+
+Returns an error if the audio can't be read, or if `channels` selects a channel that the audio doesn't have.
+*/
+pub fn read_audio_from<R: Read + Seek>(
+    mut reader: R,
+    channels: Channels,
+) -> Result<(Vec<f32>, AudioInfo), std::io::Error> {
+    let (table, info) = decode_reader(&mut reader)?;
+    select_channels(table, info, channels, READER_NAME)
+}
+
+/** Reads every frame of an audio file that's held in memory, along with what the file says about itself
+
+This works just like [`read_audio`], but the file can be embedded in the program with `include_bytes!`.
+
+# Examples
+
+```no_run
+# use wavetable::utils::{read_audio_bytes, Channels};
+let (audio, info) = read_audio_bytes(include_bytes!("../test/saw.wav"), Channels::Mix).unwrap();
+assert_eq!(audio.len(), info.frames);
+```
 */
-pub fn read_sndfile(path: &str) -> Result<(Vec<f32>, i32), std::io::Error> {
-    let mut info = SF_INFO {
-        frames: 0,
-        samplerate: 0,
-        channels: 0,
-        format: 0,
-        sections: 0,
-        seekable: 0,
+pub fn read_audio_bytes(
+    data: &[u8],
+    channels: Channels,
+) -> Result<(Vec<f32>, AudioInfo), std::io::Error> {
+    read_audio_from(Cursor::new(data), channels)
+}
+
+/* What the audio is called in the errors for audio that doesn't come from a file
+ */
+const READER_NAME: &str = "the audio data";
+
+/* Anything that audio can be read from
+ */
+trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek + ?Sized> ReadSeek for T {}
+
+/* Picks the channels out of the interleaved audio that the backends decode
+ */
+fn select_channels(
+    table: Vec<f32>,
+    info: AudioInfo,
+    channels: Channels,
+    name: &str,
+) -> Result<(Vec<f32>, AudioInfo), std::io::Error> {
+    let chans = info.channels;
+    if let Channels::Select(channel) = channels {
+        if channel >= chans {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!(
+                    "Unable to read channel {} of {}, which only has {}",
+                    channel, name, chans
+                ),
+            ));
+        }
+    }
+
+    let table = match channels {
+        _ if chans <= 1 => table,
+        Channels::All => table,
+        // Mix all channels down to a single channel by averaging them
+        Channels::Mix => Vec::from_iter(
+            table
+                .chunks(chans)
+                .map(|frame| frame.iter().map(|v| v / chans as f32).sum()),
+        ),
+        Channels::Select(channel) => {
+            Vec::from_iter(table.chunks(chans).map(|frame| frame[channel]))
+        }
     };
-    let c_path = CString::new(path).unwrap();
-    let sf: *mut SNDFILE =
-        unsafe { sndfile::sf_open(c_path.as_ptr() as *mut _, SFM_READ, &mut info) };
-    if sf as usize == 0 {
-        let reason_pchar = unsafe { sndfile::sf_strerror(sf) };
-        let reason = unsafe { CStr::from_ptr(reason_pchar).to_str().unwrap() };
-        return Err(std::io::Error::new(
+    Ok((table, info))
+}
+
+/* Reads every channel of an audio file, interleaved, with the backends that the crate was built with. When both are
+ * available, the pure Rust decoder gets the first try, and libsndfile reads whatever it can't.
+ */
+#[cfg(all(feature = "pure-rust", feature = "sndfile"))]
+fn decode(path: &str) -> Result<(Vec<f32>, AudioInfo), std::io::Error> {
+    native::decode(path).or_else(|_| libsndfile::decode(path))
+}
+
+#[cfg(all(feature = "pure-rust", feature = "sndfile"))]
+fn decode_reader(reader: &mut dyn ReadSeek) -> Result<(Vec<f32>, AudioInfo), std::io::Error> {
+    native::decode_reader(reader, READER_NAME)
+        .or_else(|_| libsndfile::decode_reader(reader, READER_NAME))
+}
+
+#[cfg(all(feature = "pure-rust", not(feature = "sndfile")))]
+fn decode(path: &str) -> Result<(Vec<f32>, AudioInfo), std::io::Error> {
+    native::decode(path)
+}
+
+#[cfg(all(feature = "pure-rust", not(feature = "sndfile")))]
+fn decode_reader(reader: &mut dyn ReadSeek) -> Result<(Vec<f32>, AudioInfo), std::io::Error> {
+    native::decode_reader(reader, READER_NAME)
+}
+
+#[cfg(all(feature = "sndfile", not(feature = "pure-rust")))]
+fn decode(path: &str) -> Result<(Vec<f32>, AudioInfo), std::io::Error> {
+    libsndfile::decode(path)
+}
+
+#[cfg(all(feature = "sndfile", not(feature = "pure-rust")))]
+fn decode_reader(reader: &mut dyn ReadSeek) -> Result<(Vec<f32>, AudioInfo), std::io::Error> {
+    libsndfile::decode_reader(reader, READER_NAME)
+}
+
+#[cfg(not(any(feature = "pure-rust", feature = "sndfile")))]
+fn decode(path: &str) -> Result<(Vec<f32>, AudioInfo), std::io::Error> {
+    Err(no_decoder(path))
+}
+
+#[cfg(not(any(feature = "pure-rust", feature = "sndfile")))]
+fn decode_reader(_: &mut dyn ReadSeek) -> Result<(Vec<f32>, AudioInfo), std::io::Error> {
+    Err(no_decoder(READER_NAME))
+}
+
+#[cfg(not(any(feature = "pure-rust", feature = "sndfile")))]
+fn no_decoder(name: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Other,
+        format!(
+            "Unable to open {}. The wavetable crate was built without an audio decoder (enable the \"pure-rust\" or \"sndfile\" feature)",
+            name
+        ),
+    )
+}
+
+/* The most of a `clm ` chunk that is read. Serum's chunks are only a few dozen bytes long.
+ */
+const CLM_MAX_READ: usize = 256;
+
+/** Reads the frame length of a wavetable WAV file from its `clm ` chunk
+
+Wavetable synthesizers like Serum save multi-frame tables as WAV files with a `clm ` chunk, which holds a string that
+starts with `<!>` followed by the number of samples in each frame (like `<!>2048 01000000 wavetable
+(www.xferrecords.com)`).
+
+Returns None if the file isn't a WAV file or it doesn't have a `clm ` chunk.
+
+# Arguments
+
+* `path`: The path to the audio file
+*/
+pub fn read_clm_frame_len(path: &str) -> Result<Option<usize>, std::io::Error> {
+    let mut file = File::open(path).map_err(|e| {
+        std::io::Error::new(
             std::io::ErrorKind::Other,
-            format!("Unable to open {}. {}", path, reason),
-        ));
+            format!("Unable to open {}. {}", path, e),
+        )
+    })?;
+    let mut header = [0u8; 12];
+    if file.read_exact(&mut header).is_err()
+        || &header[0..4] != b"RIFF"
+        || &header[8..12] != b"WAVE"
+    {
+        return Ok(None);
     }
 
-    let tablelen = (info.frames as f32).log2().floor().exp2() as usize;
-    let mut table = Vec::<f32>::with_capacity(tablelen * info.channels as usize);
-    let count = unsafe { sndfile::sf_readf_float(sf, table.as_mut_ptr(), tablelen as sf_count_t) };
-    unsafe { sndfile::sf_close(sf) };
+    let mut chunk = [0u8; 8];
+    while file.read_exact(&mut chunk).is_ok() {
+        let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as usize;
+        if &chunk[0..4] == b"clm " {
+            // The size comes from the file, so only read as much as a frame length could need
+            let mut data = Vec::with_capacity(size.min(CLM_MAX_READ));
+            (&mut file)
+                .take(CLM_MAX_READ as u64)
+                .read_to_end(&mut data)?;
+            data.truncate(size);
+            let text = String::from_utf8_lossy(&data);
+            let digits = text.strip_prefix("<!>").map(|rest| {
+                rest.split(|c: char| !c.is_ascii_digit())
+                    .next()
+                    .unwrap_or("")
+            });
+            return match digits.and_then(|digits| digits.parse::<usize>().ok()) {
+                Some(frame_len) if frame_len > 0 => Ok(Some(frame_len)),
+                _ => Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    format!(
+                        "Malformed clm chunk in {}: \"{}\"",
+                        path,
+                        text.trim_end_matches('\0')
+                    ),
+                )),
+            };
+        }
+        // Chunks are padded to an even length
+        file.seek(SeekFrom::Current((size + (size & 1)) as i64))?;
+    }
+    Ok(None)
+}
+
+/** The container formats that [`write_sndfile`] can write (and that [`read_audio`] recognizes)
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Container {
+    Wav,
+    Aiff,
+    Flac,
+    Caf,
+}
+
+/** The sample formats that [`write_sndfile`] can write (and that [`read_audio`] recognizes)
+
+FLAC can't hold 32-bit or floating point samples.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitDepth {
+    Pcm16,
+    Pcm24,
+    Pcm32,
+    Float,
+}
+
+/** Writes a single track of audio to an audio file
+
+Samples outside of the range of [-1, 1] are clipped when they're written as integers. Files are written by libsndfile, so
+this requires the `sndfile` feature.
+
+# Arguments
+
+* `path`:       The path to the audio file. If it already exists, it's overwritten.
+* `table`:      The audio to write
+* `samplerate`: The sample rate to store in the file
+* `container`:  The container format of the file
+* `depth`:      The sample format of the file
+
+# Errors
+
+Returns an error if the file can't be written, or if the container can't hold samples of the given depth.
+*/
+#[cfg_attr(not(feature = "sndfile"), allow(unused_variables))]
+pub fn write_sndfile(
+    path: &str,
+    table: &[f32],
+    samplerate: i32,
+    container: Container,
+    depth: BitDepth,
+) -> Result<(), std::io::Error> {
+    #[cfg(feature = "sndfile")]
+    return libsndfile::encode(path, table, samplerate, container, depth);
+
+    #[cfg(not(feature = "sndfile"))]
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        format!(
+            "Unable to write {}. Writing audio files requires the \"sndfile\" feature",
+            path
+        ),
+    ))
+}
+
+/** Adds a `clm ` chunk to a WAV file, which names the length of each of the wavetable frames that it holds
+
+This is the counterpart of [`read_clm_frame_len`]. The chunk is written in the same form that Serum uses, just before
+the file's `data` chunk. Any `clm ` chunk that the file already has is replaced.
+
+# Arguments
+
+* `path`:      The path to the WAV file
+* `frame_len`: The number of samples in each frame
 
-    if count as usize != tablelen {
+# Errors
+
+Returns an error if the file can't be read or written, or if it isn't a WAV file.
+*/
+pub fn write_clm_frame_len(path: &str, frame_len: usize) -> Result<(), std::io::Error> {
+    let data = std::fs::read(path).map_err(|e| {
+        std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("Unable to open {}. {}", path, e),
+        )
+    })?;
+    if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
         return Err(std::io::Error::new(
             std::io::ErrorKind::Other,
-            format!(
-                "Read fewer frames than expected. Expected {}, got {}",
-                tablelen, count
-            ),
+            format!("{} is not a WAV file", path),
         ));
     }
 
-    // Tell table how many values it's holding
-    unsafe { table.set_len(tablelen * info.channels as usize) };
+    let mut text =
+        format!("<!>{} 01000000 wavetable (www.xferrecords.com)", frame_len).into_bytes();
+    if text.len() % 2 == 1 {
+        text.push(0);
+    }
+    let mut clm = Vec::from(&b"clm "[..]);
+    clm.extend_from_slice(&(text.len() as u32).to_le_bytes());
+    clm.extend_from_slice(&text);
 
-    // Mix all channels down to a single channel by averaging them
-    if info.channels > 1 {
-        let chans = info.channels as usize;
-        for i in 0..tablelen {
-            let mut mixed = 0.0f32;
-            for c in 0..chans {
-                mixed += table[(i * chans) + c] / (chans as f32);
+    // Copy every chunk except an old clm chunk, putting the new one in front of the data
+    let mut out = Vec::with_capacity(data.len() + clm.len());
+    out.extend_from_slice(&data[..12]);
+    let mut pos = 12;
+    while pos + 8 <= data.len() {
+        let size = u32::from_le_bytes([data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]])
+            as usize;
+        let end = (pos + 8 + size + (size & 1)).min(data.len());
+        match &data[pos..pos + 4] {
+            b"clm " => (),
+            b"data" => {
+                out.extend_from_slice(&clm);
+                out.extend_from_slice(&data[pos..end]);
             }
-            table[i] = mixed;
+            _ => out.extend_from_slice(&data[pos..end]),
         }
-        table.truncate(tablelen);
+        pos = end;
     }
-    Ok((table, info.samplerate))
+    let riff_size = (out.len() - 8) as u32;
+    out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+
+    std::fs::write(path, out).map_err(|e| {
+        std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("Unable to write {}. {}", path, e),
+        )
+    })
 }
 
 /** Finds all of the frequency peaks in an audio buffer and returns them
@@ -111,8 +462,8 @@ fn frequency_peaks(buffer: &[f32], threshold: f32) -> Vec<(f32, f32)> {
 
 /** Returns the fundamental frequency in the given audio buffer
 
-TODO: The fundamental is defined as the highest-amplitude frequency. It may turn out that this is a flawed approach
-for some signals, in which case a more complex algorithm should be used.
+The fundamental is defined as the highest-amplitude frequency, so this fails on sounds with a weak fundamental, and it's
+only as accurate as the width of an FFT bin. [`detect_pitch`] is more robust, and says how sure it is.
 */
 pub fn fundamental(buffer: &[f32]) -> Option<f32> {
     let min_harm = 1e-4; // Don't return DC. 1e-4 is about 20Hz for fs of 192kHz
@@ -147,11 +498,34 @@ pub fn rms(buffer: &[f32]) -> f32 {
 
 /** Returns the best (ie, loudest) cycle of the fundamental frequency in the given audio buffer
 
+The pitch is found with [`PitchMethod::Yin`], and buffers that it isn't confident about (like noise) are refused. See
+[`best_waveform_with`] to choose the method and the confidence.
+
+The cycle is a whole number of samples, so unless the period happens to be one too, it's a little too short or too long,
+and it clicks where it wraps around. [`extract_cycle`] is more accurate.
+
 # Returns
 A slice of the buffer with the best single cycle if one is found, otherwise, None
 */
 pub fn best_waveform(buffer: &[f32]) -> Option<&[f32]> {
-    let fund = fundamental(buffer)?;
+    best_waveform_with(buffer, PitchMethod::Yin, MIN_CONFIDENCE)
+}
+
+/** Returns the best (ie, loudest) cycle of the fundamental frequency in the given audio buffer, using the given pitch
+detector
+
+# Arguments
+
+* `buffer`:     The audio
+* `method`:     How to find the pitch of the audio (see [`detect_pitch`])
+* `confidence`: The least confidence in the pitch that's accepted, from 0 to 1
+
+# Returns
+A slice of the buffer with the best single cycle if one is found, otherwise, None
+*/
+pub fn best_waveform_with(buffer: &[f32], method: PitchMethod, confidence: f32) -> Option<&[f32]> {
+    let pitch = detect_pitch(buffer, 1.0, method).filter(|p| p.confidence >= confidence)?;
+    let fund = pitch.frequency;
     let spc = (1.0 / fund).round() as usize;
 
     println!("Fundamental: {} cps", fund);
@@ -181,6 +555,618 @@ pub fn best_waveform(buffer: &[f32]) -> Option<&[f32]> {
     }
 }
 
+/** How [`extract_cycle`] joins the end of a cycle to its start
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Seam {
+    /// Leaves the cycle as it was in the buffer
+    Off,
+    /// Crossfades the given fraction (from 0 to 1) of the end of the cycle into the audio that led up to its start, so
+    /// that the end flows into the start just like the audio did. This keeps the shape of the cycle, and is the best
+    /// choice for sounds whose level or timbre changes from one cycle to the next. A cycle that starts less than a
+    /// period into the buffer has no audio before it, so it's tapered instead.
+    Crossfade(f32),
+    /// Fades the given fraction (from 0 to 1) of each end of the cycle in from (and out to) silence. This always wraps
+    /// seamlessly, but it changes the shape of the cycle at its ends.
+    Taper(f32),
+}
+
+/** A single cycle that was extracted by [`extract_cycle`]
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cycle {
+    /// The cycle, resampled to the length that was asked for
+    pub samples: Vec<f32>,
+    /// Where the cycle starts in the buffer, in samples (which may be fractional)
+    pub start: f32,
+    /// The length of the cycle in the buffer, in samples (which may be fractional)
+    pub period: f32,
+    /// How much the cycle breaks where it wraps from its end back to its start, relative to its peak. This is the larger
+    /// of the second differences of the samples on either side of the wrap (how far each one is from a straight line
+    /// through its neighbors), so it's near 0 for a cycle that wraps smoothly, and it grows with a jump or a kink.
+    pub discontinuity: f32,
+}
+
+/* How far the zero crossing at the end of a cycle can be from where the pitch puts it, relative to the period
+ */
+const CYCLE_TOLERANCE: f32 = 0.03;
+
+/** Extracts the best (ie, loudest) cycle of the fundamental frequency in the given audio buffer, with sub-sample accuracy
+
+This is a more accurate [`best_waveform`]. Rather than cutting a whole number of samples out of the buffer, it finds the
+period (which is rarely a whole number of samples), starts the cycle at a rising zero crossing between two samples, and
+resamples exactly one period, so the cycle ends just where the next one starts. The pitch is found with
+[`PitchMethod::Yin`], and buffers that it isn't confident about (like noise) are refused, just like [`best_waveform`].
+
+The cycle is taken where a rising zero crossing is followed by another one about a period later, but its length is
+always the detected period, so a sound whose pitch wavers still gives a cycle of its fundamental. Any break that's left
+where the cycle wraps can be smoothed over with the `seam`.
+
+# Arguments
+
+* `buffer`: The audio
+* `len`:    The length to resample the cycle to. If this is None, the cycle's length is its period, rounded to the
+            nearest sample.
+* `seam`:   How to join the end of the cycle to its start
+
+# Returns
+The cycle, or None if no pitch or cycle can be found in the buffer, or if the cycle would be shorter than 2 samples
+*/
+pub fn extract_cycle(buffer: &[f32], len: Option<usize>, seam: Seam) -> Option<Cycle> {
+    let pitch =
+        detect_pitch(buffer, 1.0, PitchMethod::Yin).filter(|p| p.confidence >= MIN_CONFIDENCE)?;
+    let period = 1.0 / pitch.frequency;
+
+    // The rising zero crossings, placed between the samples on either side of them
+    let crossings = Vec::from_iter((1..buffer.len()).filter_map(|i| {
+        let (y0, y1) = (buffer[i - 1], buffer[i]);
+        if y0 < 0.0 && y1 >= 0.0 {
+            Some((i - 1) as f32 + y0 / (y0 - y1))
+        } else {
+            None
+        }
+    }));
+
+    // Pair each crossing with the one nearest to a period after it, and take the loudest pair. The pair only picks where
+    // the cycle is; its length is the period itself.
+    let mut best_rms = 0.0;
+    let mut best = None;
+    for (j, start) in crossings.iter().enumerate() {
+        let target = start + period;
+        let next = j + 1 + crossings[j + 1..].partition_point(|c| *c < target);
+        let end = [next - 1, next]
+            .into_iter()
+            .filter(|k| *k > j && *k < crossings.len())
+            .map(|k| crossings[k])
+            .min_by(|a, b| (a - target).abs().total_cmp(&(b - target).abs()));
+        let end = match end {
+            Some(end) if (end - target).abs() <= CYCLE_TOLERANCE * period => end,
+            _ => continue,
+        };
+        let level =
+            rms(&buffer[start.ceil() as usize..(end.floor() as usize + 1).min(buffer.len())]);
+        if level > best_rms {
+            best = Some(*start);
+            best_rms = level;
+        }
+    }
+    let start = best?;
+
+    let len = len.unwrap_or(period.round() as usize);
+    if len < 2 {
+        return None;
+    }
+    let step = period / len as f32;
+    let mut samples = Vec::from_iter((0..len).map(|i| cubic_at(buffer, start + i as f32 * step)));
+
+    // A cycle that starts less than a period into the buffer has nothing before it to crossfade into
+    let seam = match seam {
+        Seam::Crossfade(fraction) if start < period => Seam::Taper(fraction),
+        seam => seam,
+    };
+    match seam {
+        Seam::Off => (),
+        Seam::Crossfade(fraction) => {
+            // The end fades into the period before it, which leads right into the start
+            let fade = ((fraction.clamp(0.0, 1.0) * len as f32).round() as usize).min(len - 1);
+            for (n, i) in (len - fade..len).enumerate() {
+                let mix = (n + 1) as f32 / (fade + 1) as f32;
+                let before = cubic_at(buffer, start + i as f32 * step - period);
+                samples[i] += mix * (before - samples[i]);
+            }
+        }
+        Seam::Taper(fraction) => {
+            let fade = ((fraction.clamp(0.0, 1.0) * len as f32).round() as usize).min(len / 2);
+            for n in 0..fade {
+                let gain =
+                    0.5 - 0.5 * (std::f32::consts::PI * (n as f32 + 0.5) / fade as f32).cos();
+                samples[n] *= gain;
+                samples[len - 1 - n] *= gain;
+            }
+        }
+    }
+
+    // The second differences of the samples on either side of the wrap catch both jumps and kinks
+    let peak = samples.iter().fold(0.0f32, |max, v| max.max(v.abs()));
+    let before = samples[len - 2] - 2.0 * samples[len - 1] + samples[0];
+    let after = samples[len - 1] - 2.0 * samples[0] + samples[1 % len];
+    let discontinuity = if peak > 0.0 {
+        before.abs().max(after.abs()) / peak
+    } else {
+        0.0
+    };
+
+    Some(Cycle {
+        samples,
+        start,
+        period,
+        discontinuity,
+    })
+}
+
+/* Reads a buffer at a fractional position with 4-point cubic (Catmull-Rom) interpolation. Positions outside of the
+ * buffer are clamped to its ends.
+ */
+fn cubic_at(buffer: &[f32], pos: f32) -> f32 {
+    let last = buffer.len() as isize - 1;
+    let pos = pos.clamp(0.0, last as f32);
+    let index = pos.floor() as isize;
+    let x = pos - index as f32;
+    let at = |i: isize| buffer[i.clamp(0, last) as usize];
+    let (y0, y1, y2, y3) = (at(index - 1), at(index), at(index + 1), at(index + 2));
+    y1 + 0.5
+        * x
+        * (y2 - y0 + x * (2.0 * y0 - 5.0 * y1 + 4.0 * y2 - y3 + x * (3.0 * (y1 - y2) + y3 - y0)))
+}
+
+/* The range of periods (in samples) that slice_cycles looks for. At 44.1kHz, this is about 2.8kHz down to 21.5Hz.
+ */
+const MIN_PERIOD: usize = 16;
+const MAX_PERIOD: usize = 2048;
+
+/* The YIN threshold below which a dip in the normalized difference counts as a period
+ */
+const PERIOD_THRESHOLD: f32 = 0.15;
+
+/** Tracks the pitch across an audio buffer and slices it into evenly spaced single cycles
+
+This is the usual way to turn a recording into a morphing wavetable. The buffer's pitch is tracked with the YIN
+difference function, and the part of the buffer that has a pitch (ignoring silence or noise at either end) is split into
+`frames` evenly spaced points. The cycle that is centered on each point is resampled to `len` samples, so that every
+frame has the same length, regardless of how the pitch moves.
+
+The frames are then phase aligned: the first is rotated so that its fundamental starts like a sine, and each frame after
+that is rotated to line up best with the one before it. Scanning through the frames then reproduces how the timbre
+evolves in the recording, without the waveform sliding back and forth.
+
+# Arguments
+
+* `buffer`: The audio
+* `frames`: The number of cycles to slice out
+* `len`:    The length that each cycle is resampled to
+
+# Returns
+The cycles, one after another, or None if no pitch can be found in the buffer
+*/
+pub fn slice_cycles(buffer: &[f32], frames: usize, len: usize) -> Option<Vec<f32>> {
+    let max_period = MAX_PERIOD.min(buffer.len() / 4);
+    if frames == 0 || len == 0 || max_period <= MIN_PERIOD {
+        return None;
+    }
+
+    // Find the periods at hops across the whole buffer, and the span that has a pitch
+    let hop = max_period / 2;
+    let centers = Vec::from_iter((max_period..=buffer.len() - max_period).step_by(hop));
+    // Each hop's level is the level of the quietest quarter around it, so that the hops at the edges of the pitched
+    // part aren't centered on silence
+    let levels = Vec::from_iter(centers.iter().map(|center| {
+        buffer[center - hop / 2..center + hop / 2]
+            .chunks(hop / 4)
+            .map(rms)
+            .fold(f32::INFINITY, f32::min)
+    }));
+    // Hops that are more than 20dB below the loudest one are too quiet to slice
+    let floor = 0.1 * levels.iter().fold(0.0f32, |max, l| max.max(*l));
+    let periods = Vec::from_iter(centers.iter().zip(levels.iter()).map(|(center, level)| {
+        if *level < floor {
+            None
+        } else {
+            local_period(buffer, *center, max_period)
+        }
+    }));
+    let first = periods.iter().position(|p| p.is_some())?;
+    let last = periods.iter().rposition(|p| p.is_some())?;
+    let (start, end) = (centers[first], centers[last]);
+
+    let mut table = Vec::with_capacity(frames * len);
+    for k in 0..frames {
+        let center = if frames > 1 {
+            start + (k * (end - start) + (frames - 1) / 2) / (frames - 1)
+        } else {
+            (start + end) / 2
+        };
+        let period = local_period(buffer, center, max_period).unwrap_or_else(|| {
+            // Borrow the period from the nearest hop that has one
+            let hop = (center - max_period + hop / 2) / hop;
+            let nearest = (0..periods.len())
+                .filter(|i| periods[*i].is_some())
+                .min_by_key(|i| i.abs_diff(hop))
+                .unwrap();
+            periods[nearest].unwrap()
+        });
+        let mut cycle = read_cycle(buffer, center as f32 - period / 2.0, period, len);
+
+        let shift = match table.len().checked_sub(len) {
+            Some(prev) => best_rotation(&table[prev..], &cycle),
+            None => sine_rotation(&cycle),
+        };
+        cycle.rotate_left(shift);
+        table.extend(cycle);
+    }
+    Some(table)
+}
+
+/* Finds the period (in samples) of the waveform around the center of a window in a buffer, with YIN's cumulative mean
+ * normalized difference function. Returns None if the window doesn't seem to have a pitch.
+ */
+fn local_period(buffer: &[f32], center: usize, max_period: usize) -> Option<f32> {
+    let cmndf = cmndf(
+        &buffer[center - max_period..center + max_period],
+        max_period,
+    );
+    let tau = yin_dip(&cmndf, max_period, PERIOD_THRESHOLD)?;
+    if cmndf[tau] >= 2.0 * PERIOD_THRESHOLD {
+        return None;
+    }
+    Some(tau as f32 + dip_offset(cmndf[tau - 1], cmndf[tau], cmndf[tau + 1]))
+}
+
+/** The ways that [`detect_pitch`] can find the pitch of a sound
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PitchMethod {
+    /// The YIN algorithm, which looks for the first deep dip in the normalized difference between the sound and a
+    /// delayed copy of itself. The confidence is how deep the dip is.
+    Yin,
+    /// Probabilistic YIN (pYIN), which runs YIN with a whole distribution of thresholds and takes the period that most
+    /// of them agree on. The confidence is the share of thresholds that found a period. This is only the part of pYIN
+    /// that works on a single window, without the pitch tracking across windows.
+    ProbabilisticYin,
+    /// The peak of the normalized autocorrelation. The confidence is the correlation at the peak.
+    Autocorrelation,
+    /// The harmonic product spectrum, which finds the frequency whose harmonics are loudest all together, so it still
+    /// works when the fundamental is weak. The confidence is the normalized autocorrelation at the period it finds.
+    HarmonicProduct,
+}
+
+/** A pitch found by [`detect_pitch`]
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pitch {
+    /// The frequency of the pitch, in the units of the sample rate that was given to [`detect_pitch`]
+    pub frequency: f32,
+    /// How sure the detector is that the sound has this pitch, from 0 (it's unpitched) to 1
+    pub confidence: f32,
+}
+
+/* The least confidence that best_waveform accepts
+ */
+const MIN_CONFIDENCE: f32 = 0.5;
+
+/* The number of harmonics that the harmonic product spectrum multiplies together, and how much its FFT is zero padded
+ */
+const HPS_HARMONICS: usize = 5;
+const HPS_PADDING: usize = 4;
+
+/** Finds the pitch of a sound, along with how confident the detector is about it
+
+The buffer is split into overlapping windows that are each long enough to hold two of the longest periods that are looked
+for (2048 samples, which is about 21.5Hz at 44.1kHz). Windows that are more than 20dB quieter than the loudest one are
+ignored. The pitch of each window is found with `method`, and the pitch of the buffer is the median of them, weighted by
+their confidence. The confidence of the buffer is the average confidence of its windows, so a sound that's only pitched
+some of the time is less trusted than one that's pitched all of the time.
+
+# Arguments
+
+* `buffer`:     The audio
+* `samplerate`: The sample rate of the audio. The frequency is given in the same units, so a sample rate of 1 gives
+                the frequency in cycles per sample.
+* `method`:     How to find the pitch of each window
+
+# Returns
+The pitch, or None if the buffer is silent or too short to have a pitch (it needs at least 36 samples)
+*/
+pub fn detect_pitch(buffer: &[f32], samplerate: f32, method: PitchMethod) -> Option<Pitch> {
+    let max_period = MAX_PERIOD.min(buffer.len() / 2);
+    if max_period <= MIN_PERIOD + 1 {
+        return None;
+    }
+
+    let windows = Vec::from_iter(
+        (0..=buffer.len() - 2 * max_period)
+            .step_by(max_period)
+            .map(|start| &buffer[start..start + 2 * max_period]),
+    );
+    let levels = Vec::from_iter(windows.iter().map(|window| rms(window)));
+    let floor = 0.1 * levels.iter().fold(0.0f32, |max, l| max.max(*l));
+    if floor <= 0.0 {
+        return None;
+    }
+    let estimates = Vec::from_iter(
+        windows
+            .iter()
+            .zip(levels.iter())
+            .filter(|(_, level)| **level >= floor)
+            .map(|(window, _)| window_pitch(window, max_period, method)),
+    );
+    let confidence =
+        estimates.iter().flatten().map(|(_, c)| c).sum::<f32>() / estimates.len() as f32;
+
+    // Take the median period, weighted by confidence
+    let mut voiced = Vec::from_iter(estimates.into_iter().flatten().filter(|(_, c)| *c > 0.0));
+    voiced.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let total: f32 = voiced.iter().map(|(_, c)| c).sum();
+    let mut sum = 0.0;
+    let (period, _) = *voiced.iter().find(|(_, c)| {
+        sum += c;
+        sum >= total / 2.0
+    })?;
+
+    Some(Pitch {
+        frequency: samplerate / period,
+        confidence,
+    })
+}
+
+/* Finds the period (in samples) of a window of 2 * max_period samples and the confidence in it
+ */
+fn window_pitch(window: &[f32], max_period: usize, method: PitchMethod) -> Option<(f32, f32)> {
+    match method {
+        PitchMethod::Yin => {
+            let cmndf = cmndf(window, max_period);
+            let tau = yin_dip(&cmndf, max_period, PERIOD_THRESHOLD)?;
+            let period = tau as f32 + dip_offset(cmndf[tau - 1], cmndf[tau], cmndf[tau + 1]);
+            Some((period, (1.0 - cmndf[tau]).clamp(0.0, 1.0)))
+        }
+        PitchMethod::ProbabilisticYin => {
+            let cmndf = cmndf(window, max_period);
+            // Spread the thresholds from 0.01 to 1 with a beta(2, 18) distribution, which has a mean of 0.1
+            let thresholds = Vec::from_iter((1..=100).map(|i| i as f32 / 100.0));
+            let weights = Vec::from_iter(thresholds.iter().map(|s| s * (1.0 - s).powi(17)));
+            let norm: f32 = weights.iter().sum();
+            // Each threshold votes for the first dip below it
+            let mut votes = vec![0.0f32; max_period + 1];
+            for (threshold, weight) in thresholds.iter().zip(weights.iter()) {
+                if let Some(tau) = first_dip(&cmndf, max_period, *threshold) {
+                    votes[tau] += weight / norm;
+                }
+            }
+            let tau = (MIN_PERIOD..max_period)
+                .max_by(|a, b| votes[*a].total_cmp(&votes[*b]))
+                .filter(|tau| votes[*tau] > 0.0)?;
+            let period = tau as f32 + dip_offset(cmndf[tau - 1], cmndf[tau], cmndf[tau + 1]);
+            Some((period, votes.iter().sum::<f32>().min(1.0)))
+        }
+        PitchMethod::Autocorrelation => {
+            let nacf = nacf(window, max_period);
+            // Skip the lobe around a lag of zero, which is always the highest
+            let start = (MIN_PERIOD..max_period).find(|tau| nacf[*tau] <= nacf[*tau + 1])?;
+            let best = (start..max_period).fold(f32::NEG_INFINITY, |max, tau| max.max(nacf[tau]));
+            // Take the first peak that's nearly as high as the highest, so that a multiple of the period isn't chosen
+            let tau = (start.max(1)..max_period).find(|tau| {
+                nacf[*tau] >= 0.9 * best
+                    && nacf[*tau] >= nacf[*tau - 1]
+                    && nacf[*tau] >= nacf[*tau + 1]
+            })?;
+            // The peak is where the negated correlation dips
+            let period = tau as f32 + dip_offset(-nacf[tau - 1], -nacf[tau], -nacf[tau + 1]);
+            Some((period, nacf[tau].clamp(0.0, 1.0)))
+        }
+        PitchMethod::HarmonicProduct => {
+            let period = hps_period(window, max_period)?;
+            let nacf = nacf(window, max_period);
+            let tau = (period.floor() as usize).min(max_period - 1);
+            let confidence = linear_interp(period - tau as f32, nacf[tau], nacf[tau + 1]);
+            Some((period, confidence.clamp(0.0, 1.0)))
+        }
+    }
+}
+
+/* The terms of YIN's difference function for a window of 2 * max_period samples: the cross term,
+ * r(tau) = sum(x[j] * x[j + tau]) over the first max_period samples, for tau from 0 to max_period, and the running sum
+ * of the energy of the window
+ */
+fn lag_terms(window: &[f32], max_period: usize) -> (Vec<f64>, Vec<f64>) {
+    let fftlen = next_pow_of_2(2 * max_period);
+    let mut planner = FftPlanner::new();
+    let fft = planner.plan_fft_forward(fftlen);
+    let ifft = planner.plan_fft_inverse(fftlen);
+
+    // The cross term is a correlation of the first half of the window with the whole of it
+    let mut first = vec![Complex { re: 0.0, im: 0.0 }; fftlen];
+    let mut whole = vec![Complex { re: 0.0, im: 0.0 }; fftlen];
+    for (j, v) in window.iter().enumerate() {
+        if j < max_period {
+            first[j].re = *v;
+        }
+        whole[j].re = *v;
+    }
+    fft.process(&mut first);
+    fft.process(&mut whole);
+    for (f, x) in first.iter_mut().zip(whole.iter()) {
+        *f = f.conj() * x;
+    }
+    ifft.process(&mut first);
+    let norm = fftlen as f64;
+    let cross = Vec::from_iter(first[..=max_period].iter().map(|c| c.re as f64 / norm));
+
+    let mut energy = vec![0.0f64; window.len() + 1];
+    for (j, v) in window.iter().enumerate() {
+        energy[j + 1] = energy[j] + (*v as f64) * (*v as f64);
+    }
+    (cross, energy)
+}
+
+/* YIN's cumulative mean normalized difference function of a window of 2 * max_period samples, for lags from 0 to
+ * max_period
+ */
+fn cmndf(window: &[f32], max_period: usize) -> Vec<f32> {
+    let (cross, energy) = lag_terms(window, max_period);
+    // d(tau) = sum(x[j]^2) + sum(x[j + tau]^2) - 2 * r(tau), with the energies taken from running sums
+    let mut cmndf = vec![1.0f32; max_period + 1];
+    let mut sum = 0.0;
+    for tau in 1..=max_period {
+        let d = energy[max_period] + (energy[tau + max_period] - energy[tau]) - 2.0 * cross[tau];
+        sum += d;
+        cmndf[tau] = if sum > 0.0 {
+            (d * tau as f64 / sum) as f32
+        } else {
+            1.0
+        };
+    }
+    cmndf
+}
+
+/* The normalized autocorrelation of a window of 2 * max_period samples, for lags from 0 to max_period
+ */
+fn nacf(window: &[f32], max_period: usize) -> Vec<f32> {
+    let (cross, energy) = lag_terms(window, max_period);
+    Vec::from_iter((0..=max_period).map(|tau| {
+        let power = energy[max_period] * (energy[tau + max_period] - energy[tau]);
+        if power > 0.0 {
+            (cross[tau] / power.sqrt()) as f32
+        } else {
+            0.0
+        }
+    }))
+}
+
+/* Returns the lag of the first dip in a CMNDF that goes below the threshold, or failing that, of the deepest dip
+ */
+fn yin_dip(cmndf: &[f32], max_period: usize, threshold: f32) -> Option<usize> {
+    first_dip(cmndf, max_period, threshold)
+        .or_else(|| (MIN_PERIOD..max_period).min_by(|a, b| cmndf[*a].total_cmp(&cmndf[*b])))
+}
+
+/* Returns the lag of the bottom of the first dip in a CMNDF that goes below the threshold
+ */
+fn first_dip(cmndf: &[f32], max_period: usize, threshold: f32) -> Option<usize> {
+    let mut tau = (MIN_PERIOD..max_period).find(|tau| cmndf[*tau] < threshold)?;
+    while tau + 1 < max_period && cmndf[tau + 1] < cmndf[tau] {
+        tau += 1;
+    }
+    Some(tau)
+}
+
+/* Returns how far the bottom of a parabola through three points is from the middle one, which is at most half a sample
+ */
+fn dip_offset(y0: f32, y1: f32, y2: f32) -> f32 {
+    let curve = y0 - 2.0 * y1 + y2;
+    if curve > 0.0 {
+        (0.5 * (y0 - y2) / curve).clamp(-0.5, 0.5)
+    } else {
+        0.0
+    }
+}
+
+/* Finds the period (in samples) of a window of 2 * max_period samples with the harmonic product spectrum
+ */
+fn hps_period(window: &[f32], max_period: usize) -> Option<f32> {
+    let fftlen = next_pow_of_2(window.len()) * HPS_PADDING;
+    let mut planner = FftPlanner::new();
+    let fft = planner.plan_fft_forward(fftlen);
+    let mut fftbuf = vec![Complex { re: 0.0, im: 0.0 }; fftlen];
+    let len = window.len() as f32;
+    for (i, (v, coef)) in window.iter().zip(fftbuf.iter_mut()).enumerate() {
+        // A Hann window keeps the harmonics from leaking into each other
+        coef.re = v * (0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / len).cos());
+    }
+    fft.process(&mut fftbuf);
+
+    // The product is taken as a sum of logs, so that it doesn't underflow
+    let nyquist = fftlen / 2;
+    let lowest = fftlen.div_ceil(max_period);
+    let highest = (fftlen / MIN_PERIOD).min((nyquist - 1) / HPS_HARMONICS);
+    if lowest + 1 >= highest {
+        return None;
+    }
+    let tiny = 1e-12;
+    let product = Vec::from_iter((0..=highest + 1).map(|bin| {
+        (1..=HPS_HARMONICS)
+            .map(|h| (fftbuf[(h * bin).min(nyquist)].norm() + tiny).ln())
+            .sum::<f32>()
+    }));
+    let bin = (lowest..=highest).max_by(|a, b| product[*a].total_cmp(&product[*b]))?;
+    let offset = dip_offset(-product[bin - 1], -product[bin], -product[bin + 1]);
+    let period = fftlen as f32 / (bin as f32 + offset);
+    Some(period.clamp(MIN_PERIOD as f32, max_period as f32))
+}
+
+/* Reads a single cycle of `period` samples, starting at a fractional position in a buffer, and resamples it to `len`
+ * samples. Positions outside of the buffer are clamped to its ends.
+ *
+ * A period that's longer than `len` would alias its upper harmonics if it were read straight into the shorter cycle, so
+ * it's read at a whole multiple of `len` that steps no more than a sample at a time, band-limited to the harmonics that
+ * `len` samples can hold, and then decimated.
+ */
+fn read_cycle(buffer: &[f32], start: f32, period: f32, len: usize) -> Vec<f32> {
+    let last = buffer.len() - 1;
+    let start = start.clamp(0.0, (last as f32 - period).max(0.0));
+    let factor = (period / len as f32).ceil().max(1.0) as usize;
+    let n = len * factor;
+    let cycle = Vec::from_iter((0..n).map(|i| {
+        let pos = start + i as f32 * period / n as f32;
+        let index = (pos.floor() as usize).min(last);
+        linear_interp(
+            pos - index as f32,
+            buffer[index],
+            buffer[(index + 1).min(last)],
+        )
+    }));
+    if factor == 1 {
+        return cycle;
+    }
+    Vec::from_iter(bandlimit(&cycle, len / 2).into_iter().step_by(factor))
+}
+
+/* Returns how far to rotate a cycle to the left so that its fundamental starts like a sine
+ */
+fn sine_rotation(cycle: &[f32]) -> usize {
+    let len = cycle.len();
+    let (re, im) = cycle
+        .iter()
+        .enumerate()
+        .fold((0.0, 0.0), |(re, im), (n, v)| {
+            let angle = std::f32::consts::TAU * n as f32 / len as f32;
+            (re + v * angle.cos(), im - v * angle.sin())
+        });
+    // The fundamental is cos(2πn/len + phase), and a sine has a phase of -π/2
+    let phase = im.atan2(re);
+    let turns = (-std::f32::consts::FRAC_PI_2 - phase) / std::f32::consts::TAU;
+    (turns.rem_euclid(1.0) * len as f32).round() as usize % len
+}
+
+/* Returns how far to rotate a cycle to the left so that it lines up best with a reference cycle of the same length, by
+ * way of their circular cross-correlation
+ */
+fn best_rotation(reference: &[f32], cycle: &[f32]) -> usize {
+    let len = cycle.len();
+    let mut planner = FftPlanner::new();
+    let fft = planner.plan_fft_forward(len);
+    let ifft = planner.plan_fft_inverse(len);
+    let mut refbuf = Vec::from_iter(reference.iter().map(|re| Complex { re: *re, im: 0.0 }));
+    let mut cycbuf = Vec::from_iter(cycle.iter().map(|re| Complex { re: *re, im: 0.0 }));
+    fft.process(&mut refbuf);
+    fft.process(&mut cycbuf);
+    for (r, c) in refbuf.iter_mut().zip(cycbuf.iter()) {
+        *r = r.conj() * c;
+    }
+    ifft.process(&mut refbuf);
+    (0..len)
+        .max_by(|a, b| refbuf[*a].re.total_cmp(&refbuf[*b].re))
+        .unwrap_or(0)
+}
+
 /** Performs a linear interpolation on a range of [0:1]
 */
 pub fn linear_interp(x: f32, y0: f32, y1: f32) -> f32 {
@@ -220,6 +1206,66 @@ pub fn resample(buffer: &[f32], len: usize, wrap: bool) -> Vec<f32> {
     }))
 }
 
+/** Removes all harmonics above `harmonics` from a single cycle of a periodic signal
+
+The buffer is treated as exactly one period of the signal, so bin `n` of its FFT is the nth harmonic. Every bin above
+`harmonics` (and its negative-frequency mirror) is zeroed before transforming back, so the returned cycle contains
+nothing above that harmonic.
+
+# Arguments
+
+* `buffer`:    One cycle of the signal
+* `harmonics`: The highest harmonic to keep
+*/
+pub fn bandlimit(buffer: &[f32], harmonics: usize) -> Vec<f32> {
+    let len = buffer.len();
+    let mut planner = FftPlanner::new();
+    let fft = planner.plan_fft_forward(len);
+    let ifft = planner.plan_fft_inverse(len);
+
+    let mut fftbuf = Vec::from_iter(buffer.iter().map(|re| Complex { re: *re, im: 0.0 }));
+    fft.process(&mut fftbuf);
+
+    for (i, coef) in fftbuf.iter_mut().enumerate() {
+        // Bin i and bin len - i hold the same harmonic
+        if i.min(len - i) > harmonics {
+            *coef = Complex { re: 0.0, im: 0.0 };
+        }
+    }
+
+    ifft.process(&mut fftbuf);
+    let norm = len as f32;
+    Vec::from_iter(fftbuf.iter().map(|coef| coef.re / norm))
+}
+
+/** Synthesizes a single cycle of a periodic signal from its harmonics
+
+Harmonic `k` is the sinusoid `amplitudes[k - 1] * sin(2πk * t + phases[k - 1])`, where `t` runs from 0 to 1 over the
+cycle. The harmonics are placed in the bins of a spectrum, which is then transformed back with an inverse FFT. Harmonics
+at or above the Nyquist frequency (`len / 2`) can't be represented in a cycle of `len` samples, so they're ignored.
+
+# Arguments
+
+* `len`:        The length of the cycle, in samples
+* `amplitudes`: The amplitude of each harmonic, starting with the fundamental
+* `phases`:     The phase of each harmonic (in radians). Harmonics without a phase start at 0.
+*/
+pub fn additive(len: usize, amplitudes: &[f32], phases: &[f32]) -> Vec<f32> {
+    let mut planner = FftPlanner::new();
+    let ifft = planner.plan_fft_inverse(len);
+
+    let mut fftbuf = vec![Complex { re: 0.0, im: 0.0 }; len];
+    let top = amplitudes.len().min((len.max(1) - 1) / 2);
+    for (k, amplitude) in amplitudes[..top].iter().enumerate() {
+        // The real part of the inverse transform is a cosine, so shift it back by a quarter cycle to get a sine
+        let phase = phases.get(k).copied().unwrap_or(0.0) - std::f32::consts::FRAC_PI_2;
+        fftbuf[k + 1] = Complex::from_polar(*amplitude, phase);
+    }
+
+    ifft.process(&mut fftbuf);
+    Vec::from_iter(fftbuf.iter().map(|coef| coef.re))
+}
+
 /** Returns the next power of two that is greater than or equal to x
 */
 pub fn next_pow_of_2<T>(x: T) -> T
@@ -233,9 +1279,14 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::{best_waveform, frequency_peaks, read_sndfile, resample, signal_energy};
+    use super::{
+        additive, best_waveform, best_waveform_with, detect_pitch, extract_cycle, frequency_peaks,
+        read_audio, read_audio_bytes, read_audio_from, read_clm_frame_len, resample, signal_energy,
+        slice_cycles, BitDepth, Channels, Container, Loop, LoopMode, PitchMethod, Seam,
+    };
     use float_cmp::approx_eq;
     use rand::{thread_rng, Rng};
+    use std::io::{Seek, SeekFrom};
 
     fn generate_triangle(len: usize, cps: f32) -> Vec<f32> {
         let slope = 4.0 * cps;
@@ -289,7 +1340,8 @@ mod tests {
 
     #[test]
     fn test_freq_peaks_afile() {
-        let (signal, fs) = read_sndfile("test/LongVoice.wav").unwrap();
+        let (signal, info) = read_audio("test/LongVoice.wav", Channels::Mix).unwrap();
+        let fs = info.samplerate;
 
         let sig_energy = signal_energy(&signal);
         let peaks = frequency_peaks(&signal, 1e-3 * sig_energy);
@@ -380,6 +1432,212 @@ mod tests {
         assert!(wf.is_none(), "Incorrectly captured a waveform from noise");
     }
 
+    #[test]
+    fn test_detect_pitch() {
+        //! A tone with a weak fundamental is found by every detector, and noise isn't trusted by any of them
+        let fs = 44100.0;
+        let freq = 220.0;
+        let tone = Vec::from_iter((0..fs as usize).map(|i| {
+            let phase = std::f32::consts::TAU * freq * i as f32 / fs;
+            0.05 * phase.sin() + (2..7).map(|h| (h as f32 * phase).sin()).sum::<f32>() / 5.0
+        }));
+        let noise = generate_noise(fs as usize);
+        for (method, tolerance) in [
+            (PitchMethod::Yin, 0.001),
+            (PitchMethod::ProbabilisticYin, 0.001),
+            (PitchMethod::Autocorrelation, 0.001),
+            (PitchMethod::HarmonicProduct, 0.01),
+        ] {
+            let pitch = detect_pitch(&tone, fs, method).unwrap();
+            assert!(
+                (pitch.frequency / freq - 1.0).abs() < tolerance,
+                "{:?} found {}Hz",
+                method,
+                pitch.frequency
+            );
+            assert!(pitch.confidence > 0.9, "{:?}: {:?}", method, pitch);
+
+            let confidence = detect_pitch(&noise, fs, method).map_or(0.0, |p| p.confidence);
+            assert!(
+                confidence < 0.5,
+                "{:?} trusted noise: {}",
+                method,
+                confidence
+            );
+        }
+
+        // best_waveform only refuses the noise because it isn't confident about it
+        assert!(best_waveform(&noise).is_none());
+        assert!(best_waveform_with(&noise, PitchMethod::Yin, 0.0).is_some());
+
+        assert!(detect_pitch(&[0.0; 4096], fs, PitchMethod::Yin).is_none());
+        assert!(detect_pitch(&tone[..32], fs, PitchMethod::Yin).is_none());
+    }
+
+    #[test]
+    fn test_extract_cycle() {
+        //! A cycle with a fractional period is extracted exactly, and wraps far more smoothly than a whole number of
+        //! samples does
+        let period = 469.3;
+        let wave = |t: f32, level: f32| {
+            let phase = std::f32::consts::TAU * t / period;
+            level
+                * (phase.sin() + 0.5 * (2.0 * phase + 1.0).sin() + 0.25 * (3.0 * phase - 2.0).sin())
+        };
+        let signal = Vec::from_iter((0..1 << 15).map(|n| wave(n as f32, 1.0)));
+
+        let cycle = extract_cycle(&signal, None, Seam::Off).unwrap();
+        assert!(
+            (cycle.period - period).abs() < 0.01,
+            "period: {}",
+            cycle.period
+        );
+        assert_eq!(cycle.samples.len(), 469);
+        for (i, v) in cycle.samples.iter().enumerate() {
+            let expected = wave(cycle.start + i as f32 * cycle.period / 469.0, 1.0);
+            assert!(
+                (v - expected).abs() < 1e-3,
+                "sample {}: {} != {}",
+                i,
+                v,
+                expected
+            );
+        }
+        assert!(cycle.discontinuity < 0.01, "{}", cycle.discontinuity);
+
+        // A whole number of samples leaves a break where the cycle wraps
+        let whole = best_waveform(&signal).unwrap();
+        let (len, peak) = (whole.len(), 1.5);
+        let kink = (whole[len - 2] - 2.0 * whole[len - 1] + whole[0]).abs() / peak;
+        assert!(
+            kink > 10.0 * cycle.discontinuity,
+            "{} vs {}",
+            kink,
+            cycle.discontinuity
+        );
+
+        // Cycles can be resampled to any length
+        let cycle = extract_cycle(&signal, Some(512), Seam::Off).unwrap();
+        assert_eq!(cycle.samples.len(), 512);
+
+        // When the sound swells, the end of a cycle is louder than its start, which a crossfade or a taper smooths over
+        let swell = Vec::from_iter((0..1 << 15).map(|n| wave(n as f32, (n as f32 / 2048.0).exp())));
+        let plain = extract_cycle(&swell, None, Seam::Off).unwrap();
+        let crossfaded = extract_cycle(&swell, None, Seam::Crossfade(0.1)).unwrap();
+        let tapered = extract_cycle(&swell, None, Seam::Taper(0.1)).unwrap();
+        assert!(plain.discontinuity > 1e-3, "{}", plain.discontinuity);
+        assert!(
+            crossfaded.discontinuity < 0.1 * plain.discontinuity,
+            "{}",
+            crossfaded.discontinuity
+        );
+        assert!(
+            tapered.discontinuity < 0.1 * plain.discontinuity,
+            "{}",
+            tapered.discontinuity
+        );
+        assert_eq!(crossfaded.samples[..400], plain.samples[..400]);
+
+        assert!(extract_cycle(&generate_noise(1 << 15), None, Seam::Off).is_none());
+    }
+
+    #[test]
+    fn test_extract_cycle_first() {
+        //! When the loudest cycle is the first one, there's nothing before it to crossfade into, so it's tapered instead
+        let period = 469.3;
+        let decay = Vec::from_iter((0..1 << 15).map(|n| {
+            let phase = std::f32::consts::TAU * n as f32 / period + 1.0;
+            (n as f32 / -2048.0).exp() * (phase.sin() + 0.5 * (2.0 * phase).sin())
+        }));
+        let crossfaded = extract_cycle(&decay, None, Seam::Crossfade(0.1)).unwrap();
+        assert!(crossfaded.start < crossfaded.period, "{}", crossfaded.start);
+        let tapered = extract_cycle(&decay, None, Seam::Taper(0.1)).unwrap();
+        assert_eq!(crossfaded, tapered);
+        assert!(
+            crossfaded.discontinuity < 0.01,
+            "{}",
+            crossfaded.discontinuity
+        );
+    }
+
+    #[test]
+    fn test_slice_cycles() {
+        //! A glide from a sine to a sine with a second harmonic is sliced into aligned frames of the same length
+        let len = 1 << 16;
+        let mut phase = 0.0f64;
+        let mut signal = Vec::with_capacity(len);
+        for i in 0..len {
+            let t = i as f32 / len as f32;
+            let x = std::f64::consts::TAU * phase;
+            signal.push(0.8 * x.sin() as f32 + 0.4 * t * (2.0 * x).sin() as f32);
+            phase += 1.0 / (200.0 + 50.0 * t as f64);
+        }
+        // Silence on either end shouldn't be sliced
+        let mut padded = vec![0.0; 8192];
+        padded.extend(signal);
+        padded.extend([0.0; 8192]);
+
+        let frames = 8;
+        let framelen = 256;
+        let table = slice_cycles(&padded, frames, framelen).unwrap();
+        assert_eq!(table.len(), frames * framelen);
+        for (k, frame) in table.chunks(framelen).enumerate() {
+            // The edges of the pitched part are only a fraction of the way into the glide
+            let second = if k == 0 {
+                0.0
+            } else if k == frames - 1 {
+                0.4
+            } else {
+                continue;
+            };
+            for (n, v) in frame.iter().enumerate() {
+                let x = std::f32::consts::TAU * n as f32 / framelen as f32;
+                let expected = 0.8 * x.sin() + second * (2.0 * x).sin();
+                assert!(
+                    approx_eq!(f32, *v, expected, epsilon = 0.05),
+                    "frame {}[{}] = {}, expected: {}",
+                    k,
+                    n,
+                    v,
+                    expected
+                );
+            }
+        }
+
+        assert!(slice_cycles(&generate_noise(1 << 16), frames, framelen).is_none());
+    }
+
+    #[test]
+    fn test_slice_cycles_bandlimited() {
+        //! A 200 sample period sliced into 16 sample frames can only keep up to harmonic 8. The 9th harmonic has to be
+        //! filtered out rather than folded back onto the 7th.
+        let signal = Vec::from_iter((0..1 << 15).map(|i| {
+            let x = std::f32::consts::TAU * i as f32 / 200.0;
+            0.8 * x.sin() + 0.4 * (9.0 * x).sin()
+        }));
+        let framelen = 16;
+        let table = slice_cycles(&signal, 1, framelen).unwrap();
+        // The frame is rotated by whole samples, so compare the magnitudes of its harmonics
+        let magnitude = |h: usize| {
+            let (re, im) = table
+                .iter()
+                .enumerate()
+                .fold((0.0, 0.0), |(re, im), (n, v)| {
+                    let angle = std::f32::consts::TAU * (h * n) as f32 / framelen as f32;
+                    (re + v * angle.cos(), im - v * angle.sin())
+                });
+            2.0 * (re * re + im * im).sqrt() / framelen as f32
+        };
+        assert!(
+            approx_eq!(f32, magnitude(1), 0.8, epsilon = 0.02),
+            "{}",
+            magnitude(1)
+        );
+        for h in 2..=framelen / 2 {
+            assert!(magnitude(h) < 0.01, "harmonic {}: {}", h, magnitude(h));
+        }
+    }
+
     #[test]
     fn test_resample_long() {
         let inlen = 44100;
@@ -427,4 +1685,195 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_additive() {
+        let len = 256;
+        let amplitudes = [1.0, 0.0, 0.5];
+        let phases = [0.0, 0.0, std::f32::consts::FRAC_PI_2];
+        let cycle = additive(len, &amplitudes, &phases);
+        assert_eq!(cycle.len(), len);
+        for (i, v) in cycle.iter().enumerate() {
+            let t = 2.0 * std::f32::consts::PI * i as f32 / len as f32;
+            let expected = t.sin() + 0.5 * (3.0 * t).cos();
+            assert!(
+                approx_eq!(f32, *v, expected, epsilon = 1e-5),
+                "Expected sample {} value: {}. Got {}",
+                i,
+                expected,
+                v
+            );
+        }
+
+        // Harmonics at and above the Nyquist frequency are dropped
+        let cycle = additive(8, &[0.0, 0.0, 0.0, 1.0, 1.0], &[]);
+        assert!(cycle.iter().all(|v| *v == 0.0));
+    }
+
+    #[test]
+    fn test_read_clm_frame_len() {
+        assert_eq!(read_clm_frame_len("test/serum.wav").unwrap(), Some(2048));
+        assert_eq!(read_clm_frame_len("test/saw.wav").unwrap(), None);
+        assert!(read_clm_frame_len("test/missing.wav").is_err());
+
+        // A chunk that claims to be 4GB long is only read as far as the frame length
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF\0\0\0\0WAVEclm ");
+        wav.extend_from_slice(&u32::MAX.to_le_bytes());
+        wav.extend_from_slice(b"<!>512 10000000 wavetable");
+        let path = std::env::temp_dir().join(format!("wavetable-{}-clm.wav", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, wav).unwrap();
+        assert_eq!(read_clm_frame_len(path).unwrap(), Some(512));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_read_audio() {
+        // A stereo WAV, with a smpl chunk that names a root key and two loops
+        let frames = 8u32;
+        let mut data = Vec::new();
+        for i in 0..frames as i16 {
+            data.extend_from_slice(&(i * 1024).to_le_bytes());
+            data.extend_from_slice(&(-i * 2048).to_le_bytes());
+        }
+        let mut smpl = Vec::new();
+        for v in [0, 0, 45351, 60, 0, 0, 0, 2, 0] {
+            smpl.extend_from_slice(&(v as u32).to_le_bytes());
+        }
+        // Each loop is its id, type, start, inclusive end, fraction and play count
+        for (id, kind, start, end) in [(0, 0, 2, 5), (1, 1, 1, 6)] {
+            for v in [id, kind, start, end, 0, 0] {
+                smpl.extend_from_slice(&(v as u32).to_le_bytes());
+            }
+        }
+        let mut wav = Vec::from(&b"RIFF\0\0\0\0WAVEfmt "[..]);
+        for (v, n) in [
+            (16, 4),
+            (1, 2),
+            (2, 2),
+            (22050, 4),
+            (88200, 4),
+            (4, 2),
+            (16, 2),
+        ] {
+            wav.extend_from_slice(&(v as u32).to_le_bytes()[..n]);
+        }
+        for (id, chunk) in [(b"smpl", &smpl), (b"data", &data)] {
+            wav.extend_from_slice(id);
+            wav.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            wav.extend_from_slice(chunk);
+        }
+        let riff_size = (wav.len() - 8) as u32;
+        wav[4..8].copy_from_slice(&riff_size.to_le_bytes());
+        let path = std::env::temp_dir().join(format!("wavetable-{}-smpl.wav", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, wav).unwrap();
+
+        let (all, info) = read_audio(path, Channels::All).unwrap();
+        assert_eq!(all.len(), 2 * frames as usize);
+        assert_eq!(info.samplerate, 22050);
+        assert_eq!(info.channels, 2);
+        assert_eq!(info.frames, frames as usize);
+        assert_eq!(info.container, Some(Container::Wav));
+        assert_eq!(info.depth, Some(BitDepth::Pcm16));
+        assert_eq!(info.root_key, Some(60));
+        assert_eq!(
+            info.loops,
+            vec![
+                Loop {
+                    start: 2,
+                    end: 6,
+                    mode: LoopMode::Forward
+                },
+                Loop {
+                    start: 1,
+                    end: 7,
+                    mode: LoopMode::Alternating
+                },
+            ]
+        );
+
+        let (right, _) = read_audio(path, Channels::Select(1)).unwrap();
+        let (mixed, _) = read_audio(path, Channels::Mix).unwrap();
+        for i in 0..frames as usize {
+            assert_eq!(right[i], all[2 * i + 1]);
+            assert_eq!(right[i], -(i as f32) / 16.0);
+            assert_eq!(mixed[i], -(i as f32) / 64.0);
+        }
+        assert!(read_audio(path, Channels::Select(2)).is_err());
+        std::fs::remove_file(path).unwrap();
+
+        // Every frame is read, and files without a smpl chunk have no root key or loops
+        let (saw, info) = read_audio("test/saw.wav", Channels::Mix).unwrap();
+        assert_eq!(saw.len(), info.frames);
+        assert!(saw.len() > 1024);
+        assert_eq!(info.root_key, None);
+        assert!(info.loops.is_empty());
+    }
+
+    #[test]
+    fn test_read_audio_bytes() {
+        let (expected, expected_info) = read_audio("test/saw.wav", Channels::All).unwrap();
+        let (saw, info) =
+            read_audio_bytes(include_bytes!("../test/saw.wav"), Channels::All).unwrap();
+        assert_eq!(saw, expected);
+        assert_eq!(info, expected_info);
+
+        // A reader is read from its start, no matter where it was left
+        let mut file = std::fs::File::open("test/saw.wav").unwrap();
+        file.seek(SeekFrom::Start(100)).unwrap();
+        let (saw, _) = read_audio_from(file, Channels::Mix).unwrap();
+        assert_eq!(saw, expected);
+
+        assert!(read_audio_bytes(b"RIFF", Channels::Mix).is_err());
+        assert!(read_audio_bytes(&[], Channels::Mix).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "sndfile")]
+    fn test_write_sndfile() {
+        use super::{write_clm_frame_len, write_sndfile};
+        let path = std::env::temp_dir().join(format!("wavetable-{}-write.wav", std::process::id()));
+        let path = path.to_str().unwrap();
+        let table = Vec::from_iter((0..64).map(|i| (i as f32 - 32.0) / 16.0));
+
+        // Floats are stored as they are
+        write_sndfile(path, &table, 44100, Container::Wav, BitDepth::Float).unwrap();
+        let (read, info) = read_audio(path, Channels::Mix).unwrap();
+        assert_eq!((read, info.samplerate), (table.clone(), 44100));
+
+        // Integers are clipped and rounded to their depth
+        write_sndfile(path, &table, 48000, Container::Wav, BitDepth::Pcm16).unwrap();
+        let (read, info) = read_audio(path, Channels::Mix).unwrap();
+        assert_eq!(info.samplerate, 48000);
+        for (i, (r, t)) in read.iter().zip(&table).enumerate() {
+            let expected = t.clamp(-1.0, 1.0);
+            assert!(
+                (r - expected).abs() <= 1.0 / 32768.0,
+                "read[{}] = {}, expected: {}",
+                i,
+                r,
+                expected
+            );
+        }
+
+        // The clm chunk goes in front of the data, and replaces any old one
+        assert_eq!(read_clm_frame_len(path).unwrap(), None);
+        write_clm_frame_len(path, 16).unwrap();
+        assert_eq!(read_clm_frame_len(path).unwrap(), Some(16));
+        write_clm_frame_len(path, 32).unwrap();
+        assert_eq!(read_clm_frame_len(path).unwrap(), Some(32));
+        assert_eq!(read_audio(path, Channels::Mix).unwrap().0, read);
+        std::fs::remove_file(path).unwrap();
+
+        assert!(write_sndfile(
+            "/nonexistent/dir/out.wav",
+            &table,
+            44100,
+            Container::Wav,
+            BitDepth::Float
+        )
+        .is_err());
+    }
 }
@@ -0,0 +1,287 @@
+/* The libsndfile backend, which reads and writes audio files through sndfile-sys
+ */
+use super::{AudioInfo, BitDepth, Container, Loop, LoopMode, ReadSeek};
+use sndfile_sys as sndfile;
+use sndfile_sys::{
+    sf_count_t, SFM_READ, SFM_WRITE, SF_INFO, SF_INSTRUMENT, SF_VIRTUAL_IO, SNDFILE,
+};
+use std::ffi::{c_void, CStr, CString};
+use std::io::SeekFrom;
+use std::os::raw::c_int;
+
+/* Reads every frame of every channel of an audio file, interleaved
+ */
+pub(super) fn decode(path: &str) -> Result<(Vec<f32>, AudioInfo), std::io::Error> {
+    let mut info = empty_info();
+    let c_path = CString::new(path).unwrap();
+    let sf: *mut SNDFILE =
+        unsafe { sndfile::sf_open(c_path.as_ptr() as *mut _, SFM_READ, &mut info) };
+    read_frames(sf, info, path)
+}
+
+/* Reads every frame of every channel of the audio in a reader, interleaved, through libsndfile's virtual IO
+ */
+pub(super) fn decode_reader(
+    reader: &mut dyn ReadSeek,
+    name: &str,
+) -> Result<(Vec<f32>, AudioInfo), std::io::Error> {
+    let mut vio = SF_VIRTUAL_IO {
+        get_filelen: vio_get_filelen,
+        seek: vio_seek,
+        read: vio_read,
+        write: vio_write,
+        tell: vio_tell,
+    };
+    reader.seek(SeekFrom::Start(0)).map_err(|e| {
+        std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("Unable to open {}. {}", name, e),
+        )
+    })?;
+    // The callbacks get a pointer to the fat pointer, since a trait object doesn't fit in a void pointer
+    let mut reader = reader;
+    let mut info = empty_info();
+    let sf: *mut SNDFILE = unsafe {
+        sndfile::sf_open_virtual(
+            &mut vio,
+            SFM_READ,
+            &mut info,
+            &mut reader as *mut &mut dyn ReadSeek as *mut c_void,
+        )
+    };
+    read_frames(sf, info, name)
+}
+
+fn empty_info() -> SF_INFO {
+    SF_INFO {
+        frames: 0,
+        samplerate: 0,
+        channels: 0,
+        format: 0,
+        sections: 0,
+        seekable: 0,
+    }
+}
+
+/* Reads all of the frames from a file that was just opened, and closes it
+ */
+fn read_frames(
+    sf: *mut SNDFILE,
+    info: SF_INFO,
+    name: &str,
+) -> Result<(Vec<f32>, AudioInfo), std::io::Error> {
+    if sf as usize == 0 {
+        let reason_pchar = unsafe { sndfile::sf_strerror(sf) };
+        let reason = unsafe { CStr::from_ptr(reason_pchar).to_str().unwrap() };
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("Unable to open {}. {}", name, reason),
+        ));
+    }
+
+    let frames = info.frames as usize;
+    let chans = info.channels as usize;
+    let mut table = vec![0.0f32; frames * chans];
+    let count = unsafe { sndfile::sf_readf_float(sf, table.as_mut_ptr(), frames as sf_count_t) };
+    let (root_key, loops) = read_instrument(sf);
+    unsafe { sndfile::sf_close(sf) };
+
+    if count as usize != frames {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!(
+                "Read fewer frames than expected. Expected {}, got {}",
+                frames, count
+            ),
+        ));
+    }
+
+    let info = AudioInfo {
+        samplerate: info.samplerate,
+        channels: chans,
+        frames,
+        container: match info.format & sndfile::SF_FORMAT_TYPEMASK {
+            sndfile::SF_FORMAT_WAV => Some(Container::Wav),
+            sndfile::SF_FORMAT_AIFF => Some(Container::Aiff),
+            sndfile::SF_FORMAT_FLAC => Some(Container::Flac),
+            sndfile::SF_FORMAT_CAF => Some(Container::Caf),
+            _ => None,
+        },
+        depth: match info.format & sndfile::SF_FORMAT_SUBMASK {
+            sndfile::SF_FORMAT_PCM_16 => Some(BitDepth::Pcm16),
+            sndfile::SF_FORMAT_PCM_24 => Some(BitDepth::Pcm24),
+            sndfile::SF_FORMAT_PCM_32 => Some(BitDepth::Pcm32),
+            sndfile::SF_FORMAT_FLOAT => Some(BitDepth::Float),
+            _ => None,
+        },
+        root_key,
+        loops,
+    };
+    Ok((table, info))
+}
+
+/* Reads the root key and the loops from an open file's instrument data, which libsndfile gathers from the smpl and inst
+ * chunks
+ */
+fn read_instrument(sf: *mut SNDFILE) -> (Option<u8>, Vec<Loop>) {
+    let mut inst: SF_INSTRUMENT = unsafe { std::mem::zeroed() };
+    let found = unsafe {
+        sndfile::sf_command(
+            sf,
+            sndfile::SFC_GET_INSTRUMENT,
+            &mut inst as *mut _ as *mut _,
+            std::mem::size_of::<SF_INSTRUMENT>() as c_int,
+        )
+    };
+    if found == 0 {
+        return (None, Vec::new());
+    }
+
+    let count = (inst.loop_count.max(0) as usize).min(inst.loops.len());
+    let loops = Vec::from_iter(inst.loops[..count].iter().filter_map(|l| {
+        let mode = match l.mode {
+            sndfile::SF_LOOP_FORWARD => LoopMode::Forward,
+            sndfile::SF_LOOP_BACKWARD => LoopMode::Backward,
+            sndfile::SF_LOOP_ALTERNATING => LoopMode::Alternating,
+            _ => return None,
+        };
+        Some(Loop {
+            start: l.start as usize,
+            end: l.end as usize,
+            mode,
+        })
+    }));
+    // MIDI notes only go up to 127
+    let root_key = u8::try_from(inst.basenote).ok().filter(|key| *key < 128);
+    (root_key, loops)
+}
+
+/* The virtual IO callbacks, which get the reader as their user data
+ *
+ * Safety: `user_data` must be the pointer that decode_reader gave to sf_open_virtual, which points to a
+ * `&mut dyn ReadSeek` that lives until the SNDFILE is closed, and nothing else may be using the reader.
+ */
+unsafe fn vio_reader<'a>(user_data: *mut c_void) -> &'a mut dyn ReadSeek {
+    &mut **(user_data as *mut &mut dyn ReadSeek)
+}
+
+extern "C" fn vio_get_filelen(user_data: *mut c_void) -> sf_count_t {
+    // SAFETY: libsndfile only calls back with the user data that decode_reader opened the SNDFILE with, and only while
+    // the SNDFILE (and so the reader) is open
+    let reader = unsafe { vio_reader(user_data) };
+    let len = reader.stream_position().and_then(|pos| {
+        let len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(pos))?;
+        Ok(len)
+    });
+    len.map_or(-1, |len| len as sf_count_t)
+}
+
+extern "C" fn vio_seek(offset: sf_count_t, whence: c_int, user_data: *mut c_void) -> sf_count_t {
+    let pos = match whence {
+        sndfile::SF_SEEK_SET => SeekFrom::Start(offset.max(0) as u64),
+        sndfile::SF_SEEK_CUR => SeekFrom::Current(offset),
+        _ => SeekFrom::End(offset),
+    };
+    // SAFETY: As in vio_get_filelen, the user data is decode_reader's reader, which is still open
+    unsafe { vio_reader(user_data) }
+        .seek(pos)
+        .map_or(-1, |pos| pos as sf_count_t)
+}
+
+extern "C" fn vio_read(ptr: *mut c_void, count: sf_count_t, user_data: *mut c_void) -> sf_count_t {
+    // SAFETY: As in vio_get_filelen, the user data is decode_reader's reader, which is still open
+    let reader = unsafe { vio_reader(user_data) };
+    let buffer = unsafe { std::slice::from_raw_parts_mut(ptr as *mut u8, count.max(0) as usize) };
+    // Fill as much of the buffer as possible, since a short read looks like the end of the file
+    let mut done = 0;
+    while done < buffer.len() {
+        match reader.read(&mut buffer[done..]) {
+            Ok(0) => break,
+            Ok(n) => done += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+            Err(_) => break,
+        }
+    }
+    done as sf_count_t
+}
+
+extern "C" fn vio_write(_: *const c_void, _: sf_count_t, _: *mut c_void) -> sf_count_t {
+    0
+}
+
+extern "C" fn vio_tell(user_data: *mut c_void) -> sf_count_t {
+    // SAFETY: As in vio_get_filelen, the user data is decode_reader's reader, which is still open
+    unsafe { vio_reader(user_data) }
+        .stream_position()
+        .map_or(-1, |pos| pos as sf_count_t)
+}
+
+/* Writes a single track of audio to an audio file
+ */
+pub(super) fn encode(
+    path: &str,
+    table: &[f32],
+    samplerate: i32,
+    container: Container,
+    depth: BitDepth,
+) -> Result<(), std::io::Error> {
+    let container = match container {
+        Container::Wav => sndfile::SF_FORMAT_WAV,
+        Container::Aiff => sndfile::SF_FORMAT_AIFF,
+        Container::Flac => sndfile::SF_FORMAT_FLAC,
+        Container::Caf => sndfile::SF_FORMAT_CAF,
+    };
+    let depth = match depth {
+        BitDepth::Pcm16 => sndfile::SF_FORMAT_PCM_16,
+        BitDepth::Pcm24 => sndfile::SF_FORMAT_PCM_24,
+        BitDepth::Pcm32 => sndfile::SF_FORMAT_PCM_32,
+        BitDepth::Float => sndfile::SF_FORMAT_FLOAT,
+    };
+    let mut info = SF_INFO {
+        frames: 0,
+        samplerate,
+        channels: 1,
+        format: container | depth,
+        sections: 0,
+        seekable: 0,
+    };
+    if unsafe { sndfile::sf_format_check(&info) } == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!(
+                "Unable to write {}. The format isn't supported at a sample rate of {}",
+                path, samplerate
+            ),
+        ));
+    }
+
+    let c_path = CString::new(path).unwrap();
+    let sf: *mut SNDFILE =
+        unsafe { sndfile::sf_open(c_path.as_ptr() as *mut _, SFM_WRITE, &mut info) };
+    if sf as usize == 0 {
+        let reason_pchar = unsafe { sndfile::sf_strerror(sf) };
+        let reason = unsafe { CStr::from_ptr(reason_pchar).to_str().unwrap() };
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("Unable to write {}. {}", path, reason),
+        ));
+    }
+
+    // Keep samples that are out of range from wrapping around when they're converted to integers
+    unsafe { sndfile::sf_command(sf, sndfile::SFC_SET_CLIPPING, std::ptr::null_mut(), 1) };
+    let count = unsafe { sndfile::sf_writef_float(sf, table.as_ptr(), table.len() as sf_count_t) };
+    unsafe { sndfile::sf_close(sf) };
+
+    if count as usize != table.len() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!(
+                "Wrote fewer frames than expected. Expected {}, wrote {}",
+                table.len(),
+                count
+            ),
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,331 @@
+/* The pure Rust backend, which decodes WAV, AIFF and FLAC files with symphonia
+ *
+ * Symphonia doesn't read sampler metadata, so the root key and loops are read straight from the file's chunks: `smpl`
+ * and `inst` in a WAV file, or `INST` and `MARK` in an AIFF file.
+ */
+use super::{AudioInfo, BitDepth, Container, Loop, LoopMode, ReadSeek};
+use std::io::{Cursor, SeekFrom};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_PCM_F32BE, CODEC_TYPE_PCM_F32LE};
+use symphonia::core::errors::Error as DecodeError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+/* Reads every frame of every channel of an audio file, interleaved
+ */
+pub(super) fn decode(path: &str) -> Result<(Vec<f32>, AudioInfo), std::io::Error> {
+    let data = std::fs::read(path).map_err(|e| error(path, e))?;
+    decode_bytes(data, path)
+}
+
+/* Reads every frame of every channel of the audio file in a reader, interleaved
+ */
+pub(super) fn decode_reader(
+    reader: &mut dyn ReadSeek,
+    name: &str,
+) -> Result<(Vec<f32>, AudioInfo), std::io::Error> {
+    let mut data = Vec::new();
+    reader
+        .seek(SeekFrom::Start(0))
+        .and_then(|_| reader.read_to_end(&mut data))
+        .map_err(|e| error(name, e))?;
+    decode_bytes(data, name)
+}
+
+/* Reads every frame of every channel of the audio file held in a buffer, interleaved. The name is only used for error
+ * messages.
+ */
+pub(super) fn decode_bytes(
+    data: Vec<u8>,
+    name: &str,
+) -> Result<(Vec<f32>, AudioInfo), std::io::Error> {
+    let container = match (data.get(0..4), data.get(8..12)) {
+        (Some(b"RIFF"), Some(b"WAVE")) => Container::Wav,
+        (Some(b"FORM"), Some(b"AIFF" | b"AIFC")) => Container::Aiff,
+        (Some(b"fLaC"), _) => Container::Flac,
+        _ => return Err(error(name, "The file isn't a WAV, AIFF or FLAC file")),
+    };
+    let (root_key, loops) = match container {
+        Container::Wav => read_riff_instrument(&data),
+        Container::Aiff => read_aiff_instrument(&data),
+        _ => (None, Vec::new()),
+    };
+
+    let mut hint = Hint::new();
+    hint.with_extension(match container {
+        Container::Wav => "wav",
+        Container::Aiff => "aiff",
+        _ => "flac",
+    });
+    let stream = MediaSourceStream::new(Box::new(Cursor::new(data)), Default::default());
+    let mut format = symphonia::default::get_probe()
+        .format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(|e| error(name, e))?
+        .format;
+    let track = format
+        .default_track()
+        .ok_or_else(|| error(name, "The file has no audio"))?;
+    let track_id = track.id;
+    let params = track.codec_params.clone();
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&params, &DecoderOptions::default())
+        .map_err(|e| error(name, e))?;
+
+    let mut table = Vec::new();
+    let mut chans = params.channels.map(|c| c.count()).unwrap_or(0);
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(DecodeError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(error(name, e)),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = decoder.decode(&packet).map_err(|e| error(name, e))?;
+        chans = decoded.spec().channels.count();
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+        buffer.copy_interleaved_ref(decoded);
+        table.extend_from_slice(buffer.samples());
+    }
+
+    let info = AudioInfo {
+        samplerate: params.sample_rate.unwrap_or(0) as i32,
+        channels: chans,
+        frames: table.len() / chans.max(1),
+        container: Some(container),
+        depth: match (params.codec, params.bits_per_sample) {
+            (CODEC_TYPE_PCM_F32LE | CODEC_TYPE_PCM_F32BE, _) => Some(BitDepth::Float),
+            (_, Some(16)) => Some(BitDepth::Pcm16),
+            (_, Some(24)) => Some(BitDepth::Pcm24),
+            (_, Some(32)) => Some(BitDepth::Pcm32),
+            _ => None,
+        },
+        root_key,
+        loops,
+    };
+    Ok((table, info))
+}
+
+/* Reads the root key and the loops from a WAV file's smpl and inst chunks. Like libsndfile, the root key in an inst
+ * chunk wins over the one in a smpl chunk.
+ */
+fn read_riff_instrument(data: &[u8]) -> (Option<u8>, Vec<Loop>) {
+    let u32_at = |chunk: &[u8], pos: usize| {
+        chunk
+            .get(pos..pos + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    };
+    let mut smpl_key = None;
+    let mut inst_key = None;
+    let mut loops = Vec::new();
+    for (id, chunk) in chunks(&data[12..], u32::from_le_bytes) {
+        match id {
+            b"smpl" => {
+                smpl_key = u32_at(chunk, 12).and_then(midi_note);
+                let count = u32_at(chunk, 28).unwrap_or(0) as usize;
+                // Each loop is its id, type, start, inclusive end, fraction and play count
+                loops = Vec::from_iter((0..count).filter_map(|k| {
+                    let pos = 36 + 24 * k;
+                    let mode = match u32_at(chunk, pos + 4)? {
+                        0 => LoopMode::Forward,
+                        1 => LoopMode::Alternating,
+                        2 => LoopMode::Backward,
+                        _ => return None,
+                    };
+                    Some(Loop {
+                        start: u32_at(chunk, pos + 8)? as usize,
+                        end: u32_at(chunk, pos + 12)? as usize + 1,
+                        mode,
+                    })
+                }));
+            }
+            b"inst" => inst_key = chunk.first().and_then(|key| midi_note(*key as u32)),
+            _ => (),
+        }
+    }
+    (inst_key.or(smpl_key), loops)
+}
+
+/* Reads the root key and the loops from an AIFF file's INST chunk, which finds the sustain and release loops' ends
+ * among the markers in the MARK chunk
+ */
+fn read_aiff_instrument(data: &[u8]) -> (Option<u8>, Vec<Loop>) {
+    let u16_at = |chunk: &[u8], pos: usize| {
+        chunk
+            .get(pos..pos + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+    };
+    let mut markers = Vec::new();
+    let mut inst = None;
+    for (id, chunk) in chunks(&data[12..], u32::from_be_bytes) {
+        match id {
+            b"MARK" => {
+                // Each marker is its id, position and a name that's padded to an even length
+                let count = u16_at(chunk, 0).unwrap_or(0);
+                let mut pos = 2;
+                for _ in 0..count {
+                    let (id, position, name_len) = match (
+                        u16_at(chunk, pos),
+                        chunk.get(pos + 2..pos + 6),
+                        chunk.get(pos + 6),
+                    ) {
+                        (Some(id), Some(position), Some(name_len)) => (id, position, name_len),
+                        _ => break,
+                    };
+                    let position =
+                        u32::from_be_bytes([position[0], position[1], position[2], position[3]]);
+                    markers.push((id, position as usize));
+                    pos += 7 + *name_len as usize;
+                    pos += pos & 1;
+                }
+            }
+            b"INST" => inst = Some(chunk),
+            _ => (),
+        }
+    }
+
+    let inst = match inst {
+        Some(inst) => inst,
+        None => return (None, Vec::new()),
+    };
+    let marker = |id: u16| markers.iter().find(|m| m.0 == id).map(|m| m.1);
+    // The sustain loop and then the release loop, which are each a play mode and a pair of marker ids
+    let loops = Vec::from_iter([8, 14].iter().filter_map(|pos| {
+        let mode = match u16_at(inst, *pos)? {
+            1 => LoopMode::Forward,
+            2 => LoopMode::Alternating,
+            _ => return None,
+        };
+        Some(Loop {
+            start: marker(u16_at(inst, pos + 2)?)?,
+            end: marker(u16_at(inst, pos + 4)?)?,
+            mode,
+        })
+    }));
+    (inst.first().and_then(|key| midi_note(*key as u32)), loops)
+}
+
+/* Splits the body of a RIFF or IFF file into its chunks. The sizes are read with the file's byte order.
+ */
+fn chunks(mut data: &[u8], size: fn([u8; 4]) -> u32) -> Vec<(&[u8; 4], &[u8])> {
+    let mut chunks = Vec::new();
+    while data.len() >= 8 {
+        let id: &[u8; 4] = data[0..4].try_into().unwrap();
+        let len = size(data[4..8].try_into().unwrap()) as usize;
+        let end = (8 + len).min(data.len());
+        chunks.push((id, &data[8..end]));
+        // Chunks are padded to an even length
+        data = &data[(end + (len & 1)).min(data.len())..];
+    }
+    chunks
+}
+
+/* Returns the note if it's a valid MIDI note
+ */
+fn midi_note(note: u32) -> Option<u8> {
+    u8::try_from(note).ok().filter(|note| *note < 128)
+}
+
+fn error(name: &str, reason: impl std::fmt::Display) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Other,
+        format!("Unable to open {}. {}", name, reason),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{BitDepth, Container, Loop, LoopMode};
+    use super::decode;
+
+    #[test]
+    fn test_decode_flac() {
+        let (flac, info) = decode("test/saw.flac").unwrap();
+        let (wav, _) = decode("test/saw.wav").unwrap();
+        assert_eq!(flac, wav);
+        assert_eq!(info.samplerate, 48000);
+        assert_eq!(info.channels, 1);
+        assert_eq!(info.frames, 1200);
+        assert_eq!(info.container, Some(Container::Flac));
+        assert_eq!(info.depth, Some(BitDepth::Pcm16));
+    }
+
+    #[test]
+    fn test_decode_aiff() {
+        // A stereo AIFF, with a sustain loop between two markers and a root key
+        let frames = 8u32;
+        let mut comm = Vec::new();
+        comm.extend_from_slice(&2u16.to_be_bytes());
+        comm.extend_from_slice(&frames.to_be_bytes());
+        comm.extend_from_slice(&16u16.to_be_bytes());
+        // 22050 as an 80-bit float
+        comm.extend_from_slice(&[0x40, 0x0D, 0xAC, 0x44, 0, 0, 0, 0, 0, 0]);
+        let mut ssnd = vec![0u8; 8];
+        for i in 0..frames as i16 {
+            ssnd.extend_from_slice(&(i * 1024).to_be_bytes());
+            ssnd.extend_from_slice(&(-i * 2048).to_be_bytes());
+        }
+        let mut mark = Vec::from(2u16.to_be_bytes());
+        for (id, position, name) in [(1u16, 2u32, &b"start"[..]), (2, 6, &b"end"[..])] {
+            mark.extend_from_slice(&id.to_be_bytes());
+            mark.extend_from_slice(&position.to_be_bytes());
+            mark.push(name.len() as u8);
+            mark.extend_from_slice(name);
+            if name.len() % 2 == 0 {
+                mark.push(0);
+            }
+        }
+        let mut inst = vec![57, 0, 0, 127, 1, 127, 0, 0];
+        for v in [1u16, 1, 2, 0, 0, 0] {
+            inst.extend_from_slice(&v.to_be_bytes());
+        }
+        let mut aiff = Vec::from(&b"FORM\0\0\0\0AIFF"[..]);
+        for (id, chunk) in [
+            (b"COMM", &comm),
+            (b"MARK", &mark),
+            (b"INST", &inst),
+            (b"SSND", &ssnd),
+        ] {
+            aiff.extend_from_slice(id);
+            aiff.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+            aiff.extend_from_slice(chunk);
+        }
+        let form_size = (aiff.len() - 8) as u32;
+        aiff[4..8].copy_from_slice(&form_size.to_be_bytes());
+        let path = std::env::temp_dir().join(format!("wavetable-{}-inst.aiff", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, aiff).unwrap();
+
+        let (table, info) = decode(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        for i in 0..frames as usize {
+            assert_eq!(table[2 * i], i as f32 / 32.0);
+            assert_eq!(table[2 * i + 1], -(i as f32) / 16.0);
+        }
+        assert_eq!(info.samplerate, 22050);
+        assert_eq!(info.channels, 2);
+        assert_eq!(info.frames, frames as usize);
+        assert_eq!(info.container, Some(Container::Aiff));
+        assert_eq!(info.depth, Some(BitDepth::Pcm16));
+        assert_eq!(info.root_key, Some(57));
+        assert_eq!(
+            info.loops,
+            vec![Loop {
+                start: 2,
+                end: 6,
+                mode: LoopMode::Forward
+            }]
+        );
+
+        assert!(decode("test/missing.wav").is_err());
+        assert!(decode("Cargo.toml").is_err());
+    }
+}
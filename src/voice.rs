@@ -2,11 +2,35 @@ use super::envelope;
 use super::envelope::EnvStage::*;
 use super::envelope::{Gate, ASDR};
 use super::system::System;
+use super::unison::Unison;
 use super::wt::{Phasor, Wavetable};
 use std::sync::Arc;
 
 type Envelope = ASDR;
 
+/* The oscillator that a voice plays
+ */
+enum Oscillator {
+    Single(Phasor),
+    Sync(HardSync),
+    Unison(Box<Unison>),
+}
+
+/* A hard synced oscillator pair. The master sets the voice's pitch and the slave is heard.
+ */
+struct HardSync {
+    // The master oscillator
+    master: Phasor,
+    // The slave oscillator
+    slave: Phasor,
+    // The slave's frequency, as a ratio of the master's
+    ratio: f32,
+    // The master's (unheard) output
+    masterbuf: Vec<f32>,
+    // The master's sync events
+    syncbuf: Vec<Option<f32>>,
+}
+
 /** Defines a single voice within an instrument
 
 Each note that gets played is assigned a voice for its duration. The voice manages all of the parameters of the note
@@ -14,7 +38,7 @@ and provides an interfaces for starting and releasing the note and for querying
 */
 pub struct Voice {
     // The oscillator
-    osc: Phasor,
+    osc: Oscillator,
     // The envelope
     envelope: Envelope,
     // The overall level of the note (range of [0:1])
@@ -43,11 +67,31 @@ impl Voice {
         dec: f32,
         sus: f32,
         rel: f32,
+    ) -> Self {
+        Voice::with_oscillator(
+            system,
+            Oscillator::Single(Phasor::new(system, table)),
+            att,
+            dec,
+            sus,
+            rel,
+        )
+    }
+
+    /* Creates a new Voice around the given oscillator
+     */
+    fn with_oscillator(
+        system: &Arc<System>,
+        osc: Oscillator,
+        att: f32,
+        dec: f32,
+        sus: f32,
+        rel: f32,
     ) -> Self {
         let gate = envelope::create_gate(0.0);
         Voice {
             // system: system.clone(),
-            osc: Phasor::new(system, table),
+            osc,
             envelope: Envelope::new(system, att, dec, sus, rel, &gate),
             level: envelope::read_gate(&gate),
             pitch: 0.0,
@@ -55,6 +99,112 @@ impl Voice {
         }
     }
 
+    /** Creates a new Voice with a hard synced oscillator pair
+
+    The master oscillator plays at the note's pitch and isn't heard. The voice's output comes from the slave, which
+    plays at `ratio` times the note's pitch and is reset every time the master completes a cycle.
+
+    # Arguments
+    * `system`: The System parameters
+    * `table`:  The wavetable that the voice will use
+    * `ratio`:  The slave oscillator's frequency, as a ratio of the master's
+    * `att`:    The starting attack value (in seconds)
+    * `dec`:    The starting decay value (in seconds)
+    * `sus`:    The starting sustain value
+    * `rel`:    The starting release value (in seconds)
+    */
+    pub fn new_sync(
+        system: &Arc<System>,
+        table: &Arc<Wavetable>,
+        ratio: f32,
+        att: f32,
+        dec: f32,
+        sus: f32,
+        rel: f32,
+    ) -> Self {
+        let sync = HardSync {
+            master: Phasor::new(system, table),
+            slave: Phasor::new(system, table),
+            ratio,
+            masterbuf: vec![0.0; system.bufsize()],
+            syncbuf: vec![None; system.bufsize()],
+        };
+        Voice::with_oscillator(system, Oscillator::Sync(sync), att, dec, sus, rel)
+    }
+
+    /** Creates a new Voice that plays a unison stack of oscillators
+
+    # Arguments
+    * `system`: The System parameters
+    * `unison`: The unison stack that the voice will play
+    * `att`:    The starting attack value (in seconds)
+    * `dec`:    The starting decay value (in seconds)
+    * `sus`:    The starting sustain value
+    * `rel`:    The starting release value (in seconds)
+    */
+    pub fn new_unison(
+        system: &Arc<System>,
+        unison: Unison,
+        att: f32,
+        dec: f32,
+        sus: f32,
+        rel: f32,
+    ) -> Self {
+        Voice::with_oscillator(
+            system,
+            Oscillator::Unison(Box::new(unison)),
+            att,
+            dec,
+            sus,
+            rel,
+        )
+    }
+
+    /** Sets the slave oscillator's frequency ratio
+
+    This has no effect if the voice wasn't created with [`Voice::new_sync`].
+
+    # Arguments
+    * `ratio`: The slave oscillator's frequency, as a ratio of the master's
+    */
+    pub fn set_sync_ratio(&mut self, ratio: f32) {
+        if let Oscillator::Sync(sync) = &mut self.osc {
+            sync.ratio = ratio;
+        }
+    }
+
+    /** Returns the voice's unison stack, so that its parameters can be changed
+
+    This returns None if the voice wasn't created with [`Voice::new_unison`].
+    */
+    pub fn unison_mut(&mut self) -> Option<&mut Unison> {
+        match &mut self.osc {
+            Oscillator::Unison(unison) => Some(unison),
+            _ => None,
+        }
+    }
+
+    /** Replaces the wavetable that the voice plays
+
+    If the voice is playing a note, then it crossfades to the new table. Otherwise, it switches right away. A hard
+    synced voice only crossfades its slave oscillator, since the master isn't heard.
+
+    # Arguments
+    * `table`: The new wavetable
+    * `fade`:  The length of the crossfade (in samples)
+    */
+    pub fn set_table(&mut self, table: &Arc<Wavetable>, fade: usize) {
+        let fade = if self.active() { fade } else { 0 };
+        match &mut self.osc {
+            Oscillator::Single(osc) => osc.set_table(table, fade),
+            Oscillator::Sync(sync) => {
+                sync.master.set_table(table, 0);
+                sync.slave.set_table(table, fade);
+            }
+            Oscillator::Unison(unison) => unison.set_table(table, fade),
+        }
+    }
+
     /** Start the attack stage of a note
 
     # Arguments
@@ -64,7 +214,14 @@ impl Voice {
     pub fn note_on(&mut self, level: f32, pitch: f32) {
         self.pitch = pitch;
         self.level = level;
-        self.osc.zero();
+        match &mut self.osc {
+            Oscillator::Single(osc) => osc.zero(),
+            Oscillator::Sync(sync) => {
+                sync.master.zero();
+                sync.slave.zero();
+            }
+            Oscillator::Unison(unison) => unison.reset(),
+        }
         envelope::write_gate(&self.gate, level);
     }
 
@@ -80,10 +237,68 @@ impl Voice {
     * `outbuf`: The buffer in which to return the calculated samples
     */
     pub fn perform(&mut self, outbuf: &mut [f32]) {
-        self.osc.perform(outbuf, self.pitch, 0.0);
+        self.perform_osc(outbuf);
+        self.finish(&mut [outbuf]);
+    }
+
+    /** Calculates the next set of output samples in stereo
+
+    A unison voice is panned by its stack's stereo spread (see [`Unison::set_spread`]). Other voices are in the middle,
+    with the same samples in both channels.
+
+    # Arguments:
+    * `left`:  The buffer in which to return the left channel
+    * `right`: The buffer in which to return the right channel. This must be the same length as `left`.
+
+    # Panics
+
+    This function will panic if the `right` buffer length is shorter than the `left` length.
+    */
+    pub fn perform_stereo(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let right = &mut right[..left.len()];
+        match &mut self.osc {
+            Oscillator::Unison(unison) => unison.perform_stereo(left, right, self.pitch),
+            _ => {
+                self.perform_osc(left);
+                right.copy_from_slice(left);
+            }
+        }
+        self.finish(&mut [left, right]);
+    }
+
+    /* Renders the oscillator into a single channel
+     */
+    fn perform_osc(&mut self, outbuf: &mut [f32]) {
+        match &mut self.osc {
+            Oscillator::Single(osc) => osc.perform(outbuf, self.pitch, 0.0),
+            Oscillator::Sync(sync) => {
+                let len = outbuf.len();
+                let syncbuf = &mut sync.syncbuf[..len];
+                sync.master
+                    .perform_master(&mut sync.masterbuf[..len], self.pitch, 0.0, syncbuf);
+                sync.slave
+                    .perform_slave(outbuf, self.pitch * sync.ratio, 0.0, syncbuf);
+            }
+            Oscillator::Unison(unison) => unison.perform(outbuf, self.pitch),
+        }
+    }
+
+    /* Applies the envelope to the channels that the oscillator rendered
+     */
+    fn finish(&mut self, channels: &mut [&mut [f32]]) {
         let envelope = self.envelope.perform_control();
-        for out in outbuf {
-            *out *= envelope * self.level;
+        for channel in channels.iter_mut() {
+            for out in channel.iter_mut() {
+                *out *= envelope * self.level;
+            }
+        }
+        if !self.active() {
+            // A fade that outlasts the note would hold on to its table
+            match &mut self.osc {
+                Oscillator::Single(osc) => osc.end_fade(),
+                Oscillator::Sync(sync) => sync.slave.end_fade(),
+                Oscillator::Unison(unison) => unison.end_fade(),
+            }
         }
     }
 
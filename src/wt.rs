@@ -1,21 +1,27 @@
+use super::expr::Expr;
 use super::system::System;
 use super::utils;
 use std::f32::consts::PI;
+use std::io::{Read, Seek};
 use std::num::Wrapping;
+use std::ops::Range;
 use std::sync::Arc;
 
+#[cfg(target_arch = "x86_64")]
+mod simd;
+mod spectral;
+
 /** An interpolating wavetable oscillator
 
 Because of the requirements of the interpolation algorithm used, there are a couple of limits on the acceptable table
-size:
+size for the fastest tables:
 1. it must be a power of two
-2. it can be no larger than 131071 (or 2^17).
+2. it can be no larger than 2^32 (and no larger than 2^16 for a [`Phasor`] with a [`PhaseMode::Narrow`] phase).
 
 This may have some implications if you are trying to build a wavetable from a sampled waveform, but if your sample does
-not satisfy these requirements (likely), you simply have to resample it so that it does. In the future, this library will
-provide a function that can do this for you, but for now, you'll either need to do this programatically yourself, or
-you'll have to pre-process your sample with your DAW or with some other audo processing tool, such as
-[Audacity](https://www.audacityteam.org).
+not satisfy these requirements (likely), you can either resample it so that it does (which is what
+[`Wavetable::from_sndfile`] does), or you can build a table of exactly the length that you have (see
+[Tables of any length](#tables-of-any-length) below).
 
 Note: The algorithms used for this implementation were based off of supercollider's Osc Ugen see
 [here](https://github.com/supercollider/supercollider/blob/cea67fcd49eb899366d6f7252c70157c5bc8b18f/server/plugins/OscUGens.cpp#L1247)
@@ -62,7 +68,7 @@ The Wavetable class uses two tables under the hood in order to eliminate a few o
 runtime. Instead of directly storing the table values, each table stores a pre-calculated portion of the interpolation
 calculation, saving us a subtraction, a register copy (or an index operation), and a floating point subtraction in the
 calculation of 0.m (essentially, we get to calculate 1.m instead, which turns out to be cheaper). In exchange, we double
-the memory footprint of the wavetable (which can't be more than 130kB extra). Here's a quick rundown of the table
+the memory footprint of each mip level (see below). Here's a quick rundown of the table
 equations (where `m` is the fractional part of the phase, `val1` is `table[n]` and `val2` is `table[n+1]` and `tbl1/2` are
 the pre-conditioned tables that Wavetable stores):
 
@@ -77,14 +83,292 @@ out = tbl1 + (tbl2 * (1 + m))
     = 2a - b + (b - a) + (b - a) * m
     = a + (b - a) * m
 ```
+
+# Interpolation modes
+
+Linear interpolation is the default, and it is the fastest mode, but it isn't the only one. On small tables it rolls off
+the upper harmonics and adds images of them, so a Wavetable can be switched to another [`Interpolation`] mode with
+[`Wavetable::with_interpolation`]. Like the linear mode, each of the other modes pre-calculates a table layout that
+suits it:
+
+* `Truncate` stores `x[n]` and reads it without interpolating, for a lo-fi, stepped sound.
+* `Cubic` stores the four coefficients of the Hermite polynomial between `x[n]` and `x[n+1]` side by side, so that a
+  read is a single index followed by a polynomial evaluation.
+* `Sinc` stores the table with enough wrapped samples on either end that the taps of the windowed-sinc filter never
+  need to be masked, and shares a bank of pre-calculated filter kernels (one for each of a set of fractional phases)
+  between all of the table's levels.
+
+# Tables of any length
+
+Resampling a cycle to a power of two length smears its samples, so [`Wavetable::new_exact`] and
+[`Wavetable::from_sndfile_exact`] build tables that keep the length that they're given. These tables can't mask the phase
+to find the index. Instead, a [`Phasor`] keeps its phase within the table's length by adding or subtracting the length
+whenever the phase steps past either end, which is cheaper than a modulo operation. Everything else (the fractional
+phase, the pre-calculated table layouts, the mip levels and the frames) works exactly as it does for the power of two
+tables, and power of two tables built this way still use the fast path.
+
+Audio-rate modulation can step the phase by more than the whole table in a single sample, so [`Phasor::perform_fm`] and
+[`Phasor::perform_mod`] fall back to a modulo operation for those steps (and only for those).
+
+The wrap needs some headroom in the fixed-point phase, so these tables can be no longer than 2^30 samples, and a
+[`PhaseMode::Narrow`] phase can only be used for tables of this kind of up to 2^14 samples.
+
+# Band limiting
+
+A table with bright harmonics will alias once it's played fast enough that those harmonics land above the Nyquist
+frequency. To prevent this, the Wavetable builds a chain of mip levels when it's created. Level 0 is the table exactly as
+it was given, and level `n` is the same waveform with every harmonic above `len / 2^(n+1)` removed (using an FFT). The
+levels all have the same length, so a [`Phasor`] can move between them without changing its phase, and it picks (or
+crossfades between) them based on how quickly its phase is moving (see [`MipMode`]). There are `log2(len)` levels, so
+the memory footprint of a table grows by that factor.
+
+# Frames
+
+A Wavetable can hold several frames, which are single cycles of the same length laid end-to-end (see
+[`Wavetable::new_frames`]). A [`Phasor`] can scan through the frames with a position in the range of [0, 1], where 0
+reads the first frame and 1 reads the last one. Positions in between linearly interpolate between the two closest
+frames, so sweeping the position morphs smoothly from one waveform to the next. Each frame has its own mip levels and
+its own pair of pre-conditioned tables, so the cost of a read is the same for every frame.
+
+Wavetable files from synthesizers like Serum store their frames this way, with a `clm ` chunk that names the frame
+length. [`Wavetable::from_sndfile_clm`] reads that chunk (or takes the frame length as a hint, for files without one) and
+splits the file into frames.
+
+Frames can also be sliced out of an ordinary recording. [`Wavetable::from_sndfile_sliced`] tracks the pitch through the
+whole file and takes evenly spaced cycles from it, so that scanning the table follows the recording's timbre as it
+changes, like a vowel opening up.
+
+# Additive tables
+
+Tables don't have to come from recordings. [`Wavetable::from_harmonics`] synthesizes a cycle from the amplitude and phase
+of each of its harmonics with an inverse FFT, and [`Wavetable::from_shape`] uses it to build the standard waveforms (see
+[`Shape`]). These tables only hold harmonics that fit below the table's own Nyquist frequency, so they're band-limited
+from the start, and the mip levels take care of the rest.
+
+# Expressions
+
+Tables can also be written as math expressions (see [`Expr`] for the syntax), which is handy for tables that live in
+patch files. [`Wavetable::from_expr`] and [`Wavetable::from_expr_frames`] evaluate an expression of the phase `x` at
+every sample of every frame, and [`Wavetable::from_harmonic_expr`] evaluates an expression of the harmonic number `h` to
+get the amplitude of every harmonic, and then builds each frame additively.
+
+```
+# use wavetable::wt::Wavetable;
+// A sine that gains a third harmonic over 16 frames
+let wt = Wavetable::from_expr_frames("sin(x) + 0.3*sin(3*x)*frame", 2048, 16).unwrap();
+// A sine whose third harmonic swells and fades again over the frames
+let wt = Wavetable::from_expr_frames("sin(x) + 0.3*sin(3*x)*env(frame)", 2048, 16).unwrap();
+// A saw, built from its harmonics
+let saw = Wavetable::from_harmonic_expr("2 / (pi * h)", 2048, 1).unwrap();
+```
+
+# Spectral processing
+
+A table can be reshaped through the spectrum of each of its frames, with operations like [`Wavetable::lowpass`],
+[`Wavetable::tilt`], [`Wavetable::odd_even`] and [`Wavetable::stretch`], or cleaned up with [`Wavetable::remove_dc`] and
+[`Wavetable::normalize_peak`]. These are offline operations: each one builds a new Wavetable (with all of its mip levels),
+so they shouldn't be run on the audio thread.
+
+```
+# use wavetable::wt::{Shape, Wavetable};
+// A mellow saw with only its odd harmonics
+let wt = Wavetable::from_shape(Shape::Saw, 2048).lowpass(8.0, 12.0).odd_even(-1.0).normalize_peak(1.0);
+```
 */
 pub struct Wavetable {
-    // Stores 2 * x[n] - x[n+1]
+    // The table's frames, all of the same length
+    frames: Vec<Frame>,
+    // Masks the valid integral index bits. This is all ones for tables that aren't a power of two long.
+    lomask: usize,
+    // Whether the table is a power of two long
+    pow2: bool,
+    // The interpolation mode that the frames are laid out for
+    interpolation: Interpolation,
+}
+
+/* A single cycle of a Wavetable's waveform, along with its mip levels
+ */
+struct Frame {
+    // The mip levels. Level 0 is the frame as it was given and each following level holds half as many harmonics
+    levels: Vec<Level>,
+}
+
+/* A single, band-limited version of a Frame's waveform, laid out for one of the interpolation modes. Each mode only
+ * fills in the fields that it reads (see Layout), and leaves the rest empty.
+ */
+struct Level {
+    // The mode that the level is laid out for
+    interpolation: Interpolation,
+    // Linear: stores 2 * x[n] - x[n+1]
+    // Truncate: stores x[n]
+    // Sinc: stores x[n - SINC_TAPS/2 + 1], with SINC_TAPS - 1 extra samples so that the taps can run past the end
     table1: Vec<f32>,
-    // Stores x[n + 1] - x[n]
+    // Linear: stores x[n + 1] - x[n]
     table2: Vec<f32>,
-    // Masks the valid integral index bits
-    lomask: i32,
+    // Cubic: stores the Hermite polynomial coefficients for the segment between x[n] and x[n+1]
+    coefs: Vec<[f32; 4]>,
+    // Sinc: the filter kernels, shared between all levels
+    kernel: Option<Arc<SincKernel>>,
+}
+
+/* Reads a Level that is laid out for one of the interpolation modes. The Phasor's loops are generic over the layout, so
+ * that the mode is picked once per block rather than once per sample, and the linear loop reads its two tables without
+ * any branches, just as it always has.
+ */
+trait Layout {
+    // Reads the level at the given index and fractional phase (stored as 1.m, see Phasor)
+    fn read(level: &Level, index: usize, frac1: f32) -> f32;
+}
+
+struct LinearLayout;
+struct TruncateLayout;
+struct CubicLayout;
+struct SincLayout;
+
+/* A bank of windowed-sinc filter kernels, one for each of SINC_PHASES fractional phases
+ */
+struct SincKernel {
+    // The tap weights for each phase, plus one extra row for a phase of 1.0
+    rows: Vec<[f32; SINC_TAPS]>,
+    // Stores rows[n + 1] - rows[n]
+    deltas: Vec<[f32; SINC_TAPS]>,
+}
+
+const SINC_TAPS: usize = 8;
+const SINC_PHASES: usize = 512;
+
+/** Selects how a Wavetable interpolates between its samples
+
+See the [`Wavetable`] documentation for more about how each mode is calculated.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// No interpolation. The sample at or below the phase is used.
+    Truncate,
+    /// Linear interpolation between the two surrounding samples. This is the default, and the fastest mode.
+    Linear,
+    /// 4-point, 3rd-order Hermite interpolation
+    Cubic,
+    /// 8-point windowed-sinc interpolation
+    Sinc,
+}
+
+/** A standard waveform that [`Wavetable::from_shape`] can build
+
+The shapes are built from their Fourier series, so they have the ripples of a band-limited waveform rather than sharp
+corners, and none of them has a DC offset.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    /// A sine wave
+    Sine,
+    /// A sawtooth that ramps up from -1 to 1 over the cycle
+    Saw,
+    /// A square wave that is 1 for the first half of the cycle and -1 for the second
+    Square,
+    /// A triangle wave that starts at 0, peaks at 1 a quarter of the way through the cycle and bottoms out at -1 three
+    /// quarters of the way through
+    Triangle,
+    /// A pulse wave that is high for the given fraction of the cycle (in the range of (0, 1)) and low for the rest. The
+    /// two levels are 2 apart, and centered so that the average is 0, so a width of 0.5 is the same as [`Shape::Square`].
+    Pulse(f32),
+}
+
+/** Selects how a Phasor chooses between the mip levels of its Wavetable
+
+Both modes only ever read levels that are band-limited enough for the current frequency, so neither will alias.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MipMode {
+    /// Reads the single brightest level that won't alias. This is the cheapest mode, but the level (and so the
+    /// brightness) changes in steps during a pitch sweep.
+    Pick,
+    /// Crossfades between two adjacent levels based on the frequency, so that sweeps change smoothly.
+    Crossfade,
+}
+
+/** Selects the fixed-point format of a Phasor's phase
+
+See the [`Phasor`] documentation for the details of each format.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PhaseMode {
+    /// A 32-bit phase with 16 fractional bits. This is the default for tables of up to 2^16 samples.
+    Narrow,
+    /// A 64-bit phase with 32 fractional bits, for long tables and precise tuning.
+    Wide,
+}
+
+/** Selects how a Phasor warps its phase before reading its Wavetable
+
+Each mode is controlled by an amount, which is given to [`Phasor::set_warp_amount`] or [`Phasor::perform_warp_fm`]. An
+amount of 0 leaves the phase alone (except for [`Warp::Quantize`], which then quantizes it to the table's own samples).
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Warp {
+    /// No warp
+    Off,
+    /// Casio CZ-style phase distortion. The first half of the table is read over a shorter (or longer) part of the cycle
+    /// and the second half over the rest. The amount is in the range of [-1, 1], and positive amounts shorten the first
+    /// half.
+    Bend,
+    /// PWM-style squeeze. The whole table is read over the start of the cycle and the rest of the cycle holds the value
+    /// at the start of the table. The amount is in the range of [0, 1], and squeezes the table into as little as 5% of
+    /// the cycle.
+    Squeeze,
+    /// Reads the table forward and then backward. The amount is in the range of [0, 1], and moves the turning point
+    /// from the end of the cycle (at 0) to the middle of it (at 1). Small amounts make the return trip very steep, so
+    /// they read a darker mip level.
+    Mirror,
+    /// Reads the table several times per cycle, like a hard synced oscillator. The amount is in the range of [0, 1],
+    /// and sweeps the number of repeats exponentially from 1 up to 16.
+    Sync,
+    /// Quantizes the phase to a number of steps. The amount is in the range of [0, 1], and lowers the number of steps
+    /// exponentially from the table length down to 2.
+    Quantize,
+}
+
+/** Selects how [`ImportOptions`] sets the level of a loaded table
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Normalize {
+    /// Keeps the level from the file
+    Off,
+    /// Scales the table so that its largest sample has the given magnitude
+    Peak(f32),
+    /// Scales the table so that its RMS level is the given level
+    Rms(f32),
+}
+
+/** Cleans up a waveform as it's loaded from an audio file (see [`Wavetable::from_sndfile_with`])
+
+A cycle that's cut from a recording usually has some DC offset, an arbitrary level, and a start that falls anywhere in
+the cycle. A DC offset and a level that's too high make voices clip when they're summed, and a level that's too low makes
+them hard to hear. The start matters because voices reset their phase to zero on every note, so a table that doesn't
+start at a zero crossing clicks at the start of each note.
+
+The options are applied in order: the DC offset is removed, then the table is rotated, and then it's normalized. The
+seam is only used when a single cycle is trimmed from the file, and it's joined before the other options are applied.
+The default options leave the table alone.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImportOptions {
+    /// Whether to remove the table's DC offset
+    pub remove_dc: bool,
+    /// Whether to rotate the table so that it starts at the rising zero crossing with the steepest slope
+    pub rotate: bool,
+    /// How to set the table's level
+    pub normalize: Normalize,
+    /// How to join the end of a cycle that's trimmed from the file to its start (see [`utils::extract_cycle`])
+    pub seam: utils::Seam,
+}
+
+/* The phase accumulator, in one of the PhaseMode formats
+ */
+#[derive(Clone, Copy)]
+enum Phase {
+    Narrow(Wrapping<i32>),
+    Wide(Wrapping<i64>),
 }
 
 /** Generates a signal from a Wavetable by sweeping a phase across the table and giving the value at each phase sample.
@@ -92,7 +376,7 @@ pub struct Wavetable {
 The phase is tracked using a 32-bit, fixed-point value (signed to enable negative frequencies). Using a fixed-point value
 prevents the noise and modulation that floating point precision errors would introduce. The upshot of this is that the phase
 will change at a constant, predictable rate, but that the input frequencies and phase offsets (which are floating point) will
-be quantized (notice how both of these are cast to i32 values in Wavetable::perform()).
+be quantized (notice how both of these are cast to fixed-point values in Phasor::perform()).
 
 # Phase calculations
 
@@ -137,26 +421,131 @@ the significand, the exponent is 0, and the sign is positive. This creates a bin
 representation, equals `1.m`, `m` being the phase's fractional component. If we just wanted the pure fractional part then
 we would have to subtract the 1.0 from the value, but because of the way that the two tables were pre-calculated, this
 value can simply be multiplied by the value at index `n` of `table2`.
+
+# Wide phase
+
+The 32-bit phase (which is [`PhaseMode::Narrow`]) has two drawbacks. First, it only has 16 integral bits, so it can't index
+a table longer than 2^16 samples. Second, the phase increment is quantized to 1/65536th of a table sample, which means
+that low notes on small tables can be noticeably out of tune (20Hz on a 16 sample table at 48kHz is off by about 3.5
+cents). A Phasor can instead use a [`PhaseMode::Wide`] phase, which is a 64-bit, fixed-point value with 32 fractional
+bits. This indexes tables of up to 2^32 samples and quantizes the increment finely enough that the tuning error is a tiny
+fraction of a cent. The frequency and phase inputs are also converted to the fixed-point phase in double precision, so
+that the 32 fractional bits aren't wasted.
+
+The index is calculated the same way for both modes, and so is the fractional value, except that the wide phase shifts
+its top 23 fractional bits down into the significand instead of shifting its 16 fractional bits up.
+
+# Frequency modulation
+
+Beyond the raw frequency and phase buffers that [`Phasor::perform_fm`] takes, [`Phasor::perform_mod`] takes three
+modulation inputs that are scaled by the phasor itself:
+1. A linear FM input, which is multiplied by the phasor's FM depth (in Hz, see [`Phasor::set_fm_depth`]) and added to the
+   frequency. The sum may go negative, in which case the phase simply runs backward ("through-zero" FM).
+2. An exponential FM input, in semitones, which scales the frequency before the linear FM is added.
+3. A phase modulation input, which is multiplied by the phasor's PM index (in radians, see
+   [`Phasor::set_pm_index`]) and added to the phase.
+
+The phase modulation input is what DX-style FM synthesis uses. A modulator is just another phasor, running at some ratio
+of the carrier's frequency, whose output feeds the carrier's phase modulation input:
+
+```
+# use wavetable::wt::{Wavetable, Phasor};
+# use wavetable::system::System;
+# use std::sync::Arc;
+let system = Arc::new(System::new(48000.0, 1, 256));
+let sine = Vec::from_iter((0..1024).map(|i| (i as f32 * std::f32::consts::TAU / 1024.0).sin()));
+let wt = Arc::new(Wavetable::new(&sine));
+
+let mut modulator = Phasor::new(&system, &wt);
+let mut carrier = Phasor::new(&system, &wt);
+carrier.set_pm_index(2.0);
+
+let (freq, ratio) = (220.0, 3.5);
+let mut modbuf = [0.0f32; 256];
+let mut outbuf = [0.0f32; 256];
+modulator.perform(&mut modbuf, freq * ratio, 0.0);
+carrier.perform_mod(&mut outbuf, freq, None, None, Some(&modbuf));
+```
+
+# Phase warp
+
+A phasor can remap its phase before reading the table (see [`Warp`]), which turns one table into a whole family of
+timbres. The warp is applied after any phase offset and the mip level is chosen for the steepest part of the warp, so a
+squeezed or synced table won't alias. The warp amount is a control-rate setting (see [`Phasor::set_warp_amount`]), but
+it can also be modulated sample by sample with [`Phasor::perform_warp_fm`]. The hard sync functions ignore the warp.
+
+# Vectorization
+
+On x86_64 CPUs with AVX2, a phasor with a narrow phase that reads a power-of-two table with linear interpolation and no
+warp calculates 8 output samples at a time, gathering the values for all 8 phases from `table1` and `table2` at once.
+The vectorized loops perform exactly the same arithmetic as the scalar ones, so the output doesn't depend on which one
+runs. The CPU is checked at runtime, so the same build runs everywhere.
+
+# Hard sync
+
+Two Phasors can be hard synced. The master is run with [`Phasor::perform_master`], which fills a sync buffer with an
+event for each output sample after which its phase wrapped. Each event is the fraction of the way to the next sample at
+which the wrap happened. The slave is run with [`Phasor::perform_slave`] on the same sync buffer and resets its phase
+to zero at exactly that point in time, so the timing of the resets isn't quantized to the sample rate.
+
+Simply resetting the slave's phase would put a hard step in its output, which aliases badly. Instead, the height of
+the step is measured at the moment of the reset and a two-sample polyBLEP (a polynomial approximation of a band-limited
+step) is used to smooth it across the samples on either side of it.
+
+# Swapping tables
+
+A Phasor's table can be replaced while it's playing with [`Phasor::set_table`], which only clones an `Arc`, so it's safe
+to call on the audio thread. The phase carries over to the new table (scaled to its length), and the switch can be
+smoothed with a crossfade. During the crossfade, the phasor renders both tables from the same phase and mixes them, so it
+costs about twice as much. The old table is let go of as soon as the crossfade is over. See [`crate::swap`] for handing
+new tables to the audio thread, and for making sure that they're freed somewhere else.
 */
 pub struct Phasor {
     // system: Arc<System>,
     // Wavetable reference
     table: Arc<Wavetable>,
-    // Fixed-point phase, with 16 fractional bits
-    phase: Wrapping<i32>,
+    // Fixed-point phase
+    phase: Phase,
     // Converts radial phase values to table index increments
-    radtoinc: f32,
+    radtoinc: f64,
     // Converts frequency (in cycles per second) to table index increments per output samples
-    cpstoinc: f32,
+    cpstoinc: f64,
+    // How to choose the wavetable's mip level
+    mipmode: MipMode,
+    // The polyBLEP correction that a sync reset left for the next output sample
+    blep: f32,
+    // The frequency deviation (in Hz) of a full-scale linear FM input
+    fmdepth: f32,
+    // The phase deviation (in radians) of a full-scale phase modulation input
+    pmindex: f32,
+    // How the phase is warped before reading the table
+    warp: Warp,
+    // The control-rate warp amount
+    warp_amount: f32,
+    // The table that's being crossfaded out, after a table swap
+    fade: Option<Fade>,
+    // Holds the old table's output during a crossfade
+    fadebuf: Vec<f32>,
     // sampledur: f32
 }
 
+/* A crossfade from a Phasor's old table to its current one
+ */
+struct Fade {
+    // The old table
+    table: Arc<Wavetable>,
+    // The length of the crossfade, in samples
+    length: usize,
+    // The number of samples left in the crossfade
+    remaining: usize,
+}
+
 impl Wavetable {
     /** Creates a new Wavetable
 
     # Arguments
 
-    * `table`:     A slice that holds the values for the table. The length must be a power of two and no more than 2^17.
+    * `table`:     A slice that holds the values for the table. The length must be a power of two and no more than 2^32.
 
     # Examples
 
@@ -168,163 +557,2149 @@ impl Wavetable {
     ```
     */
     pub fn new(table: &[f32]) -> Self {
-        let size = table.len();
-        assert_eq!(
-            size & (size - 1),
-            0,
+        Wavetable::new_frames(table, table.len())
+    }
+
+    /** Creates a new Wavetable with multiple frames
+
+    # Arguments
+
+    * `table`:     A slice that holds the frames, one after another. The length must be a multiple of `frame_len`.
+    * `frame_len`: The length of each frame. This must be a power of two and no more than 2^32.
+
+    # Examples
+
+    ```
+    # use wavetable::wt::Wavetable;
+    // Create a wavetable that morphs from a ramp to a constant
+    let mut table = Vec::from_iter((0..128).map(|v| -> f32 {v as f32}));
+    table.extend([64.0; 128]);
+    let wt = Wavetable::new_frames(&table, 128);
+    assert_eq!(wt.frames(), 2);
+    ```
+    */
+    pub fn new_frames(table: &[f32], frame_len: usize) -> Self {
+        Wavetable::build(table, frame_len, Interpolation::Linear, false)
+    }
+
+    /** Creates a new Wavetable of any length
+
+    If the length happens to be a power of two then the table is identical to one created by [`Wavetable::new`].
+    Otherwise, the phasors that read it will wrap their phases without masking (see the
+    [Tables of any length](#tables-of-any-length) section for more).
+
+    # Arguments
+
+    * `table`: A slice that holds the values for the table. The length can be no more than 2^32 if it's a power of two,
+               and no more than 2^30 otherwise.
+
+    # Examples
+
+    ```
+    # use wavetable::wt::Wavetable;
+    let table = Vec::from_iter((0..100).map(|v| -> f32 {v as f32}));
+    let wt = Wavetable::new_exact(&table);
+    assert_eq!(wt.len(), 100);
+    ```
+    */
+    pub fn new_exact(table: &[f32]) -> Self {
+        Wavetable::new_frames_exact(table, table.len())
+    }
+
+    /** Creates a new Wavetable with multiple frames of any length
+
+    # Arguments
+
+    * `table`:     A slice that holds the frames, one after another. The length must be a multiple of `frame_len`.
+    * `frame_len`: The length of each frame. This can be no more than 2^32 if it's a power of two, and no more than 2^30
+                   otherwise.
+    */
+    pub fn new_frames_exact(table: &[f32], frame_len: usize) -> Self {
+        Wavetable::build(table, frame_len, Interpolation::Linear, true)
+    }
+
+    /** Rebuilds the Wavetable for a different interpolation mode
+
+    # Examples
+
+    ```
+    # use wavetable::wt::{Interpolation, Wavetable};
+    let table = Vec::from_iter((0..128).map(|v| -> f32 {v as f32}));
+    let wt = Wavetable::new(&table).with_interpolation(Interpolation::Cubic);
+    assert_eq!(wt.interpolation(), Interpolation::Cubic);
+    ```
+    */
+    pub fn with_interpolation(self, interpolation: Interpolation) -> Self {
+        if interpolation == self.interpolation {
+            return self;
+        }
+        let mut table = Vec::with_capacity(self.len() * self.frames());
+        for frame in self.frames.iter() {
+            table.extend(frame.levels[0].samples());
+        }
+        Wavetable::build(&table, self.len(), interpolation, true)
+    }
+
+    /* Returns an error if a frame length that came from the caller can't be used by Wavetable::new_frames, rather than
+    letting build panic on it
+    */
+    fn check_frame_len(frame_len: usize) -> Result<(), std::io::Error> {
+        if !frame_len.is_power_of_two() || frame_len as u64 > 1 << 32 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!(
+                    "Wavetable size must be a power of two and no more than 2^32. Got {}",
+                    frame_len
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    fn build(table: &[f32], frame_len: usize, interpolation: Interpolation, exact: bool) -> Self {
+        let size = frame_len;
+        let pow2 = size.is_power_of_two();
+        assert!(
+            exact || pow2,
             "Wavetable size must be a power of two. Got {}",
             size
         );
         assert!(
-            size <= 131072,
-            "Phase computation is not precise for wavetables longer than (2**17)"
+            size as u64 <= 1 << 32,
+            "Phase computation is not precise for wavetables longer than (2**32)"
+        );
+        assert!(
+            pow2 || size <= WIDE_MAX_EXACT_LEN,
+            "Wavetables that aren't a power of two long can be no longer than (2**30). Got {}",
+            size
+        );
+        assert!(
+            !table.is_empty() && table.len() % size == 0,
+            "Wavetable of {} samples can't be split into frames of {}",
+            table.len(),
+            size
         );
 
-        let mut wt = Wavetable {
-            table1: Vec::with_capacity(size),
-            table2: Vec::with_capacity(size),
-            lomask: (size - 1) as i32,
+        let kernel = match interpolation {
+            Interpolation::Sinc => Some(Arc::new(SincKernel::new())),
+            _ => None,
         };
 
-        // Create the tables
-        for i in 0..(size - 1) {
-            let val1 = table[i];
-            let val2 = table[i + 1];
-            wt.table1.push(2.0 * val1 - val2);
-            wt.table2.push(val2 - val1);
+        Wavetable {
+            frames: Vec::from_iter(
+                table
+                    .chunks(size)
+                    .map(|frame| Frame::new(frame, interpolation, &kernel)),
+            ),
+            lomask: if pow2 { size - 1 } else { usize::MAX },
+            pow2,
+            interpolation,
         }
-        let val1 = table[size - 1];
-        let val2 = table[0];
-        wt.table1.push(2.0 * val1 - val2);
-        wt.table2.push(val2 - val1);
-        wt
     }
 
-    pub fn from_sndfile(path: &str, trim: bool) -> Result<Self, std::io::Error> {
-        let (mut table, _) = utils::read_sndfile(path)?;
-        if trim {
-            table = utils::best_waveform(&table)
-                .ok_or_else(|| {
-                    std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        format!("Failed to find acceptable waveform in {}", path),
-                    )
-                })?
-                .to_vec();
-        }
+    /** Creates a new Wavetable from the harmonics of a single cycle
 
-        let final_len = utils::next_pow_of_2(table.len());
+    See [`utils::additive`] for how the cycle is synthesized.
 
-        if final_len == table.len() {
-            Ok(Wavetable::new(&table))
-        } else {
-            Ok(Wavetable::new(&utils::resample(&table, final_len, true)))
-        }
-    }
+    # Arguments
 
-    pub fn len(&self) -> usize {
-        self.table1.len()
+    * `len`:        The length of the table. This must be a power of two and no more than 2^32.
+    * `amplitudes`: The amplitude of each harmonic, starting with the fundamental. Harmonics at or above `len / 2` are
+                    ignored.
+    * `phases`:     The phase of each harmonic (in radians), starting with the fundamental. Harmonics without a phase
+                    start at 0.
+
+    # Examples
+
+    ```
+    # use wavetable::wt::Wavetable;
+    // A square wave with its first three harmonics
+    let wt = Wavetable::from_harmonics(256, &[1.0, 0.0, 1.0 / 3.0, 0.0, 1.0 / 5.0], &[]);
+    ```
+    */
+    pub fn from_harmonics(len: usize, amplitudes: &[f32], phases: &[f32]) -> Self {
+        Wavetable::new(&utils::additive(len, amplitudes, phases))
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.len() == 0
+    /** Creates a new Wavetable that holds a band-limited standard waveform
+
+    # Arguments
+
+    * `shape`: The waveform
+    * `len`:   The length of the table. This must be a power of two and no more than 2^32.
+    */
+    pub fn from_shape(shape: Shape, len: usize) -> Self {
+        let (amplitudes, phases) = shape.harmonics(len / 2);
+        Wavetable::from_harmonics(len, &amplitudes, &phases)
     }
 
-    #[inline]
-    fn interpolate(&self, phase: i32) -> f32 {
-        let frac = phase_frac1(phase);
-        let index = ((phase >> XLOBITS1) & self.lomask) as usize;
-        self.table1[index] + (frac * self.table2[index])
+    /** Creates a new Wavetable from an expression of the phase
+
+    See the "Expressions" section above.
+
+    # Arguments
+
+    * `expr`: The expression
+    * `len`:  The length of the table. This must be a power of two and no more than 2^32.
+
+    # Errors
+
+    Returns an error if the expression can't be parsed or evaluated, or if the length isn't a power of two.
+    */
+    pub fn from_expr(expr: &str, len: usize) -> Result<Self, std::io::Error> {
+        Wavetable::from_expr_frames(expr, len, 1)
     }
-}
 
-const XLOBITS1: i32 = 16;
+    /** Creates a new Wavetable with multiple frames from an expression of the phase and the frame position
 
-impl Phasor {
-    /** Creates a new phasor for the given Wavetable
+    See the "Expressions" section above.
 
     # Arguments
-    * `system`: The System parameters
-    * `table`:  The wavetable to reference
 
-    # Returns
-    A Phasor that will generate signals from the given Wavetable.
+    * `expr`:   The expression
+    * `len`:    The length of each frame. This must be a power of two and no more than 2^32.
+    * `frames`: The number of frames
+
+    # Errors
+
+    Returns an error if the expression can't be parsed or evaluated, or if the length isn't a power of two.
     */
-    pub fn new(system: &Arc<System>, table: &Arc<Wavetable>) -> Self {
-        let sampledur = 1.0 / system.samplerate();
+    pub fn from_expr_frames(expr: &str, len: usize, frames: usize) -> Result<Self, std::io::Error> {
+        Wavetable::check_frame_len(len)?;
+        let table = Expr::parse(expr)?.waveform(len, frames.max(1))?;
+        Ok(Wavetable::new_frames(&table, len))
+    }
 
-        let size = table.len();
-        let sizef32 = size as f32;
-        Phasor {
-            // system: system.clone(),
-            table: table.clone(),
-            phase: Wrapping(0),
+    /** Creates a new Wavetable from an expression of the harmonic number and the frame position
 
-            // sampledur,
-            radtoinc: 65536.0 * sizef32 / (2.0 * PI),
-            cpstoinc: sizef32 * sampledur * 65536.0,
+    The expression gives the amplitude of each harmonic below `len / 2`. All of the harmonics start at a phase of 0. See
+    the "Expressions" section above.
+
+    # Arguments
+
+    * `expr`:   The expression
+    * `len`:    The length of each frame. This must be a power of two and no more than 2^32.
+    * `frames`: The number of frames
+
+    # Errors
+
+    Returns an error if the expression can't be parsed or evaluated, or if the length isn't a power of two.
+    */
+    pub fn from_harmonic_expr(
+        expr: &str,
+        len: usize,
+        frames: usize,
+    ) -> Result<Self, std::io::Error> {
+        Wavetable::check_frame_len(len)?;
+        let amplitudes = Expr::parse(expr)?.harmonics((len.max(1) - 1) / 2, frames.max(1))?;
+        let mut table = Vec::with_capacity(len * amplitudes.len());
+        for frame in amplitudes {
+            table.extend(utils::additive(len, &frame, &[]));
         }
+        Ok(Wavetable::new_frames(&table, len))
     }
 
-    /** Performs the wavetable oscillation operation with audio-rate frequency and/or phase modulation
+    /** Loads an audio file as a Wavetable
+
+    The table is resampled to the next power of two length (see [`Wavetable::new`]).
 
     # Arguments
 
-    * `outbuf`:  A buffer for storing the output waveform
-    * `freqin`:  A sample-by-sample frequency. This must be the same length as outbuf.
-    * `phasein`: A sample-by-sample offset phase, useful for phase modulation. This must be the same length as outbuf.
+    * `path`: The path to the audio file
+    * `trim`: Whether to find a single cycle of the waveform in the file (see [`utils::extract_cycle`]) and only use
+              that. Otherwise, the whole file (truncated to a power of two length) is used.
 
-    # Panics
+    # Errors
 
-    This function will panic if either the `freqin` or `phasein` buffer lengths are shorter than the `outbuf` length.
+    Returns an error if the file can't be read or if no cycle can be found in it.
     */
-    pub fn perform_fm(&mut self, outbuf: &mut [f32], freqin: &[f32], phasein: &[f32]) {
-        for i in 0..outbuf.len() {
-            let phaseoffset = self.phase + Wrapping((self.radtoinc * phasein[i]) as i32);
-            outbuf[i] = self.table.interpolate(phaseoffset.0);
-            self.phase += Wrapping((self.cpstoinc * freqin[i]) as i32);
-        }
+    pub fn from_sndfile(path: &str, trim: bool) -> Result<Self, std::io::Error> {
+        Wavetable::load_sndfile(path, trim, false, &ImportOptions::default())
     }
 
-    /** Sets the phase to zero
-     *
-     * This is useful to ensure that a new note starts on a zero-crossing (assuming that the table starts at 0)
-     */
-    pub fn zero(&mut self) {
-        self.phase = Wrapping(0);
+    /** Loads an audio file as a Wavetable without resampling it to a power of two length
+
+    This works just like [`Wavetable::from_sndfile`], except that the table keeps the exact samples from the file (or the
+    trimmed cycle, at its period rounded to the nearest sample), so it can be any length. See [`Wavetable::new_exact`].
+    */
+    pub fn from_sndfile_exact(path: &str, trim: bool) -> Result<Self, std::io::Error> {
+        Wavetable::load_sndfile(path, trim, true, &ImportOptions::default())
     }
 
-    /** Performs the wavetable oscillation operation with control-rate frequency and/or phase modulation
+    /** Loads an audio file as a Wavetable and cleans it up with the given options
+
+    This works just like [`Wavetable::from_sndfile`], except that the table is cleaned up after it's trimmed and
+    resampled. See [`ImportOptions`].
+    */
+    pub fn from_sndfile_with(
+        path: &str,
+        trim: bool,
+        options: &ImportOptions,
+    ) -> Result<Self, std::io::Error> {
+        Wavetable::load_sndfile(path, trim, false, options)
+    }
+
+    /** Loads an audio file as a Wavetable without resampling it, and cleans it up with the given options
+
+    This works just like [`Wavetable::from_sndfile_exact`], except that the table is cleaned up after it's trimmed. See
+    [`ImportOptions`].
+    */
+    pub fn from_sndfile_exact_with(
+        path: &str,
+        trim: bool,
+        options: &ImportOptions,
+    ) -> Result<Self, std::io::Error> {
+        Wavetable::load_sndfile(path, trim, true, options)
+    }
+
+    /** Loads an audio file that's held in memory as a Wavetable
+
+    This works just like [`Wavetable::from_sndfile`], but the file can be embedded in the program with `include_bytes!`.
 
     # Arguments
 
-    * `outbuf`:  A buffer for storing the output waveform
-    * `freqin`:  The frequency (in Hz)
-    * `phasein`: The phase offset (in radians)
+    * `data`: The contents of the audio file
+    * `trim`: Whether to find a single cycle of the waveform in the file (see [`utils::extract_cycle`]) and only use
+              that. Otherwise, the whole file (truncated to a power of two length) is used.
 
-    # Panics
+    # Errors
 
-    This function will panic if either the `freqin` or `phasein` buffer lengths are shorter than the `outbuf` length.
+    Returns an error if the audio can't be read or if no cycle can be found in it.
     */
-    pub fn perform(&mut self, outbuf: &mut [f32], freqin: f32, phasein: f32) {
-        for out in outbuf {
-            let phaseoffset = self.phase + Wrapping((self.radtoinc * phasein) as i32);
-            *out = self.table.interpolate(phaseoffset.0);
-            self.phase += Wrapping((self.cpstoinc * freqin) as i32);
-        }
+    pub fn from_bytes(data: &[u8], trim: bool) -> Result<Self, std::io::Error> {
+        Wavetable::from_bytes_with(data, trim, &ImportOptions::default())
     }
-}
 
-#[repr(C)]
-union PhaseConv {
-    iphase: i32,
-    fphase: f32,
+    /** Loads an audio file that's held in memory as a Wavetable and cleans it up with the given options
+
+    This works just like [`Wavetable::from_bytes`], except that the table is cleaned up after it's trimmed and
+    resampled. See [`ImportOptions`].
+    */
+    pub fn from_bytes_with(
+        data: &[u8],
+        trim: bool,
+        options: &ImportOptions,
+    ) -> Result<Self, std::io::Error> {
+        let (table, _) = utils::read_audio_bytes(data, utils::Channels::Mix)?;
+        Wavetable::load(table, "the audio data", trim, false, options)
+    }
+
+    /** Loads an audio file from a reader as a Wavetable
+
+    This works just like [`Wavetable::from_sndfile`], but the file can come from anywhere, like an archive or a socket.
+    The reader must hold the whole file, starting at its beginning (see [`utils::read_audio_from`]).
+
+    # Arguments
+
+    * `reader`: The reader that holds the audio file
+    * `trim`:   Whether to find a single cycle of the waveform in the file (see [`utils::extract_cycle`]) and only use
+                that. Otherwise, the whole file (truncated to a power of two length) is used.
+
+    # Errors
+
+    Returns an error if the audio can't be read or if no cycle can be found in it.
+    */
+    pub fn from_reader<R: Read + Seek>(reader: R, trim: bool) -> Result<Self, std::io::Error> {
+        Wavetable::from_reader_with(reader, trim, &ImportOptions::default())
+    }
+
+    /** Loads an audio file from a reader as a Wavetable and cleans it up with the given options
+
+    This works just like [`Wavetable::from_reader`], except that the table is cleaned up after it's trimmed and
+    resampled. See [`ImportOptions`].
+    */
+    pub fn from_reader_with<R: Read + Seek>(
+        reader: R,
+        trim: bool,
+        options: &ImportOptions,
+    ) -> Result<Self, std::io::Error> {
+        let (table, _) = utils::read_audio_from(reader, utils::Channels::Mix)?;
+        Wavetable::load(table, "the audio data", trim, false, options)
+    }
+
+    fn load_sndfile(
+        path: &str,
+        trim: bool,
+        exact: bool,
+        options: &ImportOptions,
+    ) -> Result<Self, std::io::Error> {
+        let (table, _) = utils::read_audio(path, utils::Channels::Mix)?;
+        Wavetable::load(table, path, trim, exact, options)
+    }
+
+    /* Turns the audio that was read from a file into a table, the same way no matter where the file came from
+     */
+    fn load(
+        mut table: Vec<f32>,
+        name: &str,
+        trim: bool,
+        exact: bool,
+        options: &ImportOptions,
+    ) -> Result<Self, std::io::Error> {
+        // Only use the largest power of two number of samples that the file holds
+        table.truncate((table.len() as f32).log2().floor().exp2() as usize);
+        if trim {
+            table = utils::extract_cycle(&table, None, options.seam)
+                .ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::Other,
+                        format!("Failed to find acceptable waveform in {}", name),
+                    )
+                })?
+                .samples;
+        }
+
+        if !exact {
+            let final_len = utils::next_pow_of_2(table.len());
+            if final_len != table.len() {
+                table = utils::resample(&table, final_len, true);
+            }
+        }
+        options.apply(&mut table);
+
+        if exact {
+            Ok(Wavetable::new_exact(&table))
+        } else {
+            Ok(Wavetable::new(&table))
+        }
+    }
+
+    /** Loads an audio file as a multi-frame Wavetable
+
+    The file is split into consecutive frames of `frame_len` samples. Any samples left over after the last full frame are
+    ignored.
+
+    # Arguments
+
+    * `path`:      The path to the audio file
+    * `frame_len`: The length of each frame. This must be a power of two and no more than 2^32.
+
+    # Errors
+
+    Returns an error if the file can't be read, if `frame_len` isn't a power of two, or if the file holds less than one
+    frame.
+    */
+    pub fn from_sndfile_frames(path: &str, frame_len: usize) -> Result<Self, std::io::Error> {
+        Wavetable::check_frame_len(frame_len)?;
+        let (mut table, _) = utils::read_audio(path, utils::Channels::Mix)?;
+        let nframes = table.len() / frame_len;
+        if nframes == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!(
+                    "{} holds {} samples, which is less than one frame of {}",
+                    path,
+                    table.len(),
+                    frame_len
+                ),
+            ));
+        }
+        table.truncate(nframes * frame_len);
+        Ok(Wavetable::new_frames(&table, frame_len))
+    }
+
+    /** Slices an audio file into a morphing, multi-frame Wavetable
+
+    The pitch is tracked through the whole file, and `frames` evenly spaced cycles are taken from it, resampled to
+    `frame_len` samples and phase aligned (see [`utils::slice_cycles`]). Scanning through the frames follows how the
+    timbre of the recording changes over time.
+
+    The options clean up the frames together, so they don't flatten the changes between them: the DC offset is removed
+    from each frame, every frame is rotated by the same amount, and one gain is applied to all of them.
+
+    # Arguments
+
+    * `path`:      The path to the audio file
+    * `frames`:    The number of frames to slice out
+    * `frame_len`: The length of each frame. This must be a power of two and no more than 2^32.
+    * `options`:   How to clean up the frames
+
+    # Errors
+
+    Returns an error if the file can't be read, if no pitch can be found in it, or if `frame_len` isn't a power of two.
+    */
+    pub fn from_sndfile_sliced(
+        path: &str,
+        frames: usize,
+        frame_len: usize,
+        options: &ImportOptions,
+    ) -> Result<Self, std::io::Error> {
+        Wavetable::check_frame_len(frame_len)?;
+        let (buffer, _) = utils::read_audio(path, utils::Channels::Mix)?;
+        let mut table = utils::slice_cycles(&buffer, frames, frame_len).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("Failed to find a pitch in {}", path),
+            )
+        })?;
+        options.apply_frames(&mut table, frame_len);
+        Ok(Wavetable::new_frames(&table, frame_len))
+    }
+
+    /** Loads a wavetable file, like the ones that Serum and similar synthesizers save, as a multi-frame Wavetable
+
+    The frame length is read from the file's `clm ` chunk (see [`utils::read_clm_frame_len`]). If the file doesn't have
+    one, then `frame_len` is used instead. The frame length doesn't have to be a power of two. Any samples left over after
+    the last full frame are ignored.
+
+    # Arguments
+
+    * `path`:      The path to the audio file
+    * `frame_len`: The frame length to use if the file doesn't have a `clm ` chunk
+
+    # Errors
+
+    Returns an error if the file can't be read, if it has neither a `clm ` chunk nor a `frame_len`, if the frame length is
+    0, or if it holds less than one frame.
+    */
+    pub fn from_sndfile_clm(path: &str, frame_len: Option<usize>) -> Result<Self, std::io::Error> {
+        let frame_len = utils::read_clm_frame_len(path)?
+            .or(frame_len)
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    format!("{} has no clm chunk, so a frame length must be given", path),
+                )
+            })?;
+        // The chunk's frame length is already checked, but the hint isn't
+        if frame_len == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("The frame length for {} must be greater than 0", path),
+            ));
+        }
+        let (mut table, _) = utils::read_audio(path, utils::Channels::Mix)?;
+        let nframes = table.len() / frame_len;
+        if nframes == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!(
+                    "{} holds {} samples, which is less than one frame of {}",
+                    path,
+                    table.len(),
+                    frame_len
+                ),
+            ));
+        }
+        table.truncate(nframes * frame_len);
+        Ok(Wavetable::new_frames_exact(&table, frame_len))
+    }
+
+    /** Writes the table to an audio file
+
+    The samples that the table was built from are written (see [`Wavetable::samples`]), with all of the frames laid
+    end-to-end. When a table with more than one frame is written to a WAV file, a `clm ` chunk that names the frame
+    length is added to it too, so that it can be read back with [`Wavetable::from_sndfile_clm`] (or by other wavetable
+    synthesizers). Like [`utils::write_sndfile`], this requires the `sndfile` feature.
+
+    # Arguments
+
+    * `path`:       The path to the audio file. If it already exists, it's overwritten.
+    * `samplerate`: The sample rate to store in the file
+    * `container`:  The container format of the file
+    * `depth`:      The sample format of the file
+
+    # Errors
+
+    Returns an error if the file can't be written, or if the container can't hold samples of the given depth.
+    */
+    pub fn write_sndfile(
+        &self,
+        path: &str,
+        samplerate: i32,
+        container: utils::Container,
+        depth: utils::BitDepth,
+    ) -> Result<(), std::io::Error> {
+        utils::write_sndfile(path, &self.samples(), samplerate, container, depth)?;
+        if self.frames.len() > 1 && container == utils::Container::Wav {
+            utils::write_clm_frame_len(path, self.len())?;
+        }
+        Ok(())
+    }
+
+    /** Returns the length of each of the table's frames
+     */
+    pub fn len(&self) -> usize {
+        self.frames[0].levels[0].len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /** Returns the number of mip levels in the table
+     */
+    pub fn levels(&self) -> usize {
+        self.frames[0].levels.len()
+    }
+
+    /** Returns whether the table's length is a power of two, which lets phasors use the faster, masked wrap
+     */
+    pub fn is_pow2(&self) -> bool {
+        self.pow2
+    }
+
+    /* Returns the longest table that a narrow phase can index
+     */
+    fn narrow_max_len(&self) -> usize {
+        if self.pow2 {
+            NARROW_MAX_LEN
+        } else {
+            NARROW_MAX_EXACT_LEN
+        }
+    }
+
+    /** Returns the interpolation mode that the table uses
+     */
+    pub fn interpolation(&self) -> Interpolation {
+        self.interpolation
+    }
+
+    /** Returns the number of frames in the table
+     */
+    pub fn frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    /** Returns the samples of all of the table's frames, laid end-to-end
+
+    These are the samples that the table was built from (before band limiting), so a table can be rebuilt from them with
+    [`Wavetable::new_frames_exact`]. They're recovered from the layout of the table's interpolation mode, and the
+    linear layout can leave them off by a rounding error.
+    */
+    pub fn samples(&self) -> Vec<f32> {
+        Vec::from_iter(
+            self.frames
+                .iter()
+                .flat_map(|frame| frame.levels[0].samples()),
+        )
+    }
+
+    /* Returns the frame to read for a position in the range of [0, 1], along with how far to morph into the next frame
+     */
+    #[inline]
+    fn frame_pos(&self, position: f32) -> (usize, f32) {
+        let pos = position.clamp(0.0, 1.0) * (self.frames.len() - 1) as f32;
+        (pos as usize, pos.fract())
+    }
+
+    /* Returns the mip level to read for a phase step, along with how far to crossfade into the next level
+
+    The step is the distance that the phase moves per output sample, in table samples. Level n holds no harmonics above
+    len / 2^(n+1), so it can be read without aliasing as long as the step is no more than 2^n.
+    */
+    #[inline]
+    fn mip_level(&self, step: f32, mode: MipMode) -> (usize, f32) {
+        let top = (self.levels() - 1) as f32;
+        match mode {
+            MipMode::Pick => (step.log2().ceil().clamp(0.0, top) as usize, 0.0),
+            MipMode::Crossfade => {
+                let level = (step.log2() + 1.0).clamp(0.0, top);
+                (level as usize, level.fract())
+            }
+        }
+    }
+
+    /* Reads the table at the given index and fractional phase (stored as 1.m, see Phasor), frame and mip level
+     */
+    #[inline]
+    fn interpolate<L: Layout>(
+        &self,
+        index: usize,
+        frac1: f32,
+        frame: usize,
+        morph: f32,
+        level: usize,
+        xfade: f32,
+    ) -> f32 {
+        let out = self.frames[frame].interpolate::<L>(index, frac1, level, xfade);
+        if morph > 0.0 {
+            let next = self.frames[frame + 1].interpolate::<L>(index, frac1, level, xfade);
+            out + morph * (next - out)
+        } else {
+            out
+        }
+    }
+}
+
+impl Frame {
+    fn new(table: &[f32], interpolation: Interpolation, kernel: &Option<Arc<SincKernel>>) -> Self {
+        // Each level halves the harmonics of the one before it, until only the fundamental is left
+        let size = table.len();
+        let nlevels = ((usize::BITS - 1 - size.leading_zeros()) as usize).max(1);
+        let mut levels = Vec::with_capacity(nlevels);
+        levels.push(Level::new(table, interpolation, kernel));
+        for level in 1..nlevels {
+            let bandlimited = utils::bandlimit(table, size >> (level + 1));
+            levels.push(Level::new(&bandlimited, interpolation, kernel));
+        }
+        Frame { levels }
+    }
+
+    #[inline]
+    fn interpolate<L: Layout>(&self, index: usize, frac1: f32, level: usize, xfade: f32) -> f32 {
+        let out = L::read(&self.levels[level], index, frac1);
+        if xfade > 0.0 {
+            out + xfade * (L::read(&self.levels[level + 1], index, frac1) - out)
+        } else {
+            out
+        }
+    }
+}
+
+impl Level {
+    fn new(table: &[f32], interpolation: Interpolation, kernel: &Option<Arc<SincKernel>>) -> Self {
+        let size = table.len();
+        let at = |i: isize| table[i.rem_euclid(size as isize) as usize];
+        let mut level = Level {
+            interpolation,
+            table1: Vec::new(),
+            table2: Vec::new(),
+            coefs: Vec::new(),
+            kernel: None,
+        };
+        match interpolation {
+            Interpolation::Linear => {
+                level.table1.reserve(size);
+                level.table2.reserve(size);
+
+                // Create the tables
+                for i in 0..(size - 1) {
+                    let val1 = table[i];
+                    let val2 = table[i + 1];
+                    level.table1.push(2.0 * val1 - val2);
+                    level.table2.push(val2 - val1);
+                }
+                let val1 = table[size - 1];
+                let val2 = table[0];
+                level.table1.push(2.0 * val1 - val2);
+                level.table2.push(val2 - val1);
+            }
+            Interpolation::Truncate => level.table1 = table.to_vec(),
+            Interpolation::Cubic => {
+                level.coefs = Vec::from_iter((0..size as isize).map(|i| {
+                    let (xm1, x0, x1, x2) = (at(i - 1), at(i), at(i + 1), at(i + 2));
+                    [
+                        x0,
+                        0.5 * (x1 - xm1),
+                        xm1 - 2.5 * x0 + 2.0 * x1 - 0.5 * x2,
+                        0.5 * (x2 - xm1) + 1.5 * (x0 - x1),
+                    ]
+                }))
+            }
+            Interpolation::Sinc => {
+                let start = 1 - (SINC_TAPS / 2) as isize;
+                level.table1 =
+                    Vec::from_iter((0..(size + SINC_TAPS - 1) as isize).map(|i| at(i + start)));
+                level.kernel = Some(kernel.clone().expect("Sinc levels need a kernel"));
+            }
+        }
+        level
+    }
+
+    fn len(&self) -> usize {
+        match self.interpolation {
+            Interpolation::Linear | Interpolation::Truncate => self.table1.len(),
+            Interpolation::Cubic => self.coefs.len(),
+            Interpolation::Sinc => self.table1.len() - (SINC_TAPS - 1),
+        }
+    }
+
+    /* Recovers the level's original samples from its layout
+     */
+    fn samples(&self) -> Vec<f32> {
+        match self.interpolation {
+            // table1 + table2 = (2 * x[n] - x[n+1]) + (x[n+1] - x[n]) = x[n]
+            Interpolation::Linear => Vec::from_iter(
+                self.table1
+                    .iter()
+                    .zip(self.table2.iter())
+                    .map(|(t1, t2)| t1 + t2),
+            ),
+            Interpolation::Truncate => self.table1.clone(),
+            Interpolation::Cubic => Vec::from_iter(self.coefs.iter().map(|c| c[0])),
+            Interpolation::Sinc => self.table1[SINC_TAPS / 2 - 1..][..self.len()].to_vec(),
+        }
+    }
+}
+
+impl Layout for LinearLayout {
+    #[inline]
+    fn read(level: &Level, index: usize, frac1: f32) -> f32 {
+        level.table1[index] + (frac1 * level.table2[index])
+    }
+}
+
+impl Layout for TruncateLayout {
+    #[inline]
+    fn read(level: &Level, index: usize, _frac1: f32) -> f32 {
+        level.table1[index]
+    }
+}
+
+impl Layout for CubicLayout {
+    #[inline]
+    fn read(level: &Level, index: usize, frac1: f32) -> f32 {
+        let frac = frac1 - 1.0;
+        let c = &level.coefs[index];
+        ((c[3] * frac + c[2]) * frac + c[1]) * frac + c[0]
+    }
+}
+
+impl Layout for SincLayout {
+    #[inline]
+    fn read(level: &Level, index: usize, frac1: f32) -> f32 {
+        let kernel = level.kernel.as_deref().expect("Sinc levels need a kernel");
+        let row = (frac1 - 1.0) * SINC_PHASES as f32;
+        let irow = row as usize;
+        let frac = row - irow as f32;
+        let weights = &kernel.rows[irow];
+        let deltas = &kernel.deltas[irow];
+        let taps = &level.table1[index..index + SINC_TAPS];
+        let mut out = 0.0;
+        for j in 0..SINC_TAPS {
+            out += taps[j] * (weights[j] + frac * deltas[j]);
+        }
+        out
+    }
+}
+
+impl SincKernel {
+    fn new() -> Self {
+        let half = (SINC_TAPS / 2) as f32;
+        let rows = Vec::from_iter((0..=SINC_PHASES).map(|row| {
+            let frac = row as f32 / SINC_PHASES as f32;
+            let mut weights = [0.0f32; SINC_TAPS];
+            for (j, weight) in weights.iter_mut().enumerate() {
+                // Distance from tap j to the interpolated point
+                let x = (j as f32 - half + 1.0) - frac;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (PI * x).sin() / (PI * x)
+                };
+                let blackman =
+                    0.42 + 0.5 * (PI * x / half).cos() + 0.08 * (2.0 * PI * x / half).cos();
+                *weight = sinc * blackman;
+            }
+            // Normalize for unity gain at DC
+            let sum: f32 = weights.iter().sum();
+            weights.map(|w| w / sum)
+        }));
+        let deltas = Vec::from_iter((0..=SINC_PHASES).map(|row| {
+            let next = &rows[(row + 1).min(SINC_PHASES)];
+            let mut deltas = [0.0f32; SINC_TAPS];
+            for (j, delta) in deltas.iter_mut().enumerate() {
+                *delta = next[j] - rows[row][j];
+            }
+            deltas
+        }));
+        SincKernel { rows, deltas }
+    }
+}
+
+impl Shape {
+    /* Returns the amplitudes and phases of the shape's first `count` harmonics
+     */
+    fn harmonics(self, count: usize) -> (Vec<f32>, Vec<f32>) {
+        let harmonic = |k: usize| -> (f32, f32) {
+            let kf = k as f32;
+            let odd = k % 2 == 1;
+            match self {
+                Shape::Sine => (if k == 1 { 1.0 } else { 0.0 }, 0.0),
+                Shape::Saw => (2.0 / (PI * kf), PI),
+                Shape::Square => (if odd { 4.0 / (PI * kf) } else { 0.0 }, 0.0),
+                Shape::Triangle => {
+                    let amplitude = if odd { 8.0 / (PI * PI * kf * kf) } else { 0.0 };
+                    // Every other odd harmonic is inverted
+                    (amplitude, if k % 4 == 3 { PI } else { 0.0 })
+                }
+                Shape::Pulse(width) => {
+                    let width = width.clamp(0.0, 1.0);
+                    (
+                        (4.0 / (PI * kf)) * (PI * kf * width).sin(),
+                        PI / 2.0 - PI * kf * width,
+                    )
+                }
+            }
+        };
+        (1..=count).map(harmonic).unzip()
+    }
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        ImportOptions {
+            remove_dc: false,
+            rotate: false,
+            normalize: Normalize::Off,
+            seam: utils::Seam::Off,
+        }
+    }
+}
+
+impl ImportOptions {
+    /* Cleans up a single cycle in place
+     */
+    fn apply(&self, table: &mut [f32]) {
+        // Rotating the cycle doesn't change its level, so it's leveled all at once first
+        adjust_level(table, self.remove_dc, self.normalize);
+        if self.rotate {
+            if let Some(start) = rising_zero_crossing(table) {
+                table.rotate_left(start);
+            }
+        }
+    }
+
+    /* Cleans up a table of frames in place, without changing how they relate to each other. The DC offset is removed
+     * from each frame, all of the frames are rotated to the first one's rising zero crossing, and the normalization gain
+     * is measured over the whole table.
+     */
+    fn apply_frames(&self, table: &mut [f32], frame_len: usize) {
+        if table.is_empty() {
+            return;
+        }
+        if self.remove_dc {
+            for frame in table.chunks_mut(frame_len) {
+                adjust_level(frame, true, Normalize::Off);
+            }
+        }
+        if self.rotate {
+            if let Some(start) = rising_zero_crossing(&table[..frame_len]) {
+                table
+                    .chunks_mut(frame_len)
+                    .for_each(|frame| frame.rotate_left(start));
+            }
+        }
+        adjust_level(table, false, self.normalize);
+    }
+}
+
+/* Removes the DC offset from a slice of samples, and then scales it to the level that's asked for. Silence is left as it
+ * is. Both the import options and the spectral operations set levels this way.
+ */
+fn adjust_level(samples: &mut [f32], remove_dc: bool, normalize: Normalize) {
+    if samples.is_empty() {
+        return;
+    }
+    if remove_dc {
+        let dc = samples.iter().sum::<f32>() / samples.len() as f32;
+        samples.iter_mut().for_each(|v| *v -= dc);
+    }
+    let (to, from) = match normalize {
+        Normalize::Off => return,
+        Normalize::Peak(peak) => (peak, samples.iter().fold(0.0f32, |max, v| max.max(v.abs()))),
+        Normalize::Rms(rms) => {
+            let power = samples.iter().map(|v| v * v).sum::<f32>() / samples.len() as f32;
+            (rms, power.sqrt())
+        }
+    };
+    if from > 0.0 {
+        let gain = to / from;
+        samples.iter_mut().for_each(|v| *v *= gain);
+    }
+}
+
+/* Returns the index of the sample closest to the steepest rising zero crossing in a cycle, or None if the cycle never
+ * crosses zero on the way up
+ */
+fn rising_zero_crossing(table: &[f32]) -> Option<usize> {
+    let len = table.len();
+    (0..len)
+        .filter_map(|i| {
+            let (prev, next) = (table[(i + len - 1) % len], table[i]);
+            (prev < 0.0 && next >= 0.0).then_some((i, next - prev))
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _)| {
+            let prev = (i + len - 1) % len;
+            if table[i].abs() <= table[prev].abs() {
+                i
+            } else {
+                prev
+            }
+        })
+}
+
+impl Warp {
+    /* Warps a phase in [0, 1) into [0, 1)
+     */
+    #[inline]
+    fn apply(self, phase: f64, amount: f32, len: usize) -> f64 {
+        match self {
+            Warp::Off => phase,
+            Warp::Bend => {
+                let knee = 0.5 - 0.45 * amount.clamp(-1.0, 1.0) as f64;
+                if phase < knee {
+                    0.5 * phase / knee
+                } else {
+                    0.5 + 0.5 * (phase - knee) / (1.0 - knee)
+                }
+            }
+            Warp::Squeeze => {
+                let width = 1.0 - 0.95 * amount.clamp(0.0, 1.0) as f64;
+                if phase < width {
+                    phase / width
+                } else {
+                    0.0
+                }
+            }
+            Warp::Mirror => {
+                let turn = 1.0 - 0.5 * amount.clamp(0.0, 1.0) as f64;
+                if phase < turn {
+                    phase / turn
+                } else {
+                    (1.0 - phase) / (1.0 - turn)
+                }
+            }
+            Warp::Sync => (phase * self.stretch(amount) as f64).fract(),
+            Warp::Quantize => {
+                let steps = ((1.0 - amount.clamp(0.0, 1.0)) * (len as f32).log2())
+                    .exp2()
+                    .round()
+                    .max(2.0) as f64;
+                (phase * steps).floor() / steps
+            }
+        }
+    }
+
+    /* Returns the steepest slope of the warp, which is how much it can raise the frequencies in the table
+     */
+    #[inline]
+    fn stretch(self, amount: f32) -> f32 {
+        match self {
+            Warp::Off | Warp::Quantize => 1.0,
+            Warp::Bend => {
+                let knee = 0.5 - 0.45 * amount.clamp(-1.0, 1.0);
+                0.5 / knee.min(1.0 - knee)
+            }
+            Warp::Squeeze => 1.0 / (1.0 - 0.95 * amount.clamp(0.0, 1.0)),
+            Warp::Mirror => {
+                // At zero there's no return trip at all, just the table's own wrap
+                if amount <= 0.0 {
+                    1.0
+                } else {
+                    let turn = 1.0 - 0.5 * amount.min(1.0);
+                    (1.0 / turn).max(1.0 / (1.0 - turn))
+                }
+            }
+            Warp::Sync => (4.0 * amount.clamp(0.0, 1.0)).exp2(),
+        }
+    }
+}
+
+const XLOBITS1: i32 = 16;
+const XLOBITS2: i64 = 32;
+
+/* The longest tables that a narrow phase can index. Tables that aren't a power of two long need headroom for the wrap.
+ */
+const NARROW_MAX_LEN: usize = 1 << 16;
+const NARROW_MAX_EXACT_LEN: usize = 1 << 14;
+const WIDE_MAX_EXACT_LEN: usize = 1 << 30;
+
+/* Fixed-point phase arithmetic, for each of the PhaseMode formats
+ */
+trait Accumulator: Copy {
+    // Converts a distance in table samples to the fixed-point format, wrapping if it's too large
+    fn from_samples(samples: f64) -> Self;
+    fn add(self, other: Self) -> Self;
+    // Brings a phase that is no more than one span outside of [0, span) back into it
+    fn wrap(self, span: Self) -> Self;
+    // Brings any phase into [0, span)
+    fn modulo(self, span: Self) -> Self;
+    // Brings a distance into (-span, span), so that adding it to a phase only needs a wrap. This is cheap for the
+    // distances that are already in range, and only falls back to a modulo for the others.
+    fn reduce(self, span: Self) -> Self;
+    // Returns the integral part of the phase, masked into the table's range
+    fn index(self, lomask: usize) -> usize;
+    // Returns 1.m, where m is the fractional part of the phase
+    fn frac1(self) -> f32;
+}
+
+impl Accumulator for Wrapping<i32> {
+    #[inline]
+    fn from_samples(samples: f64) -> Self {
+        Wrapping((samples * 65536.0) as i64 as i32)
+    }
+
+    #[inline]
+    fn add(self, other: Self) -> Self {
+        self + other
+    }
+
+    #[inline]
+    fn wrap(self, span: Self) -> Self {
+        if self >= span {
+            self - span
+        } else if self.0 < 0 {
+            self + span
+        } else {
+            self
+        }
+    }
+
+    #[inline]
+    fn modulo(self, span: Self) -> Self {
+        Wrapping(self.0.rem_euclid(span.0))
+    }
+
+    #[inline]
+    fn reduce(self, span: Self) -> Self {
+        if self.0 > -span.0 && self.0 < span.0 {
+            self
+        } else {
+            self.modulo(span)
+        }
+    }
+
+    #[inline]
+    fn index(self, lomask: usize) -> usize {
+        (self.0 >> XLOBITS1) as usize & lomask
+    }
+
+    #[inline]
+    fn frac1(self) -> f32 {
+        phase_frac1(self.0)
+    }
+}
+
+impl Accumulator for Wrapping<i64> {
+    #[inline]
+    fn from_samples(samples: f64) -> Self {
+        Wrapping((samples * 4294967296.0) as i128 as i64)
+    }
+
+    #[inline]
+    fn add(self, other: Self) -> Self {
+        self + other
+    }
+
+    #[inline]
+    fn wrap(self, span: Self) -> Self {
+        if self >= span {
+            self - span
+        } else if self.0 < 0 {
+            self + span
+        } else {
+            self
+        }
+    }
+
+    #[inline]
+    fn modulo(self, span: Self) -> Self {
+        Wrapping(self.0.rem_euclid(span.0))
+    }
+
+    #[inline]
+    fn reduce(self, span: Self) -> Self {
+        if self.0 > -span.0 && self.0 < span.0 {
+            self
+        } else {
+            self.modulo(span)
+        }
+    }
+
+    #[inline]
+    fn index(self, lomask: usize) -> usize {
+        (self.0 >> XLOBITS2) as usize & lomask
+    }
+
+    #[inline]
+    fn frac1(self) -> f32 {
+        phase_frac1_wide(self.0)
+    }
+}
+
+impl Phasor {
+    /** Creates a new phasor for the given Wavetable
+
+    The phasor uses a [`PhaseMode::Narrow`] phase, unless the table is too long for it, in which case it uses a
+    [`PhaseMode::Wide`] phase.
+
+    # Arguments
+    * `system`: The System parameters
+    * `table`:  The wavetable to reference
+
+    # Returns
+    A Phasor that will generate signals from the given Wavetable.
+    */
+    pub fn new(system: &Arc<System>, table: &Arc<Wavetable>) -> Self {
+        let sampledur = 1.0 / system.samplerate() as f64;
+
+        let size = table.len();
+        let sizef64 = size as f64;
+        Phasor {
+            // system: system.clone(),
+            table: table.clone(),
+            phase: if size > table.narrow_max_len() {
+                Phase::Wide(Wrapping(0))
+            } else {
+                Phase::Narrow(Wrapping(0))
+            },
+
+            // sampledur,
+            radtoinc: sizef64 / (2.0 * std::f64::consts::PI),
+            cpstoinc: sizef64 * sampledur,
+            mipmode: MipMode::Crossfade,
+            blep: 0.0,
+            fmdepth: 0.0,
+            pmindex: 0.0,
+            warp: Warp::Off,
+            warp_amount: 0.0,
+            fade: None,
+            fadebuf: vec![0.0; system.bufsize().max(1)],
+        }
+    }
+
+    /** Replaces the phasor's wavetable
+
+    The phase carries over to the new table, scaled to its length. This doesn't allocate, so it's safe to call on the
+    audio thread. If the new table is too long for a narrow phase, then the phasor switches to a wide one. See the
+    "Swapping tables" section above.
+
+    # Arguments
+
+    * `table`: The new wavetable
+    * `fade`:  The length of the crossfade from the old table to the new one (in samples). If this is 0, then the
+               phasor switches right away.
+    */
+    pub fn set_table(&mut self, table: &Arc<Wavetable>, fade: usize) {
+        if table.len() > table.narrow_max_len() {
+            self.set_phasemode(PhaseMode::Wide);
+        }
+        let old = std::mem::replace(&mut self.table, table.clone());
+        self.rescale(old.len());
+        // A fade that's already running is cut short, and its table is let go of here
+        self.fade = if fade > 0 {
+            Some(Fade {
+                table: old,
+                length: fade,
+                remaining: fade,
+            })
+        } else {
+            None
+        };
+    }
+
+    /** Returns the phasor's wavetable
+     */
+    pub fn table(&self) -> &Arc<Wavetable> {
+        &self.table
+    }
+
+    /** Ends the crossfade from a previous table, if there is one, and lets go of that table
+     */
+    pub fn end_fade(&mut self) {
+        self.fade = None;
+    }
+
+    /** Sets how the phasor chooses between the mip levels of its wavetable
+
+    The default is [`MipMode::Crossfade`].
+    */
+    pub fn set_mipmode(&mut self, mode: MipMode) {
+        self.mipmode = mode;
+    }
+
+    /** Sets how the phasor warps its phase before reading the table
+
+    The default is [`Warp::Off`]. See the "Phase warp" section above.
+    */
+    pub fn set_warp(&mut self, warp: Warp) {
+        self.warp = warp;
+    }
+
+    /** Sets the warp amount that is used by all of the perform functions other than [`Phasor::perform_warp_fm`]
+
+    # Arguments
+
+    * `amount`: The warp amount. See [`Warp`] for the range of each mode.
+    */
+    pub fn set_warp_amount(&mut self, amount: f32) {
+        self.warp_amount = amount;
+    }
+
+    /** Sets the fixed-point format of the phasor's phase
+
+    The current phase is carried over into the new format.
+
+    # Panics
+
+    This function will panic if `mode` is [`PhaseMode::Narrow`] and the wavetable is longer than 2^16 samples (or 2^14
+    samples, if its length isn't a power of two).
+    */
+    pub fn set_phasemode(&mut self, mode: PhaseMode) {
+        self.phase = match (self.phase, mode) {
+            (Phase::Narrow(phase), PhaseMode::Wide) => {
+                Phase::Wide(Wrapping((phase.0 as i64) << (XLOBITS2 - XLOBITS1 as i64)))
+            }
+            (Phase::Wide(phase), PhaseMode::Narrow) => {
+                assert!(
+                    self.table.len() <= self.table.narrow_max_len(),
+                    "A narrow phase can't index a wavetable of {} samples",
+                    self.table.len()
+                );
+                Phase::Narrow(Wrapping((phase.0 >> (XLOBITS2 - XLOBITS1 as i64)) as i32))
+            }
+            (phase, _) => phase,
+        };
+    }
+
+    /** Returns the fixed-point format of the phasor's phase
+     */
+    pub fn phasemode(&self) -> PhaseMode {
+        match self.phase {
+            Phase::Narrow(_) => PhaseMode::Narrow,
+            Phase::Wide(_) => PhaseMode::Wide,
+        }
+    }
+
+    /** Performs the wavetable oscillation operation with audio-rate frequency and/or phase modulation
+
+    # Arguments
+
+    * `outbuf`:  A buffer for storing the output waveform
+    * `freqin`:  A sample-by-sample frequency. This must be the same length as outbuf.
+    * `phasein`: A sample-by-sample offset phase, useful for phase modulation. This must be the same length as outbuf.
+
+    # Panics
+
+    This function will panic if either the `freqin` or `phasein` buffer lengths are shorter than the `outbuf` length.
+    */
+    pub fn perform_fm(&mut self, outbuf: &mut [f32], freqin: &[f32], phasein: &[f32]) {
+        self.crossfade(outbuf, |osc, outbuf, range| {
+            let (freqin, phasein) = (&freqin[range.clone()], &phasein[range]);
+            osc.phase = match osc.phase {
+                Phase::Narrow(phase) => Phase::Narrow(osc.render_fm_narrow(
+                    phase,
+                    outbuf,
+                    |i| freqin[i],
+                    |i| phasein[i],
+                    |_| 0.0,
+                    |_| osc.warp_amount,
+                )),
+                Phase::Wide(phase) => Phase::Wide(osc.render_fm(
+                    phase,
+                    outbuf,
+                    |i| freqin[i],
+                    |i| phasein[i],
+                    |_| 0.0,
+                    |_| osc.warp_amount,
+                )),
+            };
+        });
+    }
+
+    /** Sets the frequency deviation of the linear FM input of [`Phasor::perform_mod`]
+
+    # Arguments
+
+    * `depth`: The frequency deviation (in Hz) of a linear FM input of 1.0
+    */
+    pub fn set_fm_depth(&mut self, depth: f32) {
+        self.fmdepth = depth;
+    }
+
+    /** Sets the modulation index of the phase modulation input of [`Phasor::perform_mod`]
+
+    # Arguments
+
+    * `index`: The phase deviation (in radians) of a phase modulation input of 1.0
+    */
+    pub fn set_pm_index(&mut self, index: f32) {
+        self.pmindex = index;
+    }
+
+    /** Performs the wavetable oscillation operation with linear FM, exponential FM and phase modulation inputs
+
+    See the "Frequency modulation" section above. Any of the inputs may be left out.
+
+    # Arguments
+
+    * `outbuf`: A buffer for storing the output waveform
+    * `freq`:   The unmodulated frequency (in Hz)
+    * `linin`:  A sample-by-sample linear FM input, scaled by the FM depth
+    * `expin`:  A sample-by-sample exponential FM input (in semitones)
+    * `pmin`:   A sample-by-sample phase modulation input, scaled by the PM index
+
+    # Panics
+
+    This function will panic if any of the given input buffer lengths are shorter than the `outbuf` length.
+    */
+    pub fn perform_mod(
+        &mut self,
+        outbuf: &mut [f32],
+        freq: f32,
+        linin: Option<&[f32]>,
+        expin: Option<&[f32]>,
+        pmin: Option<&[f32]>,
+    ) {
+        let (depth, index) = (self.fmdepth, self.pmindex);
+        self.crossfade(outbuf, |osc, outbuf, range| {
+            let start = range.start;
+            let freqin = |i: usize| {
+                let expfreq = match expin {
+                    Some(expin) => freq * (expin[start + i] / 12.0).exp2(),
+                    None => freq,
+                };
+                linin.map_or(expfreq, |linin| expfreq + depth * linin[start + i])
+            };
+            let phasein = |i: usize| pmin.map_or(0.0, |pmin| index * pmin[start + i]);
+            osc.phase = match osc.phase {
+                Phase::Narrow(phase) => Phase::Narrow(osc.render_fm_narrow(
+                    phase,
+                    outbuf,
+                    freqin,
+                    phasein,
+                    |_| 0.0,
+                    |_| osc.warp_amount,
+                )),
+                Phase::Wide(phase) => Phase::Wide(osc.render_fm(
+                    phase,
+                    outbuf,
+                    freqin,
+                    phasein,
+                    |_| 0.0,
+                    |_| osc.warp_amount,
+                )),
+            };
+        });
+    }
+
+    /** Performs the wavetable oscillation operation with audio-rate frequency, phase and frame position modulation
+
+    # Arguments
+
+    * `outbuf`:  A buffer for storing the output waveform
+    * `freqin`:  A sample-by-sample frequency. This must be the same length as outbuf.
+    * `phasein`: A sample-by-sample offset phase, useful for phase modulation. This must be the same length as outbuf.
+    * `posin`:   A sample-by-sample frame position, in the range of [0, 1]. This must be the same length as outbuf.
+
+    # Panics
+
+    This function will panic if any of the `freqin`, `phasein` or `posin` buffer lengths are shorter than the `outbuf`
+    length.
+    */
+    pub fn perform_morph_fm(
+        &mut self,
+        outbuf: &mut [f32],
+        freqin: &[f32],
+        phasein: &[f32],
+        posin: &[f32],
+    ) {
+        self.crossfade(outbuf, |osc, outbuf, range| {
+            let (freqin, phasein, posin) = (
+                &freqin[range.clone()],
+                &phasein[range.clone()],
+                &posin[range],
+            );
+            osc.phase = match osc.phase {
+                Phase::Narrow(phase) => Phase::Narrow(osc.render_fm_narrow(
+                    phase,
+                    outbuf,
+                    |i| freqin[i],
+                    |i| phasein[i],
+                    |i| posin[i],
+                    |_| osc.warp_amount,
+                )),
+                Phase::Wide(phase) => Phase::Wide(osc.render_fm(
+                    phase,
+                    outbuf,
+                    |i| freqin[i],
+                    |i| phasein[i],
+                    |i| posin[i],
+                    |_| osc.warp_amount,
+                )),
+            };
+        });
+    }
+
+    /** Performs the wavetable oscillation operation with audio-rate frequency, phase and warp amount modulation
+
+    # Arguments
+
+    * `outbuf`:   A buffer for storing the output waveform
+    * `freqin`:   A sample-by-sample frequency. This must be the same length as outbuf.
+    * `phasein`:  A sample-by-sample offset phase, useful for phase modulation. This must be the same length as outbuf.
+    * `amountin`: A sample-by-sample warp amount. This must be the same length as outbuf.
+
+    # Panics
+
+    This function will panic if any of the `freqin`, `phasein` or `amountin` buffer lengths are shorter than the
+    `outbuf` length.
+    */
+    pub fn perform_warp_fm(
+        &mut self,
+        outbuf: &mut [f32],
+        freqin: &[f32],
+        phasein: &[f32],
+        amountin: &[f32],
+    ) {
+        self.crossfade(outbuf, |osc, outbuf, range| {
+            let (freqin, phasein, amountin) = (
+                &freqin[range.clone()],
+                &phasein[range.clone()],
+                &amountin[range],
+            );
+            osc.phase = match osc.phase {
+                Phase::Narrow(phase) => Phase::Narrow(osc.render_fm_narrow(
+                    phase,
+                    outbuf,
+                    |i| freqin[i],
+                    |i| phasein[i],
+                    |_| 0.0,
+                    |i| amountin[i],
+                )),
+                Phase::Wide(phase) => Phase::Wide(osc.render_fm(
+                    phase,
+                    outbuf,
+                    |i| freqin[i],
+                    |i| phasein[i],
+                    |_| 0.0,
+                    |i| amountin[i],
+                )),
+            };
+        });
+    }
+
+    /** Sets the phase to zero
+     *
+     * This is useful to ensure that a new note starts on a zero-crossing (assuming that the table starts at 0)
+     */
+    pub fn zero(&mut self) {
+        self.phase = match self.phase {
+            Phase::Narrow(_) => Phase::Narrow(Wrapping(0)),
+            Phase::Wide(_) => Phase::Wide(Wrapping(0)),
+        };
+        self.blep = 0.0;
+    }
+
+    /** Sets the phase
+
+    # Arguments
+
+    * `phase`: The new phase (in radians)
+    */
+    pub fn set_phase(&mut self, phase: f32) {
+        let samples = (self.radtoinc * phase as f64).rem_euclid(self.table.len() as f64);
+        self.phase = match self.phase {
+            Phase::Narrow(_) => Phase::Narrow(Accumulator::from_samples(samples)),
+            Phase::Wide(_) => Phase::Wide(Accumulator::from_samples(samples)),
+        };
+        self.blep = 0.0;
+    }
+
+    /** Performs the wavetable oscillation operation with control-rate frequency and/or phase modulation
+
+    # Arguments
+
+    * `outbuf`:  A buffer for storing the output waveform
+    * `freqin`:  The frequency (in Hz)
+    * `phasein`: The phase offset (in radians)
+
+    # Panics
+
+    This function will panic if either the `freqin` or `phasein` buffer lengths are shorter than the `outbuf` length.
+    */
+    pub fn perform(&mut self, outbuf: &mut [f32], freqin: f32, phasein: f32) {
+        self.perform_morph(outbuf, freqin, phasein, 0.0);
+    }
+
+    /** Performs the wavetable oscillation operation with control-rate frequency, phase and frame position modulation
+
+    # Arguments
+
+    * `outbuf`:  A buffer for storing the output waveform
+    * `freqin`:  The frequency (in Hz)
+    * `phasein`: The phase offset (in radians)
+    * `posin`:   The frame position, in the range of [0, 1]
+    */
+    pub fn perform_morph(&mut self, outbuf: &mut [f32], freqin: f32, phasein: f32, posin: f32) {
+        self.crossfade(outbuf, |osc, outbuf, _| {
+            osc.phase = match osc.phase {
+                Phase::Narrow(phase) => {
+                    Phase::Narrow(osc.render_narrow(phase, outbuf, freqin, phasein, posin))
+                }
+                Phase::Wide(phase) => {
+                    Phase::Wide(osc.render(phase, outbuf, freqin, phasein, posin))
+                }
+            };
+        });
+    }
+
+    /** Performs the wavetable oscillation operation as a hard sync master
+
+    The phasor runs just as it does in [`Phasor::perform`] and also records when its phase wraps. See the "Hard sync"
+    section above.
+
+    # Arguments
+
+    * `outbuf`:  A buffer for storing the output waveform
+    * `freqin`:  The frequency (in Hz)
+    * `phasein`: The phase offset (in radians)
+    * `syncout`: A buffer for storing the sync events. Each sample is either `None` or `Some(frac)`, where `frac`, in
+                 the range of [0, 1], is how far past that output sample the phase wrapped.
+
+    # Panics
+
+    This function will panic if the `syncout` buffer length is shorter than the `outbuf` length.
+    */
+    pub fn perform_master(
+        &mut self,
+        outbuf: &mut [f32],
+        freqin: f32,
+        phasein: f32,
+        syncout: &mut [Option<f32>],
+    ) {
+        self.crossfade(outbuf, |osc, outbuf, range| {
+            let syncout = &mut syncout[range];
+            let (phase, blep) = match osc.phase {
+                Phase::Narrow(phase) => {
+                    let (phase, blep) =
+                        osc.render_sync(phase, outbuf, freqin, phasein, None, Some(syncout));
+                    (Phase::Narrow(phase), blep)
+                }
+                Phase::Wide(phase) => {
+                    let (phase, blep) =
+                        osc.render_sync(phase, outbuf, freqin, phasein, None, Some(syncout));
+                    (Phase::Wide(phase), blep)
+                }
+            };
+            osc.phase = phase;
+            osc.blep = blep;
+        });
+    }
+
+    /** Performs the wavetable oscillation operation as a hard sync slave
+
+    The phasor resets its phase to zero at each of the master's sync events. See the "Hard sync" section above.
+
+    # Arguments
+
+    * `outbuf`:  A buffer for storing the output waveform
+    * `freqin`:  The frequency (in Hz)
+    * `phasein`: The phase offset (in radians)
+    * `syncin`:  The sync events produced by a master's [`Phasor::perform_master`]
+
+    # Panics
+
+    This function will panic if the `syncin` buffer length is shorter than the `outbuf` length.
+    */
+    pub fn perform_slave(
+        &mut self,
+        outbuf: &mut [f32],
+        freqin: f32,
+        phasein: f32,
+        syncin: &[Option<f32>],
+    ) {
+        self.crossfade(outbuf, |osc, outbuf, range| {
+            let syncin = &syncin[range];
+            let (phase, blep) = match osc.phase {
+                Phase::Narrow(phase) => {
+                    let (phase, blep) =
+                        osc.render_sync(phase, outbuf, freqin, phasein, Some(syncin), None);
+                    (Phase::Narrow(phase), blep)
+                }
+                Phase::Wide(phase) => {
+                    let (phase, blep) =
+                        osc.render_sync(phase, outbuf, freqin, phasein, Some(syncin), None);
+                    (Phase::Wide(phase), blep)
+                }
+            };
+            osc.phase = phase;
+            osc.blep = blep;
+        });
+    }
+
+    /* Runs one of the perform methods through `perform`, which renders the given range of the output (and of the
+     * inputs) into the buffer that it's given. During a crossfade, each block is rendered from the old table and then
+     * again from the new one, starting from the same phase, and the two are mixed.
+     */
+    fn crossfade(
+        &mut self,
+        outbuf: &mut [f32],
+        mut perform: impl FnMut(&mut Self, &mut [f32], Range<usize>),
+    ) {
+        let mut start = 0;
+        while start < outbuf.len() {
+            let remaining = match &self.fade {
+                Some(fade) => fade.remaining,
+                None => break,
+            };
+            let len = (outbuf.len() - start)
+                .min(self.fadebuf.len())
+                .min(remaining);
+            let range = start..start + len;
+
+            // The old table goes first, so that the new table's sync events are the ones that are left in syncout
+            let (phase, blep) = (self.phase, self.blep);
+            let mut fadebuf = std::mem::take(&mut self.fadebuf);
+            self.swap_fade_table();
+            perform(self, &mut fadebuf[..len], range.clone());
+            self.swap_fade_table();
+            self.phase = phase;
+            self.blep = blep;
+            perform(self, &mut outbuf[range.clone()], range);
+
+            let fade = self.fade.as_mut().unwrap();
+            let done = fade.length - fade.remaining;
+            for (i, (out, old)) in outbuf[start..start + len]
+                .iter_mut()
+                .zip(fadebuf.iter())
+                .enumerate()
+            {
+                let mix = (done + i + 1) as f32 / (fade.length + 1) as f32;
+                *out = old + mix * (*out - old);
+            }
+            fade.remaining -= len;
+            if fade.remaining == 0 {
+                self.fade = None;
+            }
+            self.fadebuf = fadebuf;
+            start += len;
+        }
+        let end = outbuf.len();
+        if start < end {
+            perform(self, &mut outbuf[start..], start..end);
+        }
+    }
+
+    /* Swaps the current table with the one that's being faded out, carrying the phase over
+     */
+    fn swap_fade_table(&mut self) {
+        if let Some(fade) = self.fade.as_mut() {
+            std::mem::swap(&mut self.table, &mut fade.table);
+            let old_len = fade.table.len();
+            self.rescale(old_len);
+        }
+    }
+
+    /* Rescales the phase and the phase increments after the table has changed from one that was `old_len` long
+     */
+    fn rescale(&mut self, old_len: usize) {
+        let len = self.table.len();
+        if len == old_len {
+            return;
+        }
+        let ratio = len as f64 / old_len as f64;
+        self.radtoinc *= ratio;
+        self.cpstoinc *= ratio;
+        self.phase = match self.phase {
+            Phase::Narrow(phase) => {
+                let samples = (phase.0 as f64 / (1 << XLOBITS1) as f64).rem_euclid(old_len as f64);
+                Phase::Narrow(Accumulator::from_samples(samples * ratio))
+            }
+            Phase::Wide(phase) => {
+                let samples =
+                    (phase.0 as f64 / (1i64 << XLOBITS2) as f64).rem_euclid(old_len as f64);
+                Phase::Wide(Accumulator::from_samples(samples * ratio))
+            }
+        };
+    }
+
+    /* Runs the oscillator with control-rate inputs and returns the final phase
+     */
+    #[inline]
+    fn render<A: Accumulator>(
+        &self,
+        phase: A,
+        outbuf: &mut [f32],
+        freqin: f32,
+        phasein: f32,
+        posin: f32,
+    ) -> A {
+        match self.table.interpolation {
+            Interpolation::Linear => {
+                self.render_layout::<A, LinearLayout>(phase, outbuf, freqin, phasein, posin)
+            }
+            Interpolation::Truncate => {
+                self.render_layout::<A, TruncateLayout>(phase, outbuf, freqin, phasein, posin)
+            }
+            Interpolation::Cubic => {
+                self.render_layout::<A, CubicLayout>(phase, outbuf, freqin, phasein, posin)
+            }
+            Interpolation::Sinc => {
+                self.render_layout::<A, SincLayout>(phase, outbuf, freqin, phasein, posin)
+            }
+        }
+    }
+
+    #[inline]
+    fn render_layout<A: Accumulator, L: Layout>(
+        &self,
+        mut phase: A,
+        outbuf: &mut [f32],
+        freqin: f32,
+        phasein: f32,
+        posin: f32,
+    ) -> A {
+        let table = &self.table;
+        let span = A::from_samples(table.len() as f64);
+        let step = self.cpstoinc * freqin as f64;
+        let mut inc = A::from_samples(step);
+        let mut offset = A::from_samples(self.radtoinc * phasein as f64);
+        if !table.pow2 {
+            inc = inc.modulo(span);
+            offset = offset.modulo(span);
+        }
+        let amount = self.warp_amount;
+        let stretch = self.warp.stretch(amount);
+        let (level, xfade) = table.mip_level(step.abs() as f32 * stretch, self.mipmode);
+        let (frame, morph) = table.frame_pos(posin);
+        for out in outbuf {
+            let mut phaseoffset = phase.add(offset);
+            if !table.pow2 {
+                phaseoffset = phaseoffset.wrap(span);
+            }
+            let (index, frac1) = self.warp_index(phaseoffset, amount);
+            *out = table.interpolate::<L>(index, frac1, frame, morph, level, xfade);
+            phase = phase.add(inc);
+            if !table.pow2 {
+                phase = phase.wrap(span);
+            }
+        }
+        phase
+    }
+
+    /* Runs the oscillator with control-rate inputs, producing and/or following sync events, and returns the final
+     * phase and the polyBLEP correction that's left over for the next sample
+     */
+    #[inline]
+    fn render_sync<A: Accumulator>(
+        &self,
+        phase: A,
+        outbuf: &mut [f32],
+        freqin: f32,
+        phasein: f32,
+        syncin: Option<&[Option<f32>]>,
+        syncout: Option<&mut [Option<f32>]>,
+    ) -> (A, f32) {
+        match self.table.interpolation {
+            Interpolation::Linear => self.render_sync_layout::<A, LinearLayout>(
+                phase, outbuf, freqin, phasein, syncin, syncout,
+            ),
+            Interpolation::Truncate => self.render_sync_layout::<A, TruncateLayout>(
+                phase, outbuf, freqin, phasein, syncin, syncout,
+            ),
+            Interpolation::Cubic => self.render_sync_layout::<A, CubicLayout>(
+                phase, outbuf, freqin, phasein, syncin, syncout,
+            ),
+            Interpolation::Sinc => self.render_sync_layout::<A, SincLayout>(
+                phase, outbuf, freqin, phasein, syncin, syncout,
+            ),
+        }
+    }
+
+    #[inline]
+    fn render_sync_layout<A: Accumulator, L: Layout>(
+        &self,
+        mut phase: A,
+        outbuf: &mut [f32],
+        freqin: f32,
+        phasein: f32,
+        syncin: Option<&[Option<f32>]>,
+        mut syncout: Option<&mut [Option<f32>]>,
+    ) -> (A, f32) {
+        let table = &self.table;
+        let len = table.len() as f64;
+        let span = A::from_samples(len);
+        let step = self.cpstoinc * freqin as f64;
+        let mut inc = A::from_samples(step);
+        let mut offset = A::from_samples(self.radtoinc * phasein as f64);
+        if !table.pow2 {
+            inc = inc.modulo(span);
+            offset = offset.modulo(span);
+        }
+        let (level, xfade) = table.mip_level(step.abs() as f32, self.mipmode);
+        let advance = |phase: A, inc: A| {
+            let phase = phase.add(inc);
+            if table.pow2 {
+                phase
+            } else {
+                phase.wrap(span)
+            }
+        };
+        let read = |phase: A| {
+            let phaseoffset = advance(phase, offset);
+            table.interpolate::<L>(
+                phaseoffset.index(table.lomask),
+                phaseoffset.frac1(),
+                0,
+                0.0,
+                level,
+                xfade,
+            )
+        };
+        // A distance in table samples, brought within a single span if need be
+        let distance = |samples: f64| {
+            let distance = A::from_samples(samples);
+            if table.pow2 {
+                distance
+            } else {
+                distance.modulo(span)
+            }
+        };
+        // The position within the cycle, in table samples
+        let cycle_pos = |phase: A| phase.index(table.lomask) as f64 + (phase.frac1() - 1.0) as f64;
+
+        let mut blep = self.blep;
+        for i in 0..outbuf.len() {
+            let mut out = read(phase) + blep;
+            blep = 0.0;
+            let next = advance(phase, inc);
+            if let Some(syncout) = syncout.as_deref_mut() {
+                let (pos, nextpos) = (cycle_pos(phase), cycle_pos(next));
+                syncout[i] = if step > 0.0 && nextpos < pos {
+                    Some((((len - pos) / step) as f32).clamp(0.0, 1.0))
+                } else if step < 0.0 && nextpos > pos {
+                    Some(((pos / -step) as f32).clamp(0.0, 1.0))
+                } else {
+                    None
+                };
+            }
+            match syncin.and_then(|syncin| syncin[i]) {
+                Some(frac) => {
+                    // Measure the step that the reset makes and spread it over this sample and the next
+                    let before = read(advance(phase, distance(frac as f64 * step)));
+                    let after = read(A::from_samples(0.0));
+                    let jump = after - before;
+                    out += jump * (1.0 - frac) * (1.0 - frac) / 2.0;
+                    blep = -jump * frac * frac / 2.0;
+                    phase = advance(A::from_samples(0.0), distance((1.0 - frac as f64) * step));
+                }
+                None => phase = next,
+            }
+            outbuf[i] = out;
+        }
+        (phase, blep)
+    }
+
+    /* Returns whether the vectorized loops can run this phasor (given a narrow phase)
+     */
+    #[cfg(target_arch = "x86_64")]
+    #[inline]
+    fn vectorizable(&self) -> bool {
+        self.table.pow2
+            && self.table.interpolation == Interpolation::Linear
+            && self.warp == Warp::Off
+            && simd::available()
+    }
+
+    /* Runs the oscillator with control-rate inputs on a narrow phase, using the vectorized loop if it can, and returns
+     * the final phase
+     */
+    #[inline]
+    fn render_narrow(
+        &self,
+        phase: Wrapping<i32>,
+        outbuf: &mut [f32],
+        freqin: f32,
+        phasein: f32,
+        posin: f32,
+    ) -> Wrapping<i32> {
+        #[cfg(target_arch = "x86_64")]
+        if self.vectorizable() {
+            let table = &self.table;
+            let step = self.cpstoinc * freqin as f64;
+            let inc: Wrapping<i32> = Accumulator::from_samples(step);
+            let offset: Wrapping<i32> = Accumulator::from_samples(self.radtoinc * phasein as f64);
+            let mip = table.mip_level(step.abs() as f32, self.mipmode);
+            let frame = table.frame_pos(posin);
+            let (blocks, rest) = outbuf.split_at_mut(outbuf.len() / simd::LANES * simd::LANES);
+            let phase =
+                unsafe { simd::render(table, phase.0, inc.0, offset.0, blocks, frame, mip) };
+            return self.render(Wrapping(phase), rest, freqin, phasein, posin);
+        }
+        self.render(phase, outbuf, freqin, phasein, posin)
+    }
+
+    /* Runs the oscillator with audio-rate inputs on a narrow phase, using the vectorized loop if it can, and returns the
+     * final phase
+     *
+     * The phases and mip levels are still calculated one sample at a time, but the table reads are vectorized for every
+     * block whose samples all read the same frame and mip level.
+     */
+    #[inline]
+    fn render_fm_narrow(
+        &self,
+        phase: Wrapping<i32>,
+        outbuf: &mut [f32],
+        freqin: impl Fn(usize) -> f32,
+        phasein: impl Fn(usize) -> f32,
+        posin: impl Fn(usize) -> f32,
+        amountin: impl Fn(usize) -> f32,
+    ) -> Wrapping<i32> {
+        #[cfg(target_arch = "x86_64")]
+        if self.vectorizable() {
+            // A sample's phase (with its offset), frame position and mip level
+            type Lane = (i32, (usize, f32), (usize, f32));
+            let table = &self.table;
+            let mut phase = phase;
+            let (blocks, rest) = outbuf.split_at_mut(outbuf.len() / simd::LANES * simd::LANES);
+            for (b, block) in blocks.chunks_exact_mut(simd::LANES).enumerate() {
+                let lanes: [Lane; simd::LANES] = std::array::from_fn(|k| {
+                    let i = b * simd::LANES + k;
+                    let step = self.cpstoinc * freqin(i) as f64;
+                    let inc: Wrapping<i32> = Accumulator::from_samples(step);
+                    let offset: Wrapping<i32> =
+                        Accumulator::from_samples(self.radtoinc * phasein(i) as f64);
+                    let phaseoffset = phase + offset;
+                    phase += inc;
+                    (
+                        phaseoffset.0,
+                        table.frame_pos(posin(i)),
+                        table.mip_level(step.abs() as f32, self.mipmode),
+                    )
+                });
+                let (_, (frame, _), (level, _)) = lanes[0];
+                if lanes
+                    .iter()
+                    .all(|(_, (f, _), (l, _))| *f == frame && *l == level)
+                {
+                    let phases = lanes.map(|(p, _, _)| p);
+                    let morphs = lanes.map(|(_, (_, m), _)| m);
+                    let xfades = lanes.map(|(_, _, (_, x))| x);
+                    unsafe {
+                        simd::interpolate_block(
+                            table,
+                            &phases,
+                            (frame, &morphs),
+                            (level, &xfades),
+                            block,
+                        )
+                    };
+                } else {
+                    for (out, (p, (frame, morph), (level, xfade))) in block.iter_mut().zip(lanes) {
+                        let p = Wrapping(p);
+                        *out = table.interpolate::<LinearLayout>(
+                            p.index(table.lomask),
+                            p.frac1(),
+                            frame,
+                            morph,
+                            level,
+                            xfade,
+                        );
+                    }
+                }
+            }
+            let done = blocks.len();
+            return self.render_fm(
+                phase,
+                rest,
+                |i| freqin(i + done),
+                |i| phasein(i + done),
+                |i| posin(i + done),
+                |i| amountin(i + done),
+            );
+        }
+        self.render_fm(phase, outbuf, freqin, phasein, posin, amountin)
+    }
+
+    /* Returns the table index and 1.m fraction of the phase, after it has been warped
+     */
+    #[inline]
+    fn warp_index<A: Accumulator>(&self, phase: A, amount: f32) -> (usize, f32) {
+        let table = &self.table;
+        if self.warp == Warp::Off {
+            return (phase.index(table.lomask), phase.frac1());
+        }
+        let len = table.len();
+        let pos = (phase.index(table.lomask) as f64 + (phase.frac1() - 1.0) as f64) / len as f64;
+        let samples = (self.warp.apply(pos, amount, len) * len as f64).rem_euclid(len as f64);
+        let index = samples.floor();
+        (
+            (index as usize).min(len - 1),
+            (1.0 + samples - index) as f32,
+        )
+    }
+
+    /* Runs the oscillator with audio-rate inputs and returns the final phase
+     */
+    #[inline]
+    fn render_fm<A: Accumulator>(
+        &self,
+        phase: A,
+        outbuf: &mut [f32],
+        freqin: impl Fn(usize) -> f32,
+        phasein: impl Fn(usize) -> f32,
+        posin: impl Fn(usize) -> f32,
+        amountin: impl Fn(usize) -> f32,
+    ) -> A {
+        match self.table.interpolation {
+            Interpolation::Linear => self.render_fm_layout::<A, LinearLayout>(
+                phase, outbuf, freqin, phasein, posin, amountin,
+            ),
+            Interpolation::Truncate => self.render_fm_layout::<A, TruncateLayout>(
+                phase, outbuf, freqin, phasein, posin, amountin,
+            ),
+            Interpolation::Cubic => self.render_fm_layout::<A, CubicLayout>(
+                phase, outbuf, freqin, phasein, posin, amountin,
+            ),
+            Interpolation::Sinc => self
+                .render_fm_layout::<A, SincLayout>(phase, outbuf, freqin, phasein, posin, amountin),
+        }
+    }
+
+    #[inline]
+    fn render_fm_layout<A: Accumulator, L: Layout>(
+        &self,
+        mut phase: A,
+        outbuf: &mut [f32],
+        freqin: impl Fn(usize) -> f32,
+        phasein: impl Fn(usize) -> f32,
+        posin: impl Fn(usize) -> f32,
+        amountin: impl Fn(usize) -> f32,
+    ) -> A {
+        let table = &self.table;
+        let span = A::from_samples(table.len() as f64);
+        for (i, out) in outbuf.iter_mut().enumerate() {
+            let step = self.cpstoinc * freqin(i) as f64;
+            let mut inc = A::from_samples(step);
+            let mut offset = A::from_samples(self.radtoinc * phasein(i) as f64);
+            let mut phaseoffset = phase;
+            if table.pow2 {
+                phaseoffset = phaseoffset.add(offset);
+            } else {
+                // The modulation inputs can be any size, but they're rarely more than the table's length
+                inc = inc.reduce(span);
+                offset = offset.reduce(span);
+                phaseoffset = phaseoffset.add(offset).wrap(span);
+            }
+            let amount = amountin(i);
+            let stretch = self.warp.stretch(amount);
+            let (level, xfade) = table.mip_level(step.abs() as f32 * stretch, self.mipmode);
+            let (frame, morph) = table.frame_pos(posin(i));
+            let (index, frac1) = self.warp_index(phaseoffset, amount);
+            *out = table.interpolate::<L>(index, frac1, frame, morph, level, xfade);
+            phase = phase.add(inc);
+            if !table.pow2 {
+                phase = phase.wrap(span);
+            }
+        }
+        phase
+    }
+}
+
+#[repr(C)]
+union PhaseConv {
+    iphase: i32,
+    fphase: f32,
+}
+
+#[inline]
+fn phase_frac1(phase: i32) -> f32 {
+    let p = PhaseConv {
+        iphase: 0x3F800000 | (0x007FFF80 & ((phase) << 7)),
+    };
+    unsafe { p.fphase }
 }
 
+/* The phase_frac1 trick for a wide phase. The top 23 of the 32 fractional bits make up the significand.
+ */
 #[inline]
-fn phase_frac1(phase: i32) -> f32 {
+fn phase_frac1_wide(phase: i64) -> f32 {
     let p = PhaseConv {
-        iphase: 0x3F800000 | (0x007FFF80 & ((phase) << 7)),
+        iphase: 0x3F800000 | (0x007FFFFF & ((phase >> 9) as i32)),
     };
     unsafe { p.fphase }
 }
@@ -332,15 +2707,32 @@ fn phase_frac1(phase: i32) -> f32 {
 #[cfg(test)]
 mod tests {
     use super::super::system::System;
-    use super::{Phasor, Wavetable};
+    use super::{
+        Accumulator, ImportOptions, Interpolation, MipMode, Normalize, Phase, PhaseMode, Phasor,
+        Shape, Warp, Wavetable,
+    };
     use float_cmp::approx_eq;
+    use rustfft::{num_complex::Complex, FftPlanner};
     use std::f32::consts::PI;
+    use std::num::Wrapping;
     use std::sync::Arc;
 
     fn generate_ramp(len: usize) -> Vec<f32> {
         Vec::from_iter((0..len).map(|v| -> f32 { v as f32 }))
     }
 
+    fn generate_saw(len: usize) -> Vec<f32> {
+        Vec::from_iter((0..len).map(|v| -> f32 { 2.0 * v as f32 / len as f32 - 1.0 }))
+    }
+
+    fn spectrum(buffer: &[f32]) -> Vec<f32> {
+        let mut planner = FftPlanner::new();
+        let fft = planner.plan_fft_forward(buffer.len());
+        let mut fftbuf = Vec::from_iter(buffer.iter().map(|re| Complex { re: *re, im: 0.0 }));
+        fft.process(&mut fftbuf);
+        Vec::from_iter(fftbuf[..buffer.len() / 2].iter().map(|c| c.norm()))
+    }
+
     #[test]
     fn test_create_wavetable() {
         let table = generate_ramp(128);
@@ -567,4 +2959,991 @@ mod tests {
         let wt = Wavetable::from_sndfile("test/LongVoice.wav", true).unwrap();
         assert_eq!(wt.len(), 512); // LongVoice.wav fundamental is 469 samps long, which rounds up to 512 samples
     }
+
+    #[test]
+    fn test_mip_levels() {
+        let table_len = 1024;
+        let wt = Wavetable::new(&generate_saw(table_len));
+        assert_eq!(wt.levels(), 10);
+
+        for (n, level) in wt.frames[0].levels.iter().enumerate() {
+            let spec = spectrum(&level.samples());
+            let top = table_len >> (n + 1);
+            let highest = top.min(spec.len() - 1);
+            assert!(spec[highest] > 1.0, "Level {} lost harmonic {}", n, highest);
+            for (harm, mag) in spec.iter().enumerate().skip(top + 1) {
+                assert!(
+                    *mag < 1e-3,
+                    "Level {} has harmonic {} with magnitude {}",
+                    n,
+                    harm,
+                    mag
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_mip_aliasing() {
+        //! A saw played at bin 67 of the FFT should only have energy in multiples of bin 67. Anything else is aliasing.
+        let fs = 48000.0;
+        let fftlen = 4096;
+        let fund_bin = 67;
+        let system = Arc::new(System::new(fs, 1, fftlen));
+        let wt = Arc::new(Wavetable::new(&generate_saw(1024)));
+        let freq = fs * fund_bin as f32 / fftlen as f32;
+
+        for mode in [MipMode::Pick, MipMode::Crossfade] {
+            let mut phasor = Phasor::new(&system, &wt);
+            phasor.set_mipmode(mode);
+            let mut outbuf = vec![0.0; fftlen];
+            phasor.perform(&mut outbuf, freq, 0.0);
+
+            let spec = spectrum(&outbuf);
+            let total = spec.iter().fold(0.0, |sum, m| sum + m * m);
+            let aliased = spec
+                .iter()
+                .enumerate()
+                .filter(|(bin, _)| bin % fund_bin != 0)
+                .fold(0.0, |sum, (_, m)| sum + m * m);
+            assert!(
+                aliased / total < 1e-4,
+                "{:?}: aliased energy ratio is {}",
+                mode,
+                aliased / total
+            );
+        }
+    }
+
+    #[test]
+    fn test_warp_aliasing() {
+        //! A small mirror amount makes a short, steep return trip, which has to pick a darker mip level. The corner at
+        //! the turning point still spreads a little energy, so the bound is looser than for a plain saw.
+        let fs = 48000.0;
+        let fftlen = 4096;
+        let fund_bin = 67;
+        let system = Arc::new(System::new(fs, 1, fftlen));
+        let wt = Arc::new(Wavetable::new(&generate_saw(1024)));
+        let freq = fs * fund_bin as f32 / fftlen as f32;
+
+        for amount in [0.1, 0.2, 0.5] {
+            let mut phasor = Phasor::new(&system, &wt);
+            phasor.set_warp(Warp::Mirror);
+            phasor.set_warp_amount(amount);
+            let mut outbuf = vec![0.0; fftlen];
+            phasor.perform(&mut outbuf, freq, 0.0);
+
+            let spec = spectrum(&outbuf);
+            let total = spec.iter().fold(0.0, |sum, m| sum + m * m);
+            let aliased = spec
+                .iter()
+                .enumerate()
+                .filter(|(bin, _)| bin % fund_bin != 0)
+                .fold(0.0, |sum, (_, m)| sum + m * m);
+            assert!(
+                aliased / total < 3e-3,
+                "{}: aliased energy ratio is {}",
+                amount,
+                aliased / total
+            );
+        }
+    }
+
+    #[test]
+    fn test_morph_frames() {
+        let fs = 1024.0;
+        let system = Arc::new(System::new(fs, 1, 1024));
+        let table_len = 128;
+
+        // The first frame is a ramp and the second is a constant
+        let mut table = generate_ramp(table_len);
+        table.extend(vec![64.0; table_len]);
+        let wt = Arc::new(Wavetable::new_frames(&table, table_len));
+        assert_eq!(wt.frames(), 2);
+        assert_eq!(wt.len(), table_len);
+
+        for position in [0.0, 0.25, 1.0] {
+            let mut phasor = Phasor::new(&system, &wt);
+            let mut outbuf = [0.0; 1000];
+            phasor.perform_morph(&mut outbuf, 1.0, 0.0, position);
+            for (i, v) in outbuf.iter().enumerate() {
+                let ramp = (table_len * i) as f32 / fs;
+                let expected = ramp + position * (64.0 - ramp);
+                assert!(
+                    approx_eq!(f32, *v, expected, epsilon = 1e-3),
+                    "position {}: out[{}] = {}, expected: {}",
+                    position,
+                    i,
+                    *v,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_morph_frames_fm() {
+        let fs = 1024.0;
+        let system = Arc::new(System::new(fs, 1, 1024));
+        let table_len = 128;
+
+        // Three constant frames
+        let mut table = vec![0.0; table_len];
+        table.extend(vec![1.0; table_len]);
+        table.extend(vec![4.0; table_len]);
+        let wt = Arc::new(Wavetable::new_frames(&table, table_len));
+        let mut phasor = Phasor::new(&system, &wt);
+
+        let freqin = [1.0; 5];
+        let phasein = [0.0; 5];
+        let posin = [0.0, 0.25, 0.5, 0.75, 1.0];
+        let mut outbuf = [0.0; 5];
+        phasor.perform_morph_fm(&mut outbuf, &freqin, &phasein, &posin);
+        for (v, expected) in outbuf.iter().zip([0.0, 0.5, 1.0, 2.5, 4.0]) {
+            assert!(
+                approx_eq!(f32, *v, expected, epsilon = 1e-3),
+                "Got {}, expected: {}",
+                *v,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Wavetable of 200 samples can't be split into frames of 128")]
+    fn test_create_frames_bad() {
+        let table = generate_ramp(200);
+        let _wt = Wavetable::new_frames(&table, 128);
+    }
+
+    #[test]
+    fn test_from_sndfile_frames() {
+        // triangle.wav is 4096 samples long
+        let wt = Wavetable::from_sndfile_frames("test/triangle.wav", 1024).unwrap();
+        assert_eq!(wt.frames(), 4);
+        assert_eq!(wt.len(), 1024);
+
+        let wt = Wavetable::from_sndfile_frames("test/saw.wav", 512).unwrap();
+        assert_eq!(wt.frames(), 2); // saw.wav is 1200 samples long, which holds 2 full frames
+
+        assert!(Wavetable::from_sndfile_frames("test/saw.wav", 2048).is_err());
+        assert!(Wavetable::from_sndfile_frames("test/saw.wav", 500).is_err());
+        assert!(Wavetable::from_sndfile_frames("test/saw.wav", 0).is_err());
+    }
+
+    #[test]
+    fn test_interpolation_samples() {
+        //! Every layout should be able to give back the samples it was built from
+        let table = generate_saw(64);
+        let mut wt = Wavetable::new(&table);
+        for mode in [
+            Interpolation::Truncate,
+            Interpolation::Cubic,
+            Interpolation::Sinc,
+            Interpolation::Linear,
+        ] {
+            wt = wt.with_interpolation(mode);
+            assert_eq!(wt.interpolation(), mode);
+            assert_eq!(wt.len(), 64);
+            for (got, expected) in wt.frames[0].levels[0].samples().iter().zip(table.iter()) {
+                assert!(
+                    approx_eq!(f32, *got, *expected, epsilon = 1e-6),
+                    "{:?}: got {}, expected: {}",
+                    mode,
+                    got,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_interpolation_accuracy() {
+        //! Reads a 16-point sine table between its samples and compares the error of each mode
+        let fs = 1024.0;
+        let system = Arc::new(System::new(fs, 1, 1024));
+        let table_len = 16;
+        let table =
+            Vec::from_iter((0..table_len).map(|i| (2.0 * PI * i as f32 / table_len as f32).sin()));
+
+        let max_error = |mode: Interpolation| -> f32 {
+            let wt = Arc::new(Wavetable::new(&table).with_interpolation(mode));
+            let mut phasor = Phasor::new(&system, &wt);
+            let mut outbuf = [0.0; 1024];
+            phasor.perform(&mut outbuf, 1.0, 0.0);
+            outbuf.iter().enumerate().fold(0.0, |max, (i, v)| {
+                let expected = (2.0 * PI * i as f32 / fs).sin();
+                max.max((v - expected).abs())
+            })
+        };
+
+        let truncate = max_error(Interpolation::Truncate);
+        let linear = max_error(Interpolation::Linear);
+        let cubic = max_error(Interpolation::Cubic);
+        let sinc = max_error(Interpolation::Sinc);
+        assert!(
+            truncate > linear,
+            "truncate error: {}, linear error: {}",
+            truncate,
+            linear
+        );
+        assert!(
+            cubic < linear / 4.0,
+            "cubic error: {}, linear error: {}",
+            cubic,
+            linear
+        );
+        assert!(
+            sinc < linear / 4.0,
+            "sinc error: {}, linear error: {}",
+            sinc,
+            linear
+        );
+    }
+
+    #[test]
+    fn test_wide_phase_tuning() {
+        //! 20Hz on a 16 sample table should return to a phase of exactly zero after one second
+        let fs = 48000.0;
+        let system = Arc::new(System::new(fs, 1, 1000));
+        let table_len = 16;
+        let wt = Arc::new(Wavetable::new(&generate_ramp(table_len)));
+
+        let mut narrow = Phasor::new(&system, &wt);
+        let mut wide = Phasor::new(&system, &wt);
+        wide.set_phasemode(PhaseMode::Wide);
+        assert_eq!(narrow.phasemode(), PhaseMode::Narrow);
+        assert_eq!(wide.phasemode(), PhaseMode::Wide);
+
+        let mut outbuf = [0.0; 1000];
+        for _ in 0..48 {
+            narrow.perform(&mut outbuf, 20.0, 0.0);
+            wide.perform(&mut outbuf, 20.0, 0.0);
+        }
+
+        // Distance from a whole number of cycles
+        let cycle_error = |phasor: &Phasor| -> f64 {
+            let cycles = match phasor.phase {
+                Phase::Narrow(phase) => phase.0 as f64 / (table_len as f64 * 65536.0),
+                Phase::Wide(phase) => phase.0 as f64 / (table_len as f64 * 4294967296.0),
+            };
+            (cycles - cycles.round()).abs()
+        };
+        let (narrow, wide) = (cycle_error(&narrow), cycle_error(&wide));
+        assert!(narrow > 1e-2, "narrow drift: {} cycles", narrow);
+        assert!(wide < 1e-6, "wide drift: {} cycles", wide);
+    }
+
+    #[test]
+    fn test_wide_phase_long_table() {
+        //! A table longer than 2^16 should automatically use a wide phase and be read just like a short one
+        let table_len = 1 << 17;
+        let fs = (1 << 18) as f32;
+        let system = Arc::new(System::new(fs, 1, 4096));
+        let wt = Arc::new(Wavetable::new(&generate_ramp(table_len)));
+        let mut phasor = Phasor::new(&system, &wt);
+        assert_eq!(phasor.phasemode(), PhaseMode::Wide);
+
+        let mut outbuf = [0.0; 4096];
+        phasor.perform(&mut outbuf, 1.0, PI);
+        for (i, v) in outbuf.iter().enumerate() {
+            // Starting half way through the table, the ramp rises by half a sample per output sample
+            let expected = (table_len / 2) as f32 + 0.5 * i as f32;
+            assert!(
+                approx_eq!(f32, *v, expected, epsilon = 1e-2),
+                "out[{}] = {}, expected: {}",
+                i,
+                *v,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "A narrow phase can't index a wavetable of 131072 samples")]
+    fn test_narrow_phase_long_table() {
+        let system = Arc::new(System::new(48000.0, 1, 1024));
+        let wt = Arc::new(Wavetable::new(&generate_ramp(1 << 17)));
+        let mut phasor = Phasor::new(&system, &wt);
+        phasor.set_phasemode(PhaseMode::Narrow);
+    }
+
+    #[test]
+    fn test_exact_phasor() {
+        //! A 100 sample ramp should be read without any resampling, and should wrap at exactly 100 samples
+        let fs = 1000.0;
+        let system = Arc::new(System::new(fs, 1, 1000));
+        let table_len = 100;
+        let wt = Arc::new(Wavetable::new_exact(&generate_ramp(table_len)));
+        assert!(!wt.is_pow2());
+        assert_eq!(wt.len(), table_len);
+
+        for mode in [PhaseMode::Narrow, PhaseMode::Wide] {
+            let mut phasor = Phasor::new(&system, &wt);
+            phasor.set_phasemode(mode);
+
+            // 2.5 cycles, a quarter of a sample per output sample, starting at a phase of pi
+            let mut outbuf = [0.0; 1000];
+            phasor.perform(&mut outbuf, 0.25 * fs / table_len as f32, PI);
+            for (i, v) in outbuf.iter().enumerate() {
+                let phase = (50.0 + 0.25 * i as f32) % table_len as f32;
+                let expected = if phase < (table_len - 1) as f32 {
+                    phase
+                } else {
+                    // Interpolate from the last sample back down to the first
+                    (table_len - 1) as f32 * (table_len as f32 - phase)
+                };
+                assert!(
+                    approx_eq!(f32, *v, expected, epsilon = 1e-3),
+                    "{:?}: out[{}] = {}, expected: {}",
+                    mode,
+                    i,
+                    *v,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_exact_phasor_fm() {
+        //! Negative frequencies and large phase offsets should wrap within the table
+        let fs = 1000.0;
+        let system = Arc::new(System::new(fs, 1, 1000));
+        let table_len = 100;
+        let wt = Arc::new(Wavetable::new_exact(&generate_ramp(table_len)));
+        let mut phasor = Phasor::new(&system, &wt);
+
+        let freqin = [-2.5; 8];
+        let phasein = [7.0 * PI; 8]; // 3.5 cycles
+        let mut outbuf = [0.0; 8];
+        phasor.perform_fm(&mut outbuf, &freqin, &phasein);
+        for (i, v) in outbuf.iter().enumerate() {
+            let expected = 50.0 - 0.25 * i as f32;
+            assert!(
+                approx_eq!(f32, *v, expected, epsilon = 1e-3),
+                "out[{}] = {}, expected: {}",
+                i,
+                *v,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_reduce() {
+        //! Distances within a span are left alone, and only the longer ones are brought into range
+        fn check<A: Accumulator + PartialEq + std::fmt::Debug>() {
+            let span = A::from_samples(100.0);
+            for (samples, expected) in [(-99.5, -99.5), (99.5, 99.5), (250.0, 50.0), (-250.0, 50.0)]
+            {
+                assert_eq!(
+                    A::from_samples(samples).reduce(span),
+                    A::from_samples(expected),
+                    "{}",
+                    samples
+                );
+            }
+        }
+        check::<Wrapping<i32>>();
+        check::<Wrapping<i64>>();
+    }
+
+    #[test]
+    fn test_from_sndfile_exact() {
+        let wt = Wavetable::from_sndfile_exact("test/LongVoice.wav", true).unwrap();
+        assert_eq!(wt.len(), 469); // LongVoice.wav fundamental is 469 samps long
+        assert!(!wt.is_pow2());
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        // A table from memory is the same as one from the file
+        let wt = Wavetable::from_bytes(include_bytes!("../test/saw.wav"), false).unwrap();
+        let expected = Wavetable::from_sndfile("test/saw.wav", false).unwrap();
+        assert_eq!(wt.samples(), expected.samples());
+
+        // And so is a trimmed one read from a reader
+        let file = std::fs::File::open("test/LongVoice.wav").unwrap();
+        let wt = Wavetable::from_reader(file, true).unwrap();
+        let expected = Wavetable::from_sndfile("test/LongVoice.wav", true).unwrap();
+        assert_eq!(wt.samples(), expected.samples());
+
+        assert!(Wavetable::from_bytes(b"not audio", false).is_err());
+    }
+
+    #[test]
+    fn test_sync_master_events() {
+        let fs = 1000.0;
+        let system = Arc::new(System::new(fs, 1, 1000));
+        let wt = Arc::new(Wavetable::new(&generate_ramp(128)));
+        let mut master = Phasor::new(&system, &wt);
+        master.set_phasemode(PhaseMode::Wide);
+
+        // Check the event times going forward and backward
+        for (freq, expected) in [
+            (3.0, vec![(333, 1.0 / 3.0), (666, 2.0 / 3.0)]),
+            (-3.0, vec![(0, 0.0), (333, 1.0 / 3.0), (666, 2.0 / 3.0)]),
+        ] {
+            master.zero();
+            let mut outbuf = [0.0; 1000];
+            let mut syncbuf = [None; 1000];
+            master.perform_master(&mut outbuf, freq, 0.0, &mut syncbuf);
+            let events = Vec::from_iter(
+                syncbuf
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, e)| e.map(|frac| (i, frac))),
+            );
+            assert_eq!(events.len(), expected.len(), "events: {:?}", events);
+            for ((i, frac), (ei, efrac)) in events.iter().zip(expected) {
+                assert_eq!(*i, ei);
+                assert!(
+                    approx_eq!(f32, *frac, efrac, epsilon = 1e-4),
+                    "frac = {}, expected: {}",
+                    frac,
+                    efrac
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_sync_slave() {
+        //! The slave resets at the master's event and the step is smoothed over the samples on either side of it
+        let fs = 1000.0;
+        let system = Arc::new(System::new(fs, 1, 1000));
+        let wt = Arc::new(Wavetable::new(&generate_ramp(128)));
+        let mut master = Phasor::new(&system, &wt);
+        let mut slave = Phasor::new(&system, &wt);
+
+        let mut masterbuf = [0.0; 400];
+        let mut syncbuf = [None; 400];
+        master.perform_master(&mut masterbuf, 3.0, 0.0, &mut syncbuf);
+        let frac = syncbuf[333].expect("The master should wrap after sample 333");
+
+        // Split the slave's buffer in two, so that the pending correction has to carry over to the next call
+        let step = 0.5; // 0.5 table samples per output sample
+        let mut outbuf = [0.0; 400];
+        slave.perform_slave(&mut outbuf[..334], 3.90625, 0.0, &syncbuf[..334]);
+        slave.perform_slave(&mut outbuf[334..], 3.90625, 0.0, &syncbuf[334..]);
+
+        let before = 38.5 + frac * step; // The slave's phase at the time of the reset
+        let jump = -before;
+        let expected = [
+            (332, 38.0),
+            (333, 38.5 + jump * (1.0 - frac) * (1.0 - frac) / 2.0),
+            (334, (1.0 - frac) * step - jump * frac * frac / 2.0),
+            (340, (7.0 - frac) * step),
+        ];
+        for (i, expected) in expected {
+            assert!(
+                approx_eq!(f32, outbuf[i], expected, epsilon = 1e-3),
+                "out[{}] = {}, expected: {}",
+                i,
+                outbuf[i],
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_through_zero_fm() {
+        //! A negative linear FM input that outweighs the frequency should run the phase backward
+        let fs = 1024.0;
+        let system = Arc::new(System::new(fs, 1, 256));
+        let wt = Arc::new(Wavetable::new(&generate_ramp(128)));
+        let mut phasor = Phasor::new(&system, &wt);
+        phasor.set_mipmode(MipMode::Pick);
+        phasor.set_fm_depth(16.0);
+
+        let linin = [-1.0; 256];
+        let mut outbuf = [0.0; 256];
+        phasor.perform_mod(&mut outbuf, 8.0, Some(&linin), None, None);
+        for (i, v) in outbuf.iter().enumerate() {
+            let expected = ((128 - i % 128) % 128) as f32;
+            assert!(
+                approx_eq!(f32, *v, expected, epsilon = 1e-3),
+                "out[{}] = {}, expected: {}",
+                i,
+                *v,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_perform_mod() {
+        //! The scaled modulation inputs should match the equivalent raw frequency and phase buffers
+        let fs = 48000.0;
+        let system = Arc::new(System::new(fs, 1, 512));
+        let sine = Vec::from_iter((0..1024).map(|i| (i as f32 * 2.0 * PI / 1024.0).sin()));
+        let wt = Arc::new(Wavetable::new(&sine));
+        let mut phasor = Phasor::new(&system, &wt);
+        let mut reference = Phasor::new(&system, &wt);
+        phasor.set_fm_depth(300.0);
+        phasor.set_pm_index(2.5);
+
+        let freq = 220.0;
+        let linin = Vec::from_iter((0..512).map(|i| (i as f32 * 0.05).sin()));
+        let expin = Vec::from_iter((0..512).map(|i| 24.0 * i as f32 / 512.0 - 12.0));
+        let pmin = Vec::from_iter((0..512).map(|i| (i as f32 * 0.03).cos()));
+        let mut outbuf = [0.0; 512];
+        phasor.perform_mod(&mut outbuf, freq, Some(&linin), Some(&expin), Some(&pmin));
+
+        let freqin =
+            Vec::from_iter((0..512).map(|i| freq * 2f32.powf(expin[i] / 12.0) + 300.0 * linin[i]));
+        let phasein = Vec::from_iter(pmin.iter().map(|p| 2.5 * p));
+        let mut refbuf = [0.0; 512];
+        reference.perform_fm(&mut refbuf, &freqin, &phasein);
+        for (i, (v, r)) in outbuf.iter().zip(refbuf).enumerate() {
+            assert!(
+                approx_eq!(f32, *v, r, epsilon = 1e-4),
+                "out[{}] = {}, expected: {}",
+                i,
+                *v,
+                r
+            );
+        }
+    }
+
+    #[test]
+    fn test_warp_off() {
+        //! A zero amount shouldn't change the output of any of the continuous warps
+        let system = Arc::new(System::new(48000.0, 1, 512));
+        let wt = Arc::new(Wavetable::new(&generate_saw(1024)));
+        let mut expected = [0.0; 512];
+        Phasor::new(&system, &wt).perform(&mut expected, 110.0, 0.0);
+        for warp in [Warp::Bend, Warp::Squeeze, Warp::Mirror, Warp::Sync] {
+            let mut phasor = Phasor::new(&system, &wt);
+            phasor.set_warp(warp);
+            let mut outbuf = [0.0; 512];
+            phasor.perform(&mut outbuf, 110.0, 0.0);
+            for (i, (v, e)) in outbuf.iter().zip(expected).enumerate() {
+                assert!(
+                    approx_eq!(f32, *v, e, epsilon = 1e-5),
+                    "{:?}: out[{}] = {}, expected: {}",
+                    warp,
+                    i,
+                    *v,
+                    e
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_warp_modes() {
+        //! A 1Hz ramp at 1024Hz puts the phase of sample i at i/1024 of the cycle
+        let fs = 1024.0;
+        let system = Arc::new(System::new(fs, 1, 1024));
+        let wt = Arc::new(Wavetable::new(&generate_ramp(128)));
+        let cases = [
+            // The knee moves to 0.275, so the first half of the table is read over the first 0.275 of the cycle
+            (
+                Warp::Bend,
+                0.5,
+                [
+                    (102, 102.0 / 1024.0 * 0.5 / 0.275),
+                    (768, 0.5 + 0.5 * 0.475 / 0.725),
+                ],
+            ),
+            // The table is squeezed into the first 0.525 of the cycle
+            (Warp::Squeeze, 0.5, [(256, 0.25 / 0.525), (768, 0.0)]),
+            (Warp::Mirror, 1.0, [(256, 0.5), (768, 0.5)]),
+            // Two repeats per cycle
+            (Warp::Sync, 0.25, [(256, 0.5), (768, 0.5)]),
+            // 128^(3/7) = 8 steps
+            (Warp::Quantize, 4.0 / 7.0, [(307, 0.25), (800, 0.75)]),
+        ];
+        for (warp, amount, expected) in cases {
+            let mut phasor = Phasor::new(&system, &wt);
+            phasor.set_warp(warp);
+            phasor.set_warp_amount(amount);
+            let mut outbuf = [0.0; 1024];
+            phasor.perform(&mut outbuf, 1.0, 0.0);
+
+            // The audio-rate amount should match the control-rate one
+            let mut fmbuf = [0.0; 1024];
+            let mut fmphasor = Phasor::new(&system, &wt);
+            fmphasor.set_warp(warp);
+            fmphasor.perform_warp_fm(&mut fmbuf, &[1.0; 1024], &[0.0; 1024], &[amount; 1024]);
+            assert_eq!(outbuf, fmbuf);
+
+            for (i, pos) in expected {
+                let expected = pos * 128.0;
+                assert!(
+                    approx_eq!(f32, outbuf[i], expected, epsilon = 1e-3),
+                    "{:?}: out[{}] = {}, expected: {}",
+                    warp,
+                    i,
+                    outbuf[i],
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_vectorized_phasor() {
+        //! The vectorized loops should match the scalar ones exactly, including the samples after the last whole block
+        let system = Arc::new(System::new(48000.0, 1, 1001));
+        let mut frames = generate_saw(1024);
+        frames.extend(Vec::from_iter(
+            (0..1024).map(|i| (i as f32 * 2.0 * PI / 1024.0).sin()),
+        ));
+        let wt = Arc::new(Wavetable::new_frames(&frames, 1024));
+        let phasor = Phasor::new(&system, &wt);
+        let start = Wrapping(12345);
+
+        for (freq, phase, pos) in [
+            (110.0, 0.0, 0.0),
+            (3520.0, 1.0, 0.3),
+            (-440.0, -2.0, 1.0),
+            (30.0, 0.5, 0.75),
+        ] {
+            let mut expected = [0.0; 1001];
+            let mut outbuf = [0.0; 1001];
+            let end = phasor.render(start, &mut expected, freq, phase, pos);
+            assert_eq!(
+                phasor.render_narrow(start, &mut outbuf, freq, phase, pos),
+                end
+            );
+            assert_eq!(
+                outbuf, expected,
+                "freq: {}, phase: {}, pos: {}",
+                freq, phase, pos
+            );
+        }
+
+        // Sweep across the mip levels, so that some blocks read more than one level
+        let freqin = Vec::from_iter((0..1001).map(|i| 20.0 * 1000f32.powf(i as f32 / 1000.0)));
+        let phasein = Vec::from_iter((0..1001).map(|i| (i as f32 * 0.01).sin()));
+        let posin = Vec::from_iter((0..1001).map(|i| i as f32 / 1000.0));
+        let mut expected = [0.0; 1001];
+        let mut outbuf = [0.0; 1001];
+        let end = phasor.render_fm(
+            start,
+            &mut expected,
+            |i| freqin[i],
+            |i| phasein[i],
+            |i| posin[i],
+            |_| 0.0,
+        );
+        let vectorized = phasor.render_fm_narrow(
+            start,
+            &mut outbuf,
+            |i| freqin[i],
+            |i| phasein[i],
+            |i| posin[i],
+            |_| 0.0,
+        );
+        assert_eq!(vectorized, end);
+        assert_eq!(outbuf, expected);
+    }
+
+    #[test]
+    fn test_from_harmonics() {
+        let wt = Wavetable::from_harmonics(512, &[0.5, 0.0, 0.25], &[0.0, 0.0, PI]);
+        assert_eq!(wt.len(), 512);
+        let spec = spectrum(&wt.frames[0].levels[0].samples());
+        assert!(
+            approx_eq!(f32, spec[1], 0.5 * 256.0, epsilon = 1e-2),
+            "h1: {}",
+            spec[1]
+        );
+        assert!(
+            approx_eq!(f32, spec[3], 0.25 * 256.0, epsilon = 1e-2),
+            "h3: {}",
+            spec[3]
+        );
+        assert!(spec[2] < 1e-3, "h2: {}", spec[2]);
+    }
+
+    #[test]
+    fn test_from_shape() {
+        //! Away from their corners, the shapes should be close to their ideal waveforms
+        let len = 1024;
+        let ideal = |shape: Shape, t: f32| match shape {
+            Shape::Sine => (2.0 * PI * t).sin(),
+            Shape::Saw => 2.0 * t - 1.0,
+            Shape::Square => {
+                if t < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Shape::Triangle => 1.0 - 4.0 * (t - 0.25).abs().min((t - 1.25).abs()),
+            Shape::Pulse(width) => {
+                if t < width {
+                    2.0 - 2.0 * width
+                } else {
+                    -2.0 * width
+                }
+            }
+        };
+        for shape in [
+            Shape::Sine,
+            Shape::Saw,
+            Shape::Square,
+            Shape::Triangle,
+            Shape::Pulse(0.25),
+        ] {
+            let wt = Wavetable::from_shape(shape, len);
+            let samples = wt.frames[0].levels[0].samples();
+            for t in [0.1, 0.2, 0.3, 0.4, 0.6, 0.7, 0.8, 0.9] {
+                let v = samples[(t * len as f32) as usize];
+                let expected = ideal(shape, t);
+                assert!(
+                    approx_eq!(f32, v, expected, epsilon = 1e-2),
+                    "{:?} at {}: {}, expected: {}",
+                    shape,
+                    t,
+                    v,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_expr() {
+        let wt = Wavetable::from_expr("sin(x)", 256).unwrap();
+        let sine = Wavetable::from_shape(Shape::Sine, 256);
+        let samples = wt.frames[0].levels[0].samples();
+        for (v, e) in samples.iter().zip(sine.frames[0].levels[0].samples()) {
+            assert!(approx_eq!(f32, *v, e, epsilon = 1e-5), "{} != {}", v, e);
+        }
+
+        let wt = Wavetable::from_expr_frames("frame", 64, 5).unwrap();
+        assert_eq!(wt.frames(), 5);
+        assert_eq!(wt.frames[4].levels[0].samples()[10], 1.0);
+
+        let wt = Wavetable::from_harmonic_expr("2 / (pi * h)", 1024, 1).unwrap();
+        let saw = Wavetable::from_shape(Shape::Saw, 1024);
+        let samples = wt.frames[0].levels[0].samples();
+        for (v, e) in samples.iter().zip(saw.frames[0].levels[0].samples()) {
+            // The harmonics all start at a phase of 0, so this saw ramps down
+            assert!(approx_eq!(f32, *v, -e, epsilon = 1e-5), "{} != {}", v, -e);
+        }
+
+        assert!(Wavetable::from_expr("sin(", 256).is_err());
+        assert!(Wavetable::from_expr("sin(x)", 1000).is_err());
+        assert!(Wavetable::from_harmonic_expr("1 / h", 0, 1).is_err());
+    }
+
+    #[test]
+    fn test_from_sndfile_clm() {
+        //! serum.wav holds 4 frames of 2048 samples, where frame n is a sine at harmonic n + 1
+        let wt = Wavetable::from_sndfile_clm("test/serum.wav", None).unwrap();
+        assert_eq!(wt.len(), 2048);
+        assert_eq!(wt.frames(), 4);
+        assert!(wt.is_pow2());
+        for (n, frame) in wt.frames.iter().enumerate() {
+            let spec = spectrum(&frame.levels[0].samples());
+            assert!(
+                approx_eq!(f32, spec[n + 1], 512.0, epsilon = 1e-1),
+                "frame {}: {}",
+                n,
+                spec[n + 1]
+            );
+        }
+
+        // The chunk wins over the hint
+        assert_eq!(
+            Wavetable::from_sndfile_clm("test/serum.wav", Some(1024))
+                .unwrap()
+                .len(),
+            2048
+        );
+
+        // Without a chunk, the hint is used, even if it isn't a power of two
+        let wt = Wavetable::from_sndfile_clm("test/saw.wav", Some(600)).unwrap();
+        assert_eq!((wt.len(), wt.frames()), (600, 2));
+        assert!(Wavetable::from_sndfile_clm("test/saw.wav", None).is_err());
+        assert!(Wavetable::from_sndfile_clm("test/saw.wav", Some(0)).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "sndfile")]
+    fn test_write_sndfile() {
+        use super::super::utils::{BitDepth, Container};
+        let path =
+            std::env::temp_dir().join(format!("wavetable-{}-frames.wav", std::process::id()));
+        let path = path.to_str().unwrap();
+        let wt = Wavetable::from_sndfile_clm("test/serum.wav", None).unwrap();
+        wt.write_sndfile(path, 44100, Container::Wav, BitDepth::Float)
+            .unwrap();
+        let read = Wavetable::from_sndfile_clm(path, None).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!((read.len(), read.frames()), (2048, 4));
+        // The samples are recovered from the linear layout, so they can be off by a rounding error
+        for (i, (r, v)) in read.samples().iter().zip(wt.samples()).enumerate() {
+            assert!(
+                approx_eq!(f32, *r, v, epsilon = 1e-6),
+                "samples[{}] = {}, expected: {}",
+                i,
+                r,
+                v
+            );
+        }
+    }
+
+    #[test]
+    fn test_import_options() {
+        // A sine with a DC offset that starts a quarter of the way through its cycle
+        let len = 256;
+        let table = Vec::from_iter(
+            (0..len).map(|i| 0.25 + 0.5 * (2.0 * PI * (i + len / 4) as f32 / len as f32).sin()),
+        );
+        let mut cleaned = table.clone();
+        ImportOptions {
+            remove_dc: true,
+            rotate: true,
+            normalize: Normalize::Peak(0.9),
+            ..Default::default()
+        }
+        .apply(&mut cleaned);
+        for (i, v) in cleaned.iter().enumerate() {
+            let expected = 0.9 * (2.0 * PI * i as f32 / len as f32).sin();
+            assert!(
+                approx_eq!(f32, *v, expected, epsilon = 1e-5),
+                "cleaned[{}] = {}, expected: {}",
+                i,
+                v,
+                expected
+            );
+        }
+
+        let mut rms = table.clone();
+        ImportOptions {
+            normalize: Normalize::Rms(0.5),
+            ..Default::default()
+        }
+        .apply(&mut rms);
+        let power = rms.iter().map(|v| v * v).sum::<f32>() / len as f32;
+        assert!(approx_eq!(f32, power.sqrt(), 0.5, epsilon = 1e-5));
+
+        // The defaults leave the table alone
+        let mut untouched = table.clone();
+        ImportOptions::default().apply(&mut untouched);
+        assert_eq!(untouched, table);
+
+        let wt = Wavetable::from_sndfile_with(
+            "test/LongVoice.wav",
+            true,
+            &ImportOptions {
+                remove_dc: true,
+                rotate: true,
+                normalize: Normalize::Peak(1.0),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let samples = wt.samples();
+        let peak = samples.iter().fold(0.0f32, |max, v| max.max(v.abs()));
+        assert!(
+            approx_eq!(f32, peak, 1.0, epsilon = 1e-5),
+            "peak = {}",
+            peak
+        );
+        assert!(samples[0].abs() < 0.1, "samples[0] = {}", samples[0]);
+        assert!(samples.iter().sum::<f32>().abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_set_table() {
+        //! A table swap crossfades from the old table's output to the new one's
+        let system = Arc::new(System::new(48000.0, 1, 32));
+        let sine = Arc::new(Wavetable::from_shape(Shape::Sine, 256));
+        let saw = Arc::new(Wavetable::from_shape(Shape::Saw, 256));
+        let mut phasor = Phasor::new(&system, &sine);
+        let mut old = Phasor::new(&system, &sine);
+        let mut new = Phasor::new(&system, &saw);
+        let mut outbuf = [0.0; 100];
+        for osc in [&mut phasor, &mut old, &mut new] {
+            osc.perform(&mut outbuf, 440.0, 0.0);
+        }
+
+        phasor.set_table(&saw, 64);
+        assert!(Arc::ptr_eq(phasor.table(), &saw));
+        let mut outbuf = [0.0; 128];
+        let mut oldbuf = [0.0; 128];
+        let mut newbuf = [0.0; 128];
+        phasor.perform(&mut outbuf, 440.0, 0.0);
+        old.perform(&mut oldbuf, 440.0, 0.0);
+        new.perform(&mut newbuf, 440.0, 0.0);
+        for i in 0..128 {
+            let mix = ((i + 1) as f32 / 65.0).min(1.0);
+            let expected = oldbuf[i] + mix * (newbuf[i] - oldbuf[i]);
+            assert!(
+                approx_eq!(f32, outbuf[i], expected, epsilon = 1e-6),
+                "out[{}] = {}, expected: {}",
+                i,
+                outbuf[i],
+                expected
+            );
+        }
+        // The old table is let go of once the fade is over
+        assert_eq!(Arc::strong_count(&sine), 2);
+
+        // The phase carries over to a table of a different length
+        let long = Arc::new(Wavetable::from_shape(Shape::Sine, 4096));
+        let mut phasor = Phasor::new(&system, &sine);
+        let mut reference = Phasor::new(&system, &sine);
+        let mut outbuf = [0.0; 100];
+        let mut expected = [0.0; 200];
+        phasor.perform(&mut outbuf, 440.0, 0.0);
+        phasor.set_table(&long, 0);
+        phasor.perform(&mut outbuf, 440.0, 0.0);
+        reference.perform(&mut expected, 440.0, 0.0);
+        for (i, (out, e)) in outbuf.iter().zip(&expected[100..]).enumerate() {
+            assert!(
+                approx_eq!(f32, *out, *e, epsilon = 1e-3),
+                "out[{}] = {}, expected: {}",
+                i,
+                out,
+                e
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_sndfile_sliced() {
+        let options = ImportOptions {
+            remove_dc: true,
+            rotate: true,
+            normalize: Normalize::Peak(0.9),
+            ..Default::default()
+        };
+        let wt = Wavetable::from_sndfile_sliced("test/LongVoice.wav", 16, 1024, &options).unwrap();
+        assert_eq!(wt.frames(), 16);
+        assert_eq!(wt.len(), 1024);
+
+        let samples = wt.samples();
+        let peak = samples.iter().fold(0.0f32, |max, v| max.max(v.abs()));
+        assert!(approx_eq!(f32, peak, 0.9, epsilon = 1e-3));
+        // Neighboring frames are aligned, so each one looks much like the next
+        for (k, pair) in samples
+            .chunks(1024)
+            .collect::<Vec<_>>()
+            .windows(2)
+            .enumerate()
+        {
+            let dot = pair[0].iter().zip(pair[1]).map(|(a, b)| a * b).sum::<f32>();
+            let norm = (pair[0].iter().map(|a| a * a).sum::<f32>()
+                * pair[1].iter().map(|b| b * b).sum::<f32>())
+            .sqrt();
+            assert!(
+                dot / norm > 0.75,
+                "frames {} and {}: correlation {}",
+                k,
+                k + 1,
+                dot / norm
+            );
+        }
+
+        assert!(Wavetable::from_sndfile_sliced("test/LongVoice.wav", 16, 1000, &options).is_err());
+    }
 }
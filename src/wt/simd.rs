@@ -0,0 +1,154 @@
+/* AVX2 versions of the Phasor's inner loops, which read 8 phases at a time
+ *
+ * These only handle narrow phases on power-of-two tables with linear interpolation. Every value is calculated with the
+ * same operations, in the same order, as the scalar path, so the output is bit-identical to it.
+ */
+use super::{Frame, Level, Wavetable};
+use std::arch::x86_64::*;
+
+/* The number of phases that are read at a time
+ */
+pub(super) const LANES: usize = 8;
+
+/* Returns whether the CPU supports the vectorized loops
+ */
+#[inline]
+pub(super) fn available() -> bool {
+    is_x86_feature_detected!("avx2")
+}
+
+/* Runs Phasor::render's loop over the given buffer, whose length must be a multiple of LANES, and returns the final
+ * phase
+ */
+#[target_feature(enable = "avx2")]
+pub(super) unsafe fn render(
+    table: &Wavetable,
+    phase: i32,
+    inc: i32,
+    offset: i32,
+    outbuf: &mut [f32],
+    (frame, morph): (usize, f32),
+    (level, xfade): (usize, f32),
+) -> i32 {
+    debug_assert!(outbuf.len() % LANES == 0);
+    let lanes = _mm256_setr_epi32(0, 1, 2, 3, 4, 5, 6, 7);
+    let mut phases = _mm256_add_epi32(
+        _mm256_set1_epi32(phase.wrapping_add(offset)),
+        _mm256_mullo_epi32(lanes, _mm256_set1_epi32(inc)),
+    );
+    let blockinc = _mm256_set1_epi32(inc.wrapping_mul(LANES as i32));
+    let morph = if morph > 0.0 {
+        Some(_mm256_set1_ps(morph))
+    } else {
+        None
+    };
+    let xfade = if xfade > 0.0 {
+        Some(_mm256_set1_ps(xfade))
+    } else {
+        None
+    };
+    for block in outbuf.chunks_exact_mut(LANES) {
+        let out = interpolate(table, phases, frame, morph, level, xfade);
+        _mm256_storeu_ps(block.as_mut_ptr(), out);
+        phases = _mm256_add_epi32(phases, blockinc);
+    }
+    phase.wrapping_add(inc.wrapping_mul(outbuf.len() as i32))
+}
+
+/* Reads a block of LANES phases (with the phase offsets already added) that all share a frame and mip level
+ */
+#[target_feature(enable = "avx2")]
+pub(super) unsafe fn interpolate_block(
+    table: &Wavetable,
+    phases: &[i32; LANES],
+    (frame, morphs): (usize, &[f32; LANES]),
+    (level, xfades): (usize, &[f32; LANES]),
+    out: &mut [f32],
+) {
+    let phases = _mm256_loadu_si256(phases.as_ptr() as *const __m256i);
+    let morph = if morphs.iter().any(|m| *m > 0.0) {
+        Some(_mm256_loadu_ps(morphs.as_ptr()))
+    } else {
+        None
+    };
+    let xfade = if xfades.iter().any(|x| *x > 0.0) {
+        Some(_mm256_loadu_ps(xfades.as_ptr()))
+    } else {
+        None
+    };
+    let values = interpolate(table, phases, frame, morph, level, xfade);
+    _mm256_storeu_ps(out[..LANES].as_mut_ptr(), values);
+}
+
+/* The vectorized Wavetable::interpolate, including the phase's index and 1.m fraction
+ */
+#[target_feature(enable = "avx2")]
+#[inline]
+unsafe fn interpolate(
+    table: &Wavetable,
+    phases: __m256i,
+    frame: usize,
+    morph: Option<__m256>,
+    level: usize,
+    xfade: Option<__m256>,
+) -> __m256 {
+    let index = _mm256_and_si256(
+        _mm256_srai_epi32::<16>(phases),
+        _mm256_set1_epi32(table.lomask as i32),
+    );
+    // The same bit trick as phase_frac1()
+    let frac1 = _mm256_castsi256_ps(_mm256_or_si256(
+        _mm256_set1_epi32(0x3F800000),
+        _mm256_and_si256(
+            _mm256_set1_epi32(0x007FFF80),
+            _mm256_slli_epi32::<7>(phases),
+        ),
+    ));
+    let out = read_frame(&table.frames[frame], index, frac1, level, xfade);
+    match morph {
+        Some(morph) => {
+            let next = read_frame(&table.frames[frame + 1], index, frac1, level, xfade);
+            mix(out, next, morph)
+        }
+        None => out,
+    }
+}
+
+/* The vectorized Frame::interpolate
+ */
+#[target_feature(enable = "avx2")]
+#[inline]
+unsafe fn read_frame(
+    frame: &Frame,
+    index: __m256i,
+    frac1: __m256,
+    level: usize,
+    xfade: Option<__m256>,
+) -> __m256 {
+    let out = read(&frame.levels[level], index, frac1);
+    match xfade {
+        Some(xfade) => mix(out, read(&frame.levels[level + 1], index, frac1), xfade),
+        None => out,
+    }
+}
+
+/* The vectorized LinearLayout::read
+ */
+#[target_feature(enable = "avx2")]
+#[inline]
+unsafe fn read(level: &Level, index: __m256i, frac1: __m256) -> __m256 {
+    let val1 = _mm256_i32gather_ps::<4>(level.table1.as_ptr(), index);
+    let val2 = _mm256_i32gather_ps::<4>(level.table2.as_ptr(), index);
+    _mm256_add_ps(val1, _mm256_mul_ps(frac1, val2))
+}
+
+/* Returns out + amount * (next - out) in the lanes where the amount is positive, and out in the rest, just like the
+ * scalar crossfades
+ */
+#[target_feature(enable = "avx2")]
+#[inline]
+unsafe fn mix(out: __m256, next: __m256, amount: __m256) -> __m256 {
+    let mixed = _mm256_add_ps(out, _mm256_mul_ps(amount, _mm256_sub_ps(next, out)));
+    let positive = _mm256_cmp_ps::<_CMP_GT_OQ>(amount, _mm256_setzero_ps());
+    _mm256_blendv_ps(out, mixed, positive)
+}
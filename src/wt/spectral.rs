@@ -0,0 +1,402 @@
+/* Offline operations over the spectrum of each of a Wavetable's frames
+ *
+ * Every operation returns a new Wavetable with the same frame length, interpolation mode and number of frames as the
+ * original. The spectral operations work on the FFT of each frame, where bin k is the kth harmonic.
+ */
+use super::{adjust_level, Normalize, Wavetable};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rustfft::{num_complex::Complex, FftPlanner};
+use std::f32::consts::PI;
+
+impl Wavetable {
+    /** Returns a copy of the table without any DC offset
+     */
+    pub fn remove_dc(&self) -> Self {
+        let mut samples = self.samples();
+        for frame in samples.chunks_mut(self.len()) {
+            adjust_level(frame, true, Normalize::Off);
+        }
+        self.rebuild(&samples)
+    }
+
+    /** Returns a copy of the table that's scaled so that its largest sample has the given magnitude
+
+    All of the frames are scaled by the same amount, so they keep their levels relative to each other. A table of
+    silence is returned as it is.
+
+    # Arguments
+
+    * `peak`: The magnitude of the largest sample
+    */
+    pub fn normalize_peak(&self, peak: f32) -> Self {
+        let mut samples = self.samples();
+        adjust_level(&mut samples, false, Normalize::Peak(peak));
+        self.rebuild(&samples)
+    }
+
+    /** Returns a copy of the table that's scaled so that its RMS level is the given level
+
+    The level is measured across all of the frames, which are all scaled by the same amount. A table of silence is
+    returned as it is.
+
+    # Arguments
+
+    * `rms`: The RMS level
+    */
+    pub fn normalize_rms(&self, rms: f32) -> Self {
+        let mut samples = self.samples();
+        adjust_level(&mut samples, false, Normalize::Rms(rms));
+        self.rebuild(&samples)
+    }
+
+    /** Returns a copy of the table with the harmonics above a cutoff attenuated
+
+    Harmonics at or below the cutoff are left alone, and the ones above it fall off at the given slope, so harmonic `k`
+    is attenuated by `slope * log2(k / cutoff)` decibels. A slope of `f32::INFINITY` removes everything above the
+    cutoff.
+
+    # Arguments
+
+    * `cutoff`: The highest harmonic that's left alone. This may be fractional.
+    * `slope`:  How quickly the harmonics above the cutoff fall off (in dB per octave)
+
+    # Panics
+
+    This function will panic if `cutoff` isn't greater than 0.
+    */
+    pub fn lowpass(&self, cutoff: f32, slope: f32) -> Self {
+        assert!(
+            cutoff > 0.0,
+            "The cutoff must be greater than 0. Got {}",
+            cutoff
+        );
+        self.map_harmonics(|k, coef| {
+            if k as f32 > cutoff {
+                *coef *= rolloff((k as f32 / cutoff).log2(), slope);
+            }
+        })
+    }
+
+    /** Returns a copy of the table with the harmonics below a cutoff attenuated
+
+    Harmonics at or above the cutoff are left alone, and the ones below it fall off at the given slope, so harmonic `k`
+    is attenuated by `slope * log2(cutoff / k)` decibels. A slope of `f32::INFINITY` removes everything below the
+    cutoff. The DC offset is always removed.
+
+    # Arguments
+
+    * `cutoff`: The lowest harmonic that's left alone. This may be fractional.
+    * `slope`:  How quickly the harmonics below the cutoff fall off (in dB per octave)
+
+    # Panics
+
+    This function will panic if `cutoff` isn't greater than 0.
+    */
+    pub fn highpass(&self, cutoff: f32, slope: f32) -> Self {
+        assert!(
+            cutoff > 0.0,
+            "The cutoff must be greater than 0. Got {}",
+            cutoff
+        );
+        self.map_harmonics(|k, coef| {
+            if k == 0 {
+                *coef = Complex::new(0.0, 0.0);
+            } else if (k as f32) < cutoff {
+                *coef *= rolloff((cutoff / k as f32).log2(), slope);
+            }
+        })
+    }
+
+    /** Returns a copy of the table with the balance between its odd and even harmonics changed
+
+    # Arguments
+
+    * `balance`: The balance, from only the odd harmonics (-1.0), through all of them unchanged (0.0), to only the even
+                 harmonics (1.0). The fundamental counts as an odd harmonic.
+    */
+    pub fn odd_even(&self, balance: f32) -> Self {
+        let balance = balance.clamp(-1.0, 1.0);
+        let (odd, even) = ((1.0 - balance).min(1.0), (1.0 + balance).min(1.0));
+        self.map_harmonics(|k, coef| match k {
+            0 => (),
+            k if k % 2 == 1 => *coef *= odd,
+            _ => *coef *= even,
+        })
+    }
+
+    /** Returns a copy of the table with the phase of every harmonic set to zero
+
+    Every harmonic becomes a cosine, so each frame is symmetric around its start, with the harmonics' peaks all lined
+    up there. The magnitude of each harmonic doesn't change.
+    */
+    pub fn zero_phases(&self) -> Self {
+        self.map_harmonics(|_, coef| *coef = Complex::new(coef.norm(), 0.0))
+    }
+
+    /** Returns a copy of the table with the phase of every harmonic shifted by a random amount
+
+    Each harmonic is shifted by the same amount in every frame, so morphing between the frames stays smooth. The shifts
+    come from the seed, so the same seed always gives the same table. The harmonic at the Nyquist frequency (if the
+    table's length is even) can only be a cosine, so it isn't shifted.
+
+    # Arguments
+
+    * `amount`: How far the phases are shifted, from not at all (0.0) to anywhere in the cycle (1.0)
+    * `seed`:   The seed for the random shifts
+    */
+    pub fn randomize_phases(&self, amount: f32, seed: u64) -> Self {
+        let amount = amount.clamp(0.0, 1.0);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let shifts = Vec::from_iter(
+            (0..=self.len() / 2).map(|_| Complex::from_polar(1.0, amount * rng.gen_range(-PI..PI))),
+        );
+        // The Nyquist bin of an even length table has to stay real, so it keeps its phase, just like DC
+        let len = self.len();
+        self.map_harmonics(|k, coef| {
+            if k > 0 && 2 * k != len {
+                *coef *= shifts[k];
+            }
+        })
+    }
+
+    /** Returns a copy of the table with a spectral tilt
+
+    Harmonic `k` is boosted by `slope * log2(k)` decibels, so the fundamental stays where it is and a negative slope
+    darkens the table.
+
+    # Arguments
+
+    * `slope`: The tilt (in dB per octave)
+    */
+    pub fn tilt(&self, slope: f32) -> Self {
+        self.map_harmonics(|k, coef| {
+            if k > 1 {
+                *coef *= rolloff((k as f32).log2(), -slope);
+            }
+        })
+    }
+
+    /** Returns a copy of the table with its harmonics moved up or down the spectrum
+
+    Harmonic `k` is moved to harmonic `round(k * factor)`, keeping its magnitude and phase. Harmonics that land in the
+    same place are added together, and the ones that land above the table's Nyquist frequency are dropped. The DC offset
+    stays where it is.
+
+    # Arguments
+
+    * `factor`: How far the harmonics are moved. This must be positive.
+    */
+    pub fn stretch(&self, factor: f32) -> Self {
+        self.map_spectrum(|spectrum| {
+            let mut stretched = vec![Complex::new(0.0, 0.0); spectrum.len()];
+            stretched[0] = spectrum[0];
+            for (k, coef) in spectrum.iter().enumerate().skip(1) {
+                let to = ((k as f32 * factor).round() as usize).max(1);
+                if to < stretched.len() {
+                    stretched[to] += coef;
+                }
+            }
+            spectrum.copy_from_slice(&stretched);
+        })
+    }
+
+    /* Returns a table like this one, built from the given samples
+     */
+    fn rebuild(&self, samples: &[f32]) -> Self {
+        Wavetable::build(samples, self.len(), self.interpolation, true)
+    }
+
+    /* Calls f with each harmonic number and its coefficient, for every frame, and rebuilds the table from the result
+     */
+    fn map_harmonics(&self, mut f: impl FnMut(usize, &mut Complex<f32>)) -> Self {
+        self.map_spectrum(|spectrum| {
+            for (k, coef) in spectrum.iter_mut().enumerate() {
+                f(k, coef);
+            }
+        })
+    }
+
+    /* Calls f with the first half of the spectrum of every frame (from DC up to the Nyquist frequency), and rebuilds
+     * the table from the result
+     */
+    fn map_spectrum(&self, mut f: impl FnMut(&mut [Complex<f32>])) -> Self {
+        let len = self.len();
+        let mut planner = FftPlanner::new();
+        let fft = planner.plan_fft_forward(len);
+        let ifft = planner.plan_fft_inverse(len);
+
+        let samples = self.samples();
+        let mut table = Vec::with_capacity(samples.len());
+        for frame in samples.chunks(len) {
+            let mut fftbuf = Vec::from_iter(frame.iter().map(|re| Complex { re: *re, im: 0.0 }));
+            fft.process(&mut fftbuf);
+            f(&mut fftbuf[..=len / 2]);
+            // Mirror the result into the negative frequencies, so the frame stays real
+            for k in (len / 2 + 1)..len {
+                fftbuf[k] = fftbuf[len - k].conj();
+            }
+            ifft.process(&mut fftbuf);
+            let norm = len as f32;
+            table.extend(fftbuf.iter().map(|coef| coef.re / norm));
+        }
+        self.rebuild(&table)
+    }
+}
+
+/* Returns the gain for an attenuation of `slope` dB per octave over the given number of octaves
+ */
+fn rolloff(octaves: f32, slope: f32) -> f32 {
+    if slope.is_infinite() && slope > 0.0 {
+        0.0
+    } else {
+        10f32.powf(-slope * octaves / 20.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Shape, Wavetable};
+    use float_cmp::approx_eq;
+    use rustfft::{num_complex::Complex, FftPlanner};
+
+    // The slope that doubles or halves the level every octave
+    const DB_PER_DOUBLING: f32 = 6.0206;
+
+    fn magnitudes(table: &[f32]) -> Vec<f32> {
+        let mut fftbuf = Vec::from_iter(table.iter().map(|re| Complex { re: *re, im: 0.0 }));
+        FftPlanner::new()
+            .plan_fft_forward(table.len())
+            .process(&mut fftbuf);
+        Vec::from_iter(
+            fftbuf[..=table.len() / 2]
+                .iter()
+                .map(|c| c.norm() * 2.0 / table.len() as f32),
+        )
+    }
+
+    fn assert_close(actual: f32, expected: f32, what: &str) {
+        assert!(
+            approx_eq!(f32, actual, expected, epsilon = 1e-4),
+            "{} = {}, expected: {}",
+            what,
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_remove_dc_and_normalize() {
+        let offset = Vec::from_iter(
+            Wavetable::from_shape(Shape::Sine, 256)
+                .samples()
+                .iter()
+                .map(|v| 0.5 * v + 0.25),
+        );
+        let wt = Wavetable::new(&offset);
+        let samples = wt.remove_dc().samples();
+        assert_close(samples.iter().sum::<f32>(), 0.0, "sum");
+
+        let peak = wt.remove_dc().normalize_peak(0.8).samples();
+        assert_close(peak.iter().fold(0.0f32, |m, v| m.max(v.abs())), 0.8, "peak");
+
+        let rms = wt.remove_dc().normalize_rms(0.5).samples();
+        let power = rms.iter().map(|v| v * v).sum::<f32>() / rms.len() as f32;
+        assert_close(power.sqrt(), 0.5, "rms");
+
+        // Silence stays silent
+        let silence = Wavetable::new(&[0.0; 64]).normalize_peak(1.0).samples();
+        assert!(silence.iter().all(|v| *v == 0.0));
+    }
+
+    #[test]
+    fn test_filters() {
+        let saw = Wavetable::from_shape(Shape::Saw, 256);
+        let original = magnitudes(&saw.samples());
+
+        let lp = magnitudes(&saw.lowpass(4.0, 2.0 * DB_PER_DOUBLING).samples());
+        assert_close(lp[4], original[4], "lp[4]");
+        assert_close(lp[8], original[8] / 4.0, "lp[8]");
+        let brick = magnitudes(&saw.lowpass(4.0, f32::INFINITY).samples());
+        assert_close(brick[5], 0.0, "brick[5]");
+
+        let hp = magnitudes(&saw.highpass(4.0, DB_PER_DOUBLING).samples());
+        assert_close(hp[4], original[4], "hp[4]");
+        assert_close(hp[2], original[2] / 2.0, "hp[2]");
+
+        let tilted = magnitudes(&saw.tilt(DB_PER_DOUBLING).samples());
+        assert_close(tilted[1], original[1], "tilted[1]");
+        assert_close(tilted[4], original[4] * 4.0, "tilted[4]");
+    }
+
+    #[test]
+    #[should_panic(expected = "The cutoff must be greater than 0. Got 0")]
+    fn test_lowpass_zero_cutoff() {
+        Wavetable::from_shape(Shape::Saw, 256).lowpass(0.0, 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "The cutoff must be greater than 0. Got -1")]
+    fn test_highpass_negative_cutoff() {
+        Wavetable::from_shape(Shape::Saw, 256).highpass(-1.0, 0.0);
+    }
+
+    #[test]
+    fn test_odd_even_and_stretch() {
+        let saw = Wavetable::from_shape(Shape::Saw, 256);
+        let original = magnitudes(&saw.samples());
+
+        let odd = magnitudes(&saw.odd_even(-1.0).samples());
+        assert_close(odd[3], original[3], "odd[3]");
+        assert_close(odd[2], 0.0, "odd[2]");
+        let half = magnitudes(&saw.odd_even(0.5).samples());
+        assert_close(half[3], original[3] / 2.0, "half[3]");
+        assert_close(half[2], original[2], "half[2]");
+
+        let stretched = magnitudes(&saw.stretch(2.0).samples());
+        assert_close(stretched[2], original[1], "stretched[2]");
+        assert_close(stretched[3], 0.0, "stretched[3]");
+        assert_close(stretched[6], original[3], "stretched[6]");
+    }
+
+    #[test]
+    fn test_phases() {
+        let saw = Wavetable::from_shape(Shape::Saw, 256);
+        let original = magnitudes(&saw.samples());
+        for table in [saw.zero_phases(), saw.randomize_phases(1.0, 7)] {
+            for (k, (m, o)) in magnitudes(&table.samples())
+                .iter()
+                .zip(&original)
+                .enumerate()
+            {
+                assert_close(*m, *o, &format!("magnitude[{}]", k));
+            }
+        }
+
+        // A harmonic at the Nyquist frequency keeps its magnitude too
+        let nyquist = Wavetable::new(&Vec::from_iter((0..256).map(|n| {
+            (std::f32::consts::TAU * n as f32 / 256.0).sin() + if n % 2 == 0 { 0.5 } else { -0.5 }
+        })));
+        let original = magnitudes(&nyquist.samples());
+        for seed in 0..8 {
+            let randomized = magnitudes(&nyquist.randomize_phases(1.0, seed).samples());
+            assert_close(randomized[128], original[128], "magnitude[128]");
+            assert_close(randomized[1], original[1], "magnitude[1]");
+        }
+
+        // Zero phases line every harmonic up at the start of the cycle
+        let zeroed = saw.zero_phases().samples();
+        let max = zeroed.iter().fold(f32::MIN, |m, v| m.max(*v));
+        assert_eq!(zeroed[0], max);
+
+        // The same seed gives the same table
+        assert_eq!(
+            saw.randomize_phases(0.5, 1).samples(),
+            saw.randomize_phases(0.5, 1).samples()
+        );
+        assert_ne!(
+            saw.randomize_phases(0.5, 1).samples(),
+            saw.randomize_phases(0.5, 2).samples()
+        );
+    }
+}